@@ -78,4 +78,25 @@ pub mod utils {
         (get_file(pos), get_rank(pos))
     }
 
+    // Algebraic name of a square, e.g. 52 -> "e2"
+    pub fn square_name(pos: usize) -> String {
+        let (file, rank) = get_file_and_rank(pos);
+        format!("{}{}", (b'a' + file as u8) as char, rank + 1)
+    }
+
+    pub fn parse_square(name: &str) -> Option<u8> {
+        let bytes = name.as_bytes();
+        if bytes.len() != 2 {
+            return None
+        }
+
+        let file = bytes[0].wrapping_sub(b'a');
+        let rank = bytes[1].wrapping_sub(b'1');
+        if file > 7 || rank > 7 {
+            return None
+        }
+
+        Some((7 - rank) * 8 + file)
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+// Builds a Polyglot book from PGN files.
+//
+// cargo run --bin build_book -- book.bin games.pgn [more.pgn ...] [--max-ply N] [--min-games N]
+//     [--white-factor F] [--black-factor F] [--win N] [--draw N] [--loss N] [--summary summary.txt]
+
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+use std::str::FromStr;
+use engine::book_builder::book_builder::{BookBuilder, BookBuilderOptions};
+use engine::pgn::pgn::PgnReader;
+
+fn usage() -> ! {
+    eprintln!("usage: build_book <book.bin> <games.pgn>... [--max-ply N] [--min-games N] [--white-factor F] [--black-factor F] [--win N] [--draw N] [--loss N] [--summary FILE]");
+    process::exit(2)
+}
+
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    match args.next().map(|v| v.parse::<T>()) {
+        Some(Ok(v)) => v,
+        _ => {
+            eprintln!("{} needs a valid value", flag);
+            usage()
+        }
+    }
+}
+
+fn main() {
+    let mut options = BookBuilderOptions::default();
+    let mut summary = None;
+    let mut files = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-ply" => options.max_ply = value(&mut args, &arg),
+            "--min-games" => options.min_games = value(&mut args, &arg),
+            "--white-factor" => options.white_factor = value(&mut args, &arg),
+            "--black-factor" => options.black_factor = value(&mut args, &arg),
+            "--win" => options.win_points = value(&mut args, &arg),
+            "--draw" => options.draw_points = value(&mut args, &arg),
+            "--loss" => options.loss_points = value(&mut args, &arg),
+            "--summary" => summary = Some(value::<String>(&mut args, &arg)),
+            flag if flag.starts_with("--") => usage(),
+            _ => files.push(arg)
+        }
+    }
+
+    if files.len() < 2 {
+        usage()
+    }
+    let book_path = files.remove(0);
+
+    let mut builder = BookBuilder::new(options);
+    for path in files.iter() {
+        let reader = PgnReader::open(path).unwrap_or_else(|e| {
+            eprintln!("Could not open {}: {}", path, e);
+            process::exit(1)
+        });
+
+        if let Err(e) = builder.add_pgn(reader) {
+            eprintln!("Failed reading {}: {}", path, e);
+            process::exit(1)
+        }
+    }
+
+    let entries = builder.save_book(&book_path).unwrap_or_else(|e| {
+        eprintln!("Could not write {}: {}", book_path, e);
+        process::exit(1)
+    });
+
+    if let Some(path) = summary {
+        let written = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            builder.write_summary(&mut writer)?;
+            writer.flush()
+        });
+        if let Err(e) = written {
+            eprintln!("Could not write {}: {}", path, e);
+            process::exit(1)
+        }
+    }
+
+    let stats = builder.stats();
+    println!(
+        "{} games ({} skipped, {} truncated), {} plies, {} positions, {} book entries written to {}",
+        stats.games, stats.skipped_games, stats.truncated_games, stats.plies, builder.position_count(), entries, book_path
+    );
+}
@@ -0,0 +1,57 @@
+// Solves endgame tables by retrograde analysis and writes them to a directory.
+//
+// cargo run --release --bin solve_endgame -- tables/ KQK KRK KPK KBNK
+//
+// Tables needed for captures and promotions are solved and written as well.
+
+use std::env;
+use std::process;
+use std::time::Instant;
+use engine::endgame::endgame::{EndgameTables, Signature};
+
+fn usage() -> ! {
+    eprintln!("usage: solve_endgame <dir> <signature>...");
+    process::exit(2)
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let dir = args.next().unwrap_or_else(|| usage());
+    let signatures: Vec<String> = args.collect();
+    if signatures.is_empty() {
+        usage()
+    }
+
+    let mut tables = EndgameTables::open_dir(&dir).unwrap_or_else(|_| EndgameTables::new());
+    for name in signatures.iter() {
+        let signature = name.parse::<Signature>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2)
+        });
+
+        let start = Instant::now();
+        match tables.generate(&signature) {
+            Ok(table) => {
+                println!(
+                    "{}: longest win {:?} plies with white to move, {:?} with black to move ({:.1?})",
+                    table.signature(),
+                    table.longest_win(true),
+                    table.longest_win(false),
+                    start.elapsed()
+                );
+            }
+            Err(e) => {
+                eprintln!("Could not solve {}: {}", name, e);
+                process::exit(1)
+            }
+        }
+    }
+
+    match tables.save_dir(&dir) {
+        Ok(count) => println!("Wrote {} tables to {}", count, dir),
+        Err(e) => {
+            eprintln!("Could not write tables to {}: {}", dir, e);
+            process::exit(1)
+        }
+    }
+}
@@ -6,6 +6,7 @@ pub mod board {
     use crate::board::board::Move::{Capture, Castle, Promotion, Standard};
     use crate::board::board::Side::{Left, Right};
     use crate::debug::debug::{print_bitboard_board, print_board_from_board};
+    use crate::game::game::Game;
     use crate::move_gen::move_gen::{MoveGen, PieceType};
     use crate::move_gen::move_gen::PieceType::{BISHOP, KNIGHT, PAWN, QUEEN, ROOK};
     use crate::move_list::move_list::{AttackMoveList, MoveList};
@@ -139,6 +140,28 @@ pub mod board {
                 }
             }
         }
+
+        // Long algebraic notation as used by UCI, e.g. "e2e4", "e7e8q" or "e1g1"
+        pub fn to_uci(&self) -> String {
+            match self {
+                Move::None => "0000".to_string(),
+                Standard(from, to, _, _) | Capture(from, to, _, _, _) => {
+                    format!("{}{}", utils::square_name(*from as usize), utils::square_name(*to as usize))
+                }
+                Promotion(from, to, piece, _, _) => {
+                    format!(
+                        "{}{}{}",
+                        utils::square_name(*from as usize),
+                        utils::square_name(*to as usize),
+                        piece.to_string().to_lowercase()
+                    )
+                }
+                Castle(king_position, side, _) => {
+                    let to = if *side == Left { king_position - 2 } else { king_position + 2 };
+                    format!("{}{}", utils::square_name(*king_position as usize), utils::square_name(to as usize))
+                }
+            }
+        }
     }
     impl Eq for Move {}
 
@@ -334,8 +357,8 @@ pub mod board {
             };
         }
 
-        pub fn from_string(notation: String) -> Board {
-            let mut board =  Board {
+        pub fn empty() -> Board {
+            return Board {
                 white_pawn_board: 0,
                 black_pawn_board: 0,
                 white_queen_board: 0,
@@ -351,12 +374,38 @@ pub mod board {
                 black_king_position: 0,
                 white_king_position: 0
             };
+        }
+
+        pub fn from_string(notation: String) -> Board {
+            let mut board = Board::empty();
 
             Board::transform_string_to_state(&mut board, notation);
 
             return board
         }
 
+        // Piece and colour (true for white) standing on pos, if any
+        pub fn piece_at(&self, pos: u8) -> Option<(PieceType, bool)> {
+            if let Some(p) = self.get_captured_board(&pos, false) {
+                return Some((p, true))
+            }
+
+            if let Some(p) = self.get_captured_board(&pos, true) {
+                return Some((p, false))
+            }
+
+            return None
+        }
+
+        pub fn diagram(&self, options: DiagramOptions) -> BoardDiagram<'_> {
+            BoardDiagram {
+                board: self,
+                options,
+                white_to_move: None,
+                fen: self.to_fen_placement(),
+            }
+        }
+
         pub fn get_board_value(&self) -> u64 {
             return self.white_king_board |
                 self.white_queen_board |
@@ -715,13 +764,25 @@ pub mod board {
 
                     let promoted_piece_board = self.get_board(p, color);
                     *promoted_piece_board &= !to_position;
-                    
+
                     if cp != PieceType::None {
-                        let captured_piece_board = self.get_board(cp, color);
-                        *captured_piece_board &= !to_position;
+                        let captured_piece_board = self.get_board(cp, !color);
+                        *captured_piece_board |= to_position;
                     }
 
 
+                }
+                Castle(king_position, side, color) => {
+                    match side {
+                        Left => {
+                            self.move_piece_on_correct_board(&KING, &(king_position - 2), &king_position, color);
+                            self.move_piece_on_correct_board(&ROOK, &(king_position - 1), if color {&56_u8} else {&0_u8}, color)
+                        },
+                        Right => {
+                            self.move_piece_on_correct_board(&KING, &(king_position + 2), &king_position, color);
+                            self.move_piece_on_correct_board(&ROOK, &(king_position + 1), if color {&63_u8} else {&7_u8}, color)
+                        }
+                    }
                 }
                 _ => panic!("Undo move does not support {:?}", m)
             }
@@ -809,6 +870,86 @@ pub mod board {
             }
         }
 
+        // Static exchange evaluation: the material m wins once both sides have made every capture on its destination
+        // square that pays off, each time with their least valuable piece. Sliders behind a capturing piece join in
+        // when it leaves (x-rays). Castling and Move::None exchange nothing.
+        pub fn see(&self, m: &Move, move_gen: &MoveGen) -> i32 {
+            let (from, to, piece, captured, is_white) = match *m {
+                Standard(from, to, piece, is_white) => (from, to, piece, PieceType::None, is_white),
+                Capture(from, to, piece, captured, is_white) => (from, to, piece, captured, is_white),
+                Promotion(from, to, promotion, captured, is_white) => (from, to, promotion, captured, is_white),
+                Castle(..) | Move::None => return 0
+            };
+            let to = to as usize;
+
+            let mut gain = [0; 32];
+            gain[0] = Game::piece_value(captured);
+            if let Promotion(..) = m {
+                gain[0] += Game::piece_value(piece) - Game::piece_value(PAWN);
+            }
+
+            let white = self.get_white_occupancy();
+            let mut occupancy = self.get_board_value();
+            // The piece on the square and where the next capture comes from
+            let mut victim = piece;
+            let mut square = utils::POSITIONS[from as usize];
+            let mut side_white = is_white;
+            let mut depth = 0;
+
+            loop {
+                // What the side that just took has if its piece is taken back
+                depth += 1;
+                gain[depth] = Game::piece_value(victim) - gain[depth - 1];
+                // Neither side can gain by going on
+                if gain[depth].max(-gain[depth - 1]) < 0 || depth == gain.len() - 1 {
+                    break
+                }
+
+                occupancy &= !square;
+                side_white = !side_white;
+                let attackers = move_gen.attackers_to(self, to, occupancy) & occupancy;
+                let side_attackers = attackers & if side_white { white } else { !white };
+                let Some((attacker, attacker_square)) = self.least_valuable(side_attackers, side_white) else {
+                    break
+                };
+                // The king only takes when nothing defends the square
+                if attacker == KING && attackers & !side_attackers > 0 {
+                    break
+                }
+                victim = attacker;
+                square = attacker_square;
+            }
+
+            // Each side stops capturing where going on would lose
+            while depth > 1 {
+                depth -= 1;
+                gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            }
+            gain[0]
+        }
+
+        // Whether m wins at least threshold in the exchange
+        pub fn see_ge(&self, m: &Move, threshold: i32, move_gen: &MoveGen) -> bool {
+            self.see(m, move_gen) >= threshold
+        }
+
+        // The cheapest of the given pieces of one side with its square
+        fn least_valuable(&self, pieces: u64, is_white: bool) -> Option<(PieceType, u64)> {
+            let boards = if is_white {
+                [(PAWN, self.white_pawn_board), (KNIGHT, self.white_knight_board), (BISHOP, self.white_bishop_board),
+                    (ROOK, self.white_rook_board), (QUEEN, self.white_queen_board), (KING, self.white_king_board)]
+            } else {
+                [(PAWN, self.black_pawn_board), (KNIGHT, self.black_knight_board), (BISHOP, self.black_bishop_board),
+                    (ROOK, self.black_rook_board), (QUEEN, self.black_queen_board), (KING, self.black_king_board)]
+            };
+            boards.into_iter()
+                .find(|(_, board)| board & pieces > 0)
+                .map(|(piece, board)| {
+                    let candidates = board & pieces;
+                    (piece, candidates & candidates.wrapping_neg())
+                })
+        }
+
         pub fn check_for_multiple_pieces(&self, pos: usize) -> usize {
             let mut count = 0;
             let position = 1_u64 << pos;
@@ -884,4 +1025,110 @@ pub mod board {
         }*/
 
     }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct DiagramOptions {
+        pub unicode: bool, // chess glyphs instead of FEN letters
+        pub coordinates: bool, // rank and file labels
+        pub flipped: bool, // black at the bottom
+        pub footer: bool, // side to move and FEN below the board
+    }
+
+    impl Default for DiagramOptions {
+        fn default() -> Self {
+            DiagramOptions {
+                unicode: false,
+                coordinates: true,
+                flipped: false,
+                footer: true,
+            }
+        }
+    }
+
+    pub struct BoardDiagram<'a> {
+        pub(crate) board: &'a Board,
+        pub(crate) options: DiagramOptions,
+        pub(crate) white_to_move: Option<bool>,
+        pub(crate) fen: String,
+    }
+
+    fn piece_symbol(piece: PieceType, is_white: bool, unicode: bool) -> char {
+        if unicode {
+            return match (piece, is_white) {
+                (KING, true) => '♔',
+                (QUEEN, true) => '♕',
+                (ROOK, true) => '♖',
+                (BISHOP, true) => '♗',
+                (KNIGHT, true) => '♘',
+                (PAWN, true) => '♙',
+                (KING, false) => '♚',
+                (QUEEN, false) => '♛',
+                (ROOK, false) => '♜',
+                (BISHOP, false) => '♝',
+                (KNIGHT, false) => '♞',
+                (PAWN, false) => '♟',
+                (PieceType::None, _) => '.'
+            }
+        }
+
+        let letter = piece.to_string().chars().next().unwrap();
+        if is_white { letter } else { letter.to_ascii_lowercase() }
+    }
+
+    impl fmt::Display for BoardDiagram<'_> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            let order: [usize; 8] = if self.options.flipped { [7, 6, 5, 4, 3, 2, 1, 0] } else { [0, 1, 2, 3, 4, 5, 6, 7] };
+
+            for row in order {
+                if self.options.coordinates {
+                    write!(f, "{} ", 8 - row)?;
+                }
+
+                for (i, file) in order.iter().enumerate() {
+                    let symbol = match self.board.piece_at((row * 8 + file) as u8) {
+                        Some((p, is_white)) => piece_symbol(p, is_white, self.options.unicode),
+                        None => '.'
+                    };
+
+                    if i < 7 {
+                        write!(f, "{} ", symbol)?;
+                    } else {
+                        writeln!(f, "{}", symbol)?;
+                    }
+                }
+            }
+
+            if self.options.coordinates {
+                write!(f, "  ")?;
+                for (i, file) in order.iter().enumerate() {
+                    let letter = (b'a' + *file as u8) as char;
+                    if i < 7 {
+                        write!(f, "{} ", letter)?;
+                    } else {
+                        writeln!(f, "{}", letter)?;
+                    }
+                }
+            }
+
+            if self.options.footer {
+                writeln!(f)?;
+                match self.white_to_move {
+                    Some(true) => writeln!(f, "White to move")?,
+                    Some(false) => writeln!(f, "Black to move")?,
+                    None => ()
+                }
+                write!(f, "FEN: {}", self.fen)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    // `{}` renders an ASCII diagram, `{:#}` the same diagram with Unicode glyphs
+    impl fmt::Display for Board {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            let options = DiagramOptions { unicode: f.alternate(), ..DiagramOptions::default() };
+            write!(f, "{}", self.diagram(options))
+        }
+    }
 }
@@ -0,0 +1,264 @@
+// Building Polyglot opening books out of PGN game collections.
+//
+// Every game is replayed through Game up to a ply limit, so only moves the engine itself accepts end up in the book.
+// A move earns points for every game it was played in, counted from the side that played it,
+// and its book weight is the sum of those points times the factor for that colour.
+pub mod book_builder {
+    use std::collections::{HashMap, HashSet};
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufRead, BufWriter, Write};
+    use std::path::Path;
+    use crate::board::board::Move;
+    use crate::game::game::Game;
+    use crate::packed::packed::GameResult;
+    use crate::pgn::pgn::{PgnError, PgnGame, PgnReader};
+    use crate::polyglot::polyglot::{encode_move, polyglot_key, BookEntry};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct BookBuilderOptions {
+        // Only the first max_ply half moves of a game go into the book
+        pub max_ply: usize,
+        // Moves played in fewer games than this are left out
+        pub min_games: u32,
+        pub win_points: u32,
+        pub draw_points: u32,
+        pub loss_points: u32,
+        // Scales the weights of the moves each colour plays, 0 leaves that colour out of the book
+        pub white_factor: f64,
+        pub black_factor: f64,
+    }
+
+    impl Default for BookBuilderOptions {
+        fn default() -> Self {
+            BookBuilderOptions {
+                max_ply: 20,
+                min_games: 3,
+                win_points: 2,
+                draw_points: 1,
+                loss_points: 0,
+                white_factor: 1.0,
+                black_factor: 1.0,
+            }
+        }
+    }
+
+    // Results of the games a move was played in, from the side that played it
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct MoveStats {
+        pub games: u32,
+        pub wins: u32,
+        pub draws: u32,
+        pub losses: u32,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct BuildStats {
+        pub games: u64,
+        // Games without a result or that could not be parsed
+        pub skipped_games: u64,
+        // Games that hit a move the engine rejected, the plies before it are still counted
+        pub truncated_games: u64,
+        pub plies: u64,
+    }
+
+    struct PositionMoves {
+        fen: String,
+        is_white_turn: bool,
+        moves: Vec<(u16, Move, MoveStats)>,
+    }
+
+    pub struct BookBuilder {
+        options: BookBuilderOptions,
+        game: Game,
+        positions: HashMap<u64, PositionMoves>,
+        stats: BuildStats,
+    }
+
+    impl BookBuilder {
+        pub fn new(options: BookBuilderOptions) -> BookBuilder {
+            BookBuilder {
+                options,
+                game: Game::new(true),
+                positions: HashMap::new(),
+                stats: BuildStats::default(),
+            }
+        }
+
+        pub fn stats(&self) -> BuildStats {
+            self.stats
+        }
+
+        pub fn position_count(&self) -> usize {
+            self.positions.len()
+        }
+
+        // Adds the opening of one game and returns how many plies went into the book.
+        // On an illegal move the plies before it are kept and the error is returned.
+        pub fn add_game(&mut self, pgn: &PgnGame) -> Result<usize, PgnError> {
+            let result = match pgn.result {
+                Some(result) => result,
+                None => {
+                    self.stats.skipped_games += 1;
+                    return Ok(0)
+                }
+            };
+
+            if let Err(e) = pgn.setup(&mut self.game) {
+                self.stats.skipped_games += 1;
+                return Err(e)
+            }
+            self.stats.games += 1;
+
+            // A position and move that repeat within one game only count once for it
+            let mut seen = HashSet::new();
+            for (ply, san) in pgn.moves.iter().take(self.options.max_ply).enumerate() {
+                let m = match self.game.move_from_san(san) {
+                    Some(m) => m,
+                    None => {
+                        self.stats.truncated_games += 1;
+                        return Err(PgnError::IllegalMove { ply, san: san.clone() })
+                    }
+                };
+
+                if seen.insert((polyglot_key(&self.game), encode_move(&m))) {
+                    self.record(&m, result);
+                }
+                self.game.make_move(&m);
+                self.stats.plies += 1;
+            }
+
+            Ok(pgn.moves.len().min(self.options.max_ply))
+        }
+
+        fn record(&mut self, m: &Move, result: GameResult) {
+            let game = &self.game;
+            let position = self.positions.entry(polyglot_key(game)).or_insert_with(|| PositionMoves {
+                fen: game.to_fen(),
+                is_white_turn: game.is_white_turn,
+                moves: vec![],
+            });
+
+            let raw_move = encode_move(m);
+            let index = match position.moves.iter().position(|(raw, _, _)| *raw == raw_move) {
+                Some(index) => index,
+                None => {
+                    position.moves.push((raw_move, *m, MoveStats::default()));
+                    position.moves.len() - 1
+                }
+            };
+
+            let stats = &mut position.moves[index].2;
+            stats.games += 1;
+            match (result, position.is_white_turn) {
+                (GameResult::Draw, _) => stats.draws += 1,
+                (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => stats.wins += 1,
+                _ => stats.losses += 1
+            }
+        }
+
+        // Adds every game of a PGN stream, games that do not parse or replay are counted and skipped
+        pub fn add_pgn<R: BufRead>(&mut self, reader: PgnReader<R>) -> io::Result<()> {
+            for game in reader {
+                match game {
+                    Ok(game) => { let _ = self.add_game(&game); }
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => self.stats.skipped_games += 1,
+                    Err(e) => return Err(e)
+                }
+            }
+
+            Ok(())
+        }
+
+        fn raw_weight(&self, is_white: bool, stats: &MoveStats) -> f64 {
+            if stats.games < self.options.min_games {
+                return 0.0
+            }
+
+            let points = stats.wins * self.options.win_points
+                + stats.draws * self.options.draw_points
+                + stats.losses * self.options.loss_points;
+            let factor = if is_white { self.options.white_factor } else { self.options.black_factor };
+
+            points as f64 * factor.max(0.0)
+        }
+
+        // Weights are scaled down together when the heaviest move does not fit in 16 bits
+        fn weight_scale(&self) -> f64 {
+            let max = self.positions.values()
+                .flat_map(|position| position.moves.iter().map(|(_, _, stats)| self.raw_weight(position.is_white_turn, stats)))
+                .fold(0.0, f64::max);
+
+            if max > u16::MAX as f64 { u16::MAX as f64 / max } else { 1.0 }
+        }
+
+        fn book_weight(&self, is_white: bool, stats: &MoveStats, scale: f64) -> u16 {
+            (self.raw_weight(is_white, stats) * scale).round() as u16
+        }
+
+        // Book entries sorted by key, heaviest move first within a position. Moves with no weight are left out.
+        pub fn entries(&self) -> Vec<BookEntry> {
+            let scale = self.weight_scale();
+            let mut entries = vec![];
+
+            for (key, position) in self.positions.iter() {
+                for (raw_move, _, stats) in position.moves.iter() {
+                    let weight = self.book_weight(position.is_white_turn, stats, scale);
+                    if weight > 0 {
+                        entries.push(BookEntry { key: *key, raw_move: *raw_move, weight, learn: 0 });
+                    }
+                }
+            }
+
+            entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.raw_move.cmp(&b.raw_move)));
+            entries
+        }
+
+        pub fn write_book<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+            let entries = self.entries();
+            for entry in entries.iter() {
+                writer.write_all(&entry.to_bytes())?;
+            }
+            Ok(entries.len())
+        }
+
+        pub fn save_book<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+            let mut writer = BufWriter::new(File::create(path)?);
+            let written = self.write_book(&mut writer)?;
+            writer.flush()?;
+            Ok(written)
+        }
+
+        // Human readable listing of the book, most played positions first
+        pub fn write_summary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            let scale = self.weight_scale();
+
+            let mut positions: Vec<(&u64, &PositionMoves)> = self.positions.iter().collect();
+            let games = |position: &PositionMoves| position.moves.iter().map(|(_, _, stats)| stats.games).sum::<u32>();
+            positions.sort_by(|(key_a, a), (key_b, b)| games(b).cmp(&games(a)).then(key_a.cmp(key_b)));
+
+            for (key, position) in positions {
+                let mut moves: Vec<(&Move, &MoveStats, u16)> = position.moves.iter()
+                    .map(|(_, m, stats)| (m, stats, self.book_weight(position.is_white_turn, stats, scale)))
+                    .filter(|(_, _, weight)| *weight > 0)
+                    .collect();
+                if moves.is_empty() {
+                    continue
+                }
+                moves.sort_by_key(|(_, _, weight)| std::cmp::Reverse(*weight));
+
+                writeln!(writer, "{}  key {:016x}  games {}", position.fen, key, games(position))?;
+                for (m, stats, weight) in moves {
+                    writeln!(
+                        writer,
+                        "    {:<6} weight {:>5}  games {:>6}  +{} ={} -{}",
+                        m.to_uci(), weight, stats.games, stats.wins, stats.draws, stats.losses
+                    )?;
+                }
+                writeln!(writer)?;
+            }
+
+            Ok(())
+        }
+    }
+}
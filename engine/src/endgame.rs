@@ -0,0 +1,846 @@
+// Built-in endgame tables for positions with up to four pieces, solved by retrograde analysis.
+//
+// A table covers one material signature like "KQK" or "KRKP", white's pieces first. Every placement of the pieces
+// with either side to move gets one byte: 0 for a draw, 255 for an illegal position, otherwise the distance to mate
+// in plies plus one. A win for the side to move always has an odd distance and a loss an even one, so the byte
+// alone says which it is.
+//
+// Only one colour orientation is stored, "KKQ" is answered from the "KQK" table with the board mirrored.
+// Captures and promotions leave the table, so the tables they lead to are solved first.
+// Castling and en passant are not part of the tables, positions where they are possible are not probed.
+pub mod endgame {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::Formatter;
+    use std::fs;
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufReader, BufWriter, Read, Write};
+    use std::path::Path;
+    use std::str::FromStr;
+    use crate::board::board::Move;
+    use crate::game::game::Game;
+    use crate::move_gen::move_gen::{MoveGen, PieceType};
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::utils::utils;
+
+    pub const MAX_PIECES: usize = 4;
+
+    // Score of a solved win for the side to move, shortened by the distance to mate
+    pub const SOLVED_WIN_SCORE: i32 = 25_000;
+
+    const MAGIC: [u8; 4] = *b"EGTB";
+    const VERSION: u8 = 1;
+    const EXTENSION: &str = "egt";
+
+    const DRAW: u8 = 0;
+    const ILLEGAL: u8 = 255;
+    // While solving, the best result of the moves that leave the table when one of them draws
+    const EXIT_DRAW: u8 = 255;
+    const MAX_DISTANCE: u32 = 253;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum EndgameError {
+        InvalidSignature(String),
+        TooManyPieces(usize),
+        // The mate is further away than a table byte can hold
+        DistanceOverflow(String),
+        BadMagic,
+        UnsupportedVersion(u8),
+        SizeMismatch { expected: usize, found: usize },
+    }
+
+    impl fmt::Display for EndgameError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                EndgameError::InvalidSignature(s) => write!(f, "invalid material signature '{}'", s),
+                EndgameError::TooManyPieces(n) => write!(f, "{} pieces is more than the {} the solver supports", n, MAX_PIECES),
+                EndgameError::DistanceOverflow(s) => write!(f, "mates in {} are too long to store", s),
+                EndgameError::BadMagic => write!(f, "not an endgame table file"),
+                EndgameError::UnsupportedVersion(v) => write!(f, "unsupported endgame table version {}", v),
+                EndgameError::SizeMismatch { expected, found } => write!(f, "table holds {} positions, expected {}", found, expected),
+            }
+        }
+    }
+
+    impl Error for EndgameError {}
+
+    impl From<EndgameError> for io::Error {
+        fn from(e: EndgameError) -> Self {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        }
+    }
+
+    // Result for the side to move, with the distance to mate in plies
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Outcome {
+        Win(u32),
+        Draw,
+        Loss(u32),
+    }
+
+    impl Outcome {
+        fn from_value(value: u8) -> Option<Outcome> {
+            match value {
+                DRAW => Some(Outcome::Draw),
+                ILLEGAL => None,
+                v if v.is_multiple_of(2) => Some(Outcome::Win(v as u32 - 1)),
+                v => Some(Outcome::Loss(v as u32 - 1))
+            }
+        }
+
+        // The outcome one ply earlier, for the side that made the move leading here
+        fn before_move(self) -> Outcome {
+            match self {
+                Outcome::Win(plies) => Outcome::Loss(plies + 1),
+                Outcome::Draw => Outcome::Draw,
+                Outcome::Loss(plies) => Outcome::Win(plies + 1),
+            }
+        }
+
+        // Full moves until mate, counted for the side to move
+        pub fn moves_to_mate(&self) -> Option<u32> {
+            match self {
+                Outcome::Win(plies) | Outcome::Loss(plies) => Some(plies.div_ceil(2)),
+                Outcome::Draw => None
+            }
+        }
+
+        // Search score for the side to move, faster wins and slower losses score higher
+        pub fn score(&self) -> i32 {
+            match self {
+                Outcome::Win(plies) => SOLVED_WIN_SCORE - *plies as i32,
+                Outcome::Draw => 0,
+                Outcome::Loss(plies) => -SOLVED_WIN_SCORE + *plies as i32,
+            }
+        }
+    }
+
+    fn piece_letter(piece: PieceType) -> char {
+        match piece {
+            KING => 'K',
+            QUEEN => 'Q',
+            ROOK => 'R',
+            BISHOP => 'B',
+            KNIGHT => 'N',
+            PAWN => 'P',
+            PieceType::None => '?'
+        }
+    }
+
+    fn letter_piece(c: char) -> Option<PieceType> {
+        match c {
+            'K' => Some(KING),
+            'Q' => Some(QUEEN),
+            'R' => Some(ROOK),
+            'B' => Some(BISHOP),
+            'N' => Some(KNIGHT),
+            'P' => Some(PAWN),
+            _ => None
+        }
+    }
+
+    // Order of the pieces inside a signature, the king first
+    fn piece_order(piece: PieceType) -> usize {
+        match piece {
+            KING => 0,
+            QUEEN => 1,
+            ROOK => 2,
+            BISHOP => 3,
+            KNIGHT => 4,
+            PAWN => 5,
+            PieceType::None => 6
+        }
+    }
+
+    fn piece_value(piece: PieceType) -> u32 {
+        match piece {
+            QUEEN => 9,
+            ROOK => 5,
+            BISHOP | KNIGHT => 3,
+            PAWN => 1,
+            _ => 0
+        }
+    }
+
+    // The pieces on the board for one table: the white king, white's other pieces, the black king and black's other pieces
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Signature {
+        pieces: Vec<(PieceType, bool)>,
+    }
+
+    impl Signature {
+        fn from_pieces(mut pieces: Vec<(PieceType, bool)>) -> Signature {
+            pieces.sort_by_key(|(piece, is_white)| (!*is_white, piece_order(*piece)));
+            Signature { pieces }
+        }
+
+        pub fn piece_count(&self) -> usize {
+            self.pieces.len()
+        }
+
+        pub fn pieces(&self) -> &[(PieceType, bool)] {
+            &self.pieces
+        }
+
+        fn side(&self, is_white: bool) -> String {
+            self.pieces.iter().filter(|(_, white)| *white == is_white).map(|(piece, _)| piece_letter(*piece)).collect()
+        }
+
+        fn side_value(&self, is_white: bool) -> (u32, String) {
+            let value = self.pieces.iter().filter(|(_, white)| *white == is_white).map(|(piece, _)| piece_value(*piece)).sum();
+            (value, self.side(is_white).chars().rev().collect())
+        }
+
+        // Tables are stored with the stronger side as white
+        pub fn is_canonical(&self) -> bool {
+            self.side_value(true) >= self.side_value(false)
+        }
+
+        pub fn flipped(&self) -> Signature {
+            Signature::from_pieces(self.pieces.iter().map(|(piece, is_white)| (*piece, !*is_white)).collect())
+        }
+
+        pub fn canonical(&self) -> Signature {
+            if self.is_canonical() { self.clone() } else { self.flipped() }
+        }
+
+        // Signatures the position can turn into through a capture or a promotion
+        fn exits(&self) -> Vec<Signature> {
+            let mut exits: Vec<Signature> = vec![];
+
+            for (i, (piece, is_white)) in self.pieces.iter().enumerate() {
+                if *piece == KING {
+                    continue
+                }
+
+                let mut captured = self.pieces.clone();
+                captured.remove(i);
+                exits.push(Signature::from_pieces(captured));
+
+                if *piece == PAWN {
+                    for promotion in [QUEEN, ROOK, BISHOP, KNIGHT] {
+                        let mut promoted = self.pieces.clone();
+                        promoted[i] = (promotion, *is_white);
+                        exits.push(Signature::from_pieces(promoted));
+                    }
+                }
+            }
+
+            let mut canonical: Vec<Signature> = exits.iter().map(|s| s.canonical()).filter(|s| s.piece_count() > 2).collect();
+            canonical.sort_by_key(|s| s.to_string());
+            canonical.dedup();
+            canonical
+        }
+
+        fn size(&self) -> usize {
+            2 << (6 * self.pieces.len())
+        }
+    }
+
+    impl fmt::Display for Signature {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "{}{}", self.side(true), self.side(false))
+        }
+    }
+
+    impl FromStr for Signature {
+        type Err = EndgameError;
+
+        // "KQK", "KBNK" or "KRKP", a 'v' between the sides is allowed
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let invalid = || EndgameError::InvalidSignature(s.to_string());
+            let letters: Vec<char> = s.trim().chars().filter(|c| *c != 'v').collect();
+
+            if letters.first() != Some(&'K') || letters.iter().filter(|c| **c == 'K').count() != 2 {
+                return Err(invalid())
+            }
+
+            let mut pieces = vec![];
+            let mut is_white = true;
+            for (i, c) in letters.iter().enumerate() {
+                let piece = letter_piece(*c).ok_or_else(invalid)?;
+                if piece == KING && i > 0 {
+                    is_white = false;
+                }
+                pieces.push((piece, is_white));
+            }
+
+            if pieces.len() > MAX_PIECES {
+                return Err(EndgameError::TooManyPieces(pieces.len()))
+            }
+
+            Ok(Signature::from_pieces(pieces))
+        }
+    }
+
+    // The signature of the pieces of a position with their squares in signature order
+    fn position_pieces(game: &Game) -> (Signature, Vec<usize>) {
+        let mut pieces = vec![];
+        let mut occupancy = game.board.get_board_value();
+        while occupancy > 0 {
+            let pos = utils::pop_lsb(&mut occupancy);
+            let (piece, is_white) = game.board.piece_at(pos as u8).unwrap();
+            pieces.push((piece, is_white, pos));
+        }
+
+        pieces.sort_by_key(|(piece, is_white, pos)| (!*is_white, piece_order(*piece), *pos));
+        let squares = pieces.iter().map(|(_, _, pos)| *pos).collect();
+        (Signature { pieces: pieces.into_iter().map(|(piece, is_white, _)| (piece, is_white)).collect() }, squares)
+    }
+
+    pub struct EndgameTable {
+        signature: Signature,
+        values: Vec<u8>,
+    }
+
+    impl EndgameTable {
+        pub fn signature(&self) -> &Signature {
+            &self.signature
+        }
+
+        // Number of positions, legal or not
+        pub fn len(&self) -> usize {
+            self.values.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.values.is_empty()
+        }
+
+        fn index(squares: &[usize], white_to_move: bool) -> usize {
+            squares.iter().fold(!white_to_move as usize, |index, square| index << 6 | square)
+        }
+
+        fn value(&self, squares: &[usize], white_to_move: bool) -> u8 {
+            self.values[Self::index(squares, white_to_move)]
+        }
+
+        // Outcome with the pieces on the given squares, in signature order. None for an illegal placement.
+        pub fn outcome(&self, squares: &[usize], white_to_move: bool) -> Option<Outcome> {
+            if squares.len() != self.signature.piece_count() || squares.iter().any(|s| *s > 63) {
+                return None
+            }
+            Outcome::from_value(self.value(squares, white_to_move))
+        }
+
+        // The longest win the side to move can have
+        pub fn longest_win(&self, white_to_move: bool) -> Option<u32> {
+            let half = self.values.len() / 2;
+            let side = if white_to_move { &self.values[..half] } else { &self.values[half..] };
+            side.iter()
+                .filter_map(|value| match Outcome::from_value(*value) {
+                    Some(Outcome::Win(plies)) => Some(plies),
+                    _ => None
+                })
+                .max()
+        }
+
+        // Header, then the values run length encoded as (value, LEB128 run length) pairs
+        pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            let name = self.signature.to_string();
+            writer.write_all(&MAGIC)?;
+            writer.write_all(&[VERSION, name.len() as u8])?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&(self.values.len() as u64).to_le_bytes())?;
+
+            let mut i = 0;
+            while i < self.values.len() {
+                let value = self.values[i];
+                let run = self.values[i..].iter().take_while(|v| **v == value).count();
+                writer.write_all(&[value])?;
+
+                let mut rest = run;
+                loop {
+                    let byte = (rest & 0x7F) as u8;
+                    rest >>= 7;
+                    if rest == 0 {
+                        writer.write_all(&[byte])?;
+                        break
+                    }
+                    writer.write_all(&[byte | 0x80])?;
+                }
+
+                i += run;
+            }
+
+            Ok(())
+        }
+
+        pub fn read<R: Read>(reader: &mut R) -> io::Result<EndgameTable> {
+            let mut header = [0_u8; 6];
+            reader.read_exact(&mut header)?;
+            if header[..4] != MAGIC {
+                return Err(EndgameError::BadMagic.into())
+            }
+            if header[4] != VERSION {
+                return Err(EndgameError::UnsupportedVersion(header[4]).into())
+            }
+
+            let mut name = vec![0_u8; header[5] as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8_lossy(&name).to_string();
+            let signature = name.parse::<Signature>()?;
+
+            let mut len = [0_u8; 8];
+            reader.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len) as usize;
+            if len != signature.size() {
+                return Err(EndgameError::SizeMismatch { expected: signature.size(), found: len }.into())
+            }
+
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes)?;
+            let mut values = Vec::with_capacity(len);
+            let mut bytes = bytes.into_iter();
+            while let Some(value) = bytes.next() {
+                let mut run = 0;
+                let mut shift = 0;
+                loop {
+                    let byte = bytes.next().ok_or(io::ErrorKind::UnexpectedEof)?;
+                    run |= ((byte & 0x7F) as usize) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 || shift > 35 {
+                        break
+                    }
+                }
+
+                if values.len() + run > len {
+                    return Err(EndgameError::SizeMismatch { expected: len, found: values.len() + run }.into())
+                }
+                values.resize(values.len() + run, value);
+            }
+
+            if values.len() != len {
+                return Err(EndgameError::SizeMismatch { expected: len, found: values.len() }.into())
+            }
+
+            Ok(EndgameTable { signature, values })
+        }
+
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(path)?);
+            self.write(&mut writer)?;
+            writer.flush()
+        }
+
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<EndgameTable> {
+            EndgameTable::read(&mut BufReader::new(File::open(path)?))
+        }
+    }
+
+    // A set of solved tables, probed together so captures and promotions can move between them
+    #[derive(Default)]
+    pub struct EndgameTables {
+        // Keyed by the signature name
+        tables: HashMap<String, EndgameTable>,
+    }
+
+    impl EndgameTables {
+        pub fn new() -> EndgameTables {
+            EndgameTables::default()
+        }
+
+        pub fn len(&self) -> usize {
+            self.tables.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.tables.is_empty()
+        }
+
+        pub fn get(&self, signature: &Signature) -> Option<&EndgameTable> {
+            self.tables.get(&signature.to_string())
+        }
+
+        pub fn insert(&mut self, table: EndgameTable) {
+            self.tables.insert(table.signature.to_string(), table);
+        }
+
+        // Solves the table for the signature, and before it every missing table it can reach
+        pub fn generate(&mut self, signature: &Signature) -> Result<&EndgameTable, EndgameError> {
+            let signature = signature.canonical();
+            if signature.piece_count() > MAX_PIECES {
+                return Err(EndgameError::TooManyPieces(signature.piece_count()))
+            }
+
+            let name = signature.to_string();
+            if !self.tables.contains_key(&name) {
+                for exit in signature.exits() {
+                    self.generate(&exit)?;
+                }
+
+                let move_gen = MoveGen::init();
+                let table = Solver::new(&signature, &move_gen, self).solve()?;
+                self.tables.insert(name.clone(), table);
+            }
+
+            Ok(&self.tables[&name])
+        }
+
+        // Loads every table file in the directory
+        pub fn open_dir<P: AsRef<Path>>(path: P) -> io::Result<EndgameTables> {
+            let mut tables = EndgameTables::new();
+            for file in fs::read_dir(path)? {
+                let path = file?.path();
+                if path.extension().and_then(|e| e.to_str()) == Some(EXTENSION) {
+                    tables.insert(EndgameTable::open(&path)?);
+                }
+            }
+            Ok(tables)
+        }
+
+        // Writes every table as <signature>.egt, returns how many were written
+        pub fn save_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+            fs::create_dir_all(&path)?;
+            for table in self.tables.values() {
+                table.save(path.as_ref().join(format!("{}.{}", table.signature, EXTENSION)))?;
+            }
+            Ok(self.tables.len())
+        }
+
+        // The stored value for pieces given in signature order, mirrored into the stored orientation when needed
+        fn lookup(&self, signature: &Signature, squares: &[usize], white_to_move: bool) -> Option<u8> {
+            if signature.piece_count() == 2 {
+                return Some(DRAW)
+            }
+
+            if signature.is_canonical() {
+                return self.get(signature).map(|table| table.value(squares, white_to_move))
+            }
+
+            // Black's pieces come first in the flipped signature, in the same order they have here
+            let flipped = signature.flipped();
+            let white = signature.pieces.iter().filter(|(_, is_white)| *is_white).count();
+            let mirrored: Vec<usize> = squares[white..].iter().chain(squares[..white].iter()).map(|s| s ^ 56).collect();
+            self.get(&flipped).map(|table| table.value(&mirrored, !white_to_move))
+        }
+
+        // Exact result of the position, None when there is no table for it or it has castling or en passant rights
+        pub fn probe(&self, game: &Game) -> Option<Outcome> {
+            if game.board.get_board_value().count_ones() as usize > MAX_PIECES
+                || game.effective_castling_rights().any()
+                || game.en_passant_capture_possible() {
+                return None
+            }
+
+            let (signature, squares) = position_pieces(game);
+            Outcome::from_value(self.lookup(&signature, &squares, game.is_white_turn)?)
+        }
+
+        // Every legal move with its outcome, best first
+        pub fn root_moves(&self, game: &mut Game) -> Option<Vec<(Move, Outcome)>> {
+            self.probe(game)?;
+
+            let mut moves = vec![];
+            for m in game.legal_moves() {
+                game.make_move(&m);
+                let outcome = self.probe(game);
+                game.undo_move();
+                moves.push((m, outcome?.before_move()));
+            }
+
+            moves.sort_by_key(|(_, outcome)| std::cmp::Reverse(outcome.score()));
+            Some(moves)
+        }
+
+        pub fn best_move(&self, game: &mut Game) -> Option<(Move, Outcome)> {
+            self.root_moves(game)?.into_iter().next()
+        }
+    }
+
+    // Where a move from a position in the table goes
+    enum Successor {
+        // A quiet move to another position of the same table
+        Inside,
+        // Stored value of the position in the table the capture or promotion leads to
+        Exit(u8),
+    }
+
+    struct Solver<'a> {
+        signature: &'a Signature,
+        move_gen: &'a MoveGen,
+        tables: &'a EndgameTables,
+        count: usize,
+    }
+
+    impl<'a> Solver<'a> {
+        fn new(signature: &'a Signature, move_gen: &'a MoveGen, tables: &'a EndgameTables) -> Solver<'a> {
+            Solver { signature, move_gen, tables, count: signature.piece_count() }
+        }
+
+        fn decode(&self, index: usize, squares: &mut [usize]) -> bool {
+            for i in (0..self.count).rev() {
+                squares[i] = (index >> (6 * (self.count - 1 - i))) & 63;
+            }
+            index >> (6 * self.count) == 0
+        }
+
+        fn shift(&self, slot: usize) -> usize {
+            6 * (self.count - 1 - slot)
+        }
+
+        fn attacks(&self, piece: PieceType, is_white: bool, pos: usize, occupancy: u64) -> u64 {
+            let move_gen = self.move_gen;
+            match piece {
+                PAWN => if is_white { move_gen.white_pawn_attack_table[pos] } else { move_gen.black_pawn_attack_table[pos] },
+                KNIGHT => move_gen.knight_position_board[pos],
+                KING => move_gen.king_position_board[pos],
+                ROOK => move_gen.get_rook_moves(pos, occupancy),
+                BISHOP => move_gen.get_bishop_moves(pos, occupancy),
+                QUEEN => move_gen.get_rook_moves(pos, occupancy) | move_gen.get_bishop_moves(pos, occupancy),
+                PieceType::None => 0
+            }
+        }
+
+        // Whether the king of the given colour is attacked, leaving out a captured piece
+        fn king_attacked(&self, squares: &[usize], is_white: bool, captured: Option<usize>) -> bool {
+            let pieces = &self.signature.pieces;
+            let occupancy = squares.iter().enumerate()
+                .filter(|(i, _)| Some(*i) != captured)
+                .fold(0, |occupancy, (_, s)| occupancy | utils::POSITIONS[*s]);
+            let king = pieces.iter().position(|(piece, white)| *piece == KING && *white == is_white).unwrap();
+
+            pieces.iter().enumerate().any(|(i, (piece, white))| {
+                *white != is_white && Some(i) != captured
+                    && self.attacks(*piece, *white, squares[i], occupancy) & utils::POSITIONS[squares[king]] > 0
+            })
+        }
+
+        fn is_legal(&self, squares: &[usize], white_to_move: bool) -> bool {
+            let mut occupancy = 0;
+            for (i, s) in squares.iter().enumerate() {
+                if occupancy & utils::POSITIONS[*s] > 0 {
+                    return false
+                }
+                occupancy |= utils::POSITIONS[*s];
+
+                let row = s / 8;
+                if self.signature.pieces[i].0 == PAWN && (row == 0 || row == 7) {
+                    return false
+                }
+            }
+
+            !self.king_attacked(squares, !white_to_move, None)
+        }
+
+        fn exit_value(&self, pieces: Vec<(PieceType, bool, usize)>, white_to_move: bool) -> u8 {
+            let mut pieces = pieces;
+            pieces.sort_by_key(|(piece, is_white, _)| (!*is_white, piece_order(*piece)));
+            let squares: Vec<usize> = pieces.iter().map(|(_, _, s)| *s).collect();
+            let signature = Signature { pieces: pieces.into_iter().map(|(piece, is_white, _)| (piece, is_white)).collect() };
+
+            self.tables.lookup(&signature, &squares, white_to_move).expect("Tables for captures and promotions are solved first")
+        }
+
+        // Calls f with every legal move of the side to move
+        fn for_each_move(&self, squares: &[usize], white_to_move: bool, mut f: impl FnMut(Successor)) {
+            let pieces = &self.signature.pieces;
+            let occupancy = squares.iter().fold(0, |occupancy, s| occupancy | utils::POSITIONS[*s]);
+            let own = squares.iter().enumerate()
+                .filter(|(i, _)| pieces[*i].1 == white_to_move)
+                .fold(0, |own, (_, s)| own | utils::POSITIONS[*s]);
+            let mut next = squares.to_vec();
+
+            for (i, (piece, is_white)) in pieces.iter().enumerate() {
+                if *is_white != white_to_move {
+                    continue
+                }
+
+                let from = squares[i];
+                let mut targets = self.attacks(*piece, *is_white, from, occupancy) & !own;
+                if *piece == PAWN {
+                    targets &= occupancy;
+                    let (step, start_row): (isize, usize) = if *is_white { (-8, 6) } else { (8, 1) };
+                    let one = (from as isize + step) as usize;
+                    if occupancy & utils::POSITIONS[one] == 0 {
+                        targets |= utils::POSITIONS[one];
+                        let two = (one as isize + step) as usize;
+                        if from / 8 == start_row && occupancy & utils::POSITIONS[two] == 0 {
+                            targets |= utils::POSITIONS[two];
+                        }
+                    }
+                }
+
+                while targets > 0 {
+                    let to = utils::pop_lsb(&mut targets);
+                    let captured = squares.iter().position(|s| *s == to);
+                    next[i] = to;
+
+                    if !self.king_attacked(&next, white_to_move, captured) {
+                        let promotes = *piece == PAWN && (to / 8 == 0 || to / 8 == 7);
+                        if captured.is_none() && !promotes {
+                            f(Successor::Inside);
+                        } else {
+                            let remaining: Vec<(PieceType, bool, usize)> = pieces.iter().enumerate()
+                                .filter(|(j, _)| Some(*j) != captured)
+                                .map(|(j, (p, w))| (*p, *w, next[j]))
+                                .collect();
+
+                            if promotes {
+                                for promotion in [QUEEN, ROOK, BISHOP, KNIGHT] {
+                                    let promoted = remaining.iter()
+                                        .map(|(p, w, s)| if *s == to { (promotion, *w, *s) } else { (*p, *w, *s) })
+                                        .collect();
+                                    f(Successor::Exit(self.exit_value(promoted, !white_to_move)));
+                                }
+                            } else {
+                                f(Successor::Exit(self.exit_value(remaining, !white_to_move)));
+                            }
+                        }
+                    }
+
+                    next[i] = from;
+                }
+            }
+        }
+
+        // Calls f with the index of every position where the side that just moved made a quiet move leading here
+        fn for_each_predecessor(&self, squares: &[usize], white_to_move: bool, mut f: impl FnMut(usize)) {
+            let pieces = &self.signature.pieces;
+            let occupancy = squares.iter().fold(0, |occupancy, s| occupancy | utils::POSITIONS[*s]);
+            let index = EndgameTable::index(squares, white_to_move);
+            let side_bit = 1 << (6 * self.count);
+
+            for (i, (piece, is_white)) in pieces.iter().enumerate() {
+                if *is_white == white_to_move {
+                    continue
+                }
+
+                let to = squares[i];
+                let mut sources = if *piece == PAWN {
+                    let (step, start_row): (isize, usize) = if *is_white { (8, 6) } else { (-8, 1) };
+                    let one = (to as isize + step) as usize;
+                    let mut sources = 0;
+                    if (to as isize + step) >= 8 && one < 56 && occupancy & utils::POSITIONS[one] == 0 {
+                        sources |= utils::POSITIONS[one];
+                        let two = (one as isize + step) as usize;
+                        if (two / 8) == start_row && occupancy & utils::POSITIONS[two] == 0 {
+                            sources |= utils::POSITIONS[two];
+                        }
+                    }
+                    sources
+                } else {
+                    self.attacks(*piece, *is_white, to, occupancy) & !occupancy
+                };
+
+                let shift = self.shift(i);
+                while sources > 0 {
+                    let from = utils::pop_lsb(&mut sources);
+                    f((index ^ side_bit) - (to << shift) + (from << shift));
+                }
+            }
+        }
+
+        fn solve(&self) -> Result<EndgameTable, EndgameError> {
+            let size = self.signature.size();
+            let mut values = vec![DRAW; size];
+            // In table moves not yet known to lose
+            let mut remaining = vec![0_u8; size];
+            // Best result of the moves leaving the table, 0 when there are none
+            let mut exits = vec![0_u8; size];
+            let mut last = 0;
+
+            let overflow = || EndgameError::DistanceOverflow(self.signature.to_string());
+            let mut squares = vec![0; self.count];
+
+            for index in 0..size {
+                self.decode(index, &mut squares);
+                let white_to_move = index < size / 2;
+                if !self.is_legal(&squares, white_to_move) {
+                    values[index] = ILLEGAL;
+                    continue
+                }
+
+                let mut moves = 0;
+                let mut inside = 0_u32;
+                let mut best_win = None;
+                let mut worst_loss = None;
+                let mut draws = false;
+                self.for_each_move(&squares, white_to_move, |successor| {
+                    moves += 1;
+                    match successor {
+                        Successor::Inside => inside += 1,
+                        Successor::Exit(value) => match Outcome::from_value(value).map(Outcome::before_move) {
+                            Some(Outcome::Win(plies)) => best_win = Some(best_win.map_or(plies, |best: u32| best.min(plies))),
+                            Some(Outcome::Loss(plies)) => worst_loss = Some(worst_loss.map_or(plies, |worst: u32| worst.max(plies))),
+                            _ => draws = true
+                        }
+                    }
+                });
+
+                let exit = match (best_win, draws, worst_loss) {
+                    (Some(plies), _, _) | (None, false, Some(plies)) => plies as u8 + 1,
+                    (None, true, _) => EXIT_DRAW,
+                    (None, false, None) => 0
+                };
+
+                if moves == 0 {
+                    if self.king_attacked(&squares, white_to_move, None) {
+                        values[index] = 1;
+                        last = last.max(1);
+                    }
+                } else if inside == 0 {
+                    values[index] = if exit == EXIT_DRAW { DRAW } else { exit };
+                } else {
+                    remaining[index] = inside.min(255) as u8;
+                    exits[index] = exit;
+                }
+
+                if exit != EXIT_DRAW {
+                    last = last.max(exit as u32);
+                }
+            }
+
+            // Positions get solved in order of their distance to mate: a position that can move into a loss
+            // is a win one ply longer, and once every move of a position is known to lose it is lost
+            let mut plies = 0;
+            while plies < last {
+                let value = plies as u8 + 1;
+
+                if plies % 2 == 1 {
+                    for index in 0..size {
+                        if values[index] == DRAW && remaining[index] > 0 && exits[index] == value {
+                            values[index] = value;
+                        }
+                    }
+                }
+
+                for index in 0..size {
+                    if values[index] != value {
+                        continue
+                    }
+                    self.decode(index, &mut squares);
+                    let white_to_move = index < size / 2;
+
+                    self.for_each_predecessor(&squares, white_to_move, |previous| {
+                        if values[previous] != DRAW || remaining[previous] == 0 {
+                            return
+                        }
+
+                        if plies % 2 == 0 {
+                            values[previous] = value + 1;
+                            last = last.max(plies + 2);
+                        } else {
+                            remaining[previous] -= 1;
+                            let exit = exits[previous];
+                            if remaining[previous] == 0 && (exit == 0 || (exit != EXIT_DRAW && !exit.is_multiple_of(2))) {
+                                let loss = (plies + 1).max(exit.saturating_sub(1) as u32);
+                                values[previous] = loss as u8 + 1;
+                                last = last.max(loss + 1);
+                            }
+                        }
+                    });
+                }
+
+                if last > MAX_DISTANCE {
+                    return Err(overflow())
+                }
+                plies += 1;
+            }
+
+            Ok(EndgameTable { signature: self.signature.clone(), values })
+        }
+    }
+}
@@ -1,21 +1,201 @@
-pub(crate) mod engine {
-    use std::cmp::{max, min, Ordering, PartialOrd};
+pub mod engine {
+    use std::cmp::{Ordering, PartialOrd};
     use std::fmt::{Debug};
     use std::ops::Sub;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+    use std::time::Duration;
+    use std::thread;
     use PieceType::{BISHOP, KNIGHT, PAWN, QUEEN, ROOK};
     use crate::board::board::Move;
-    use crate::debug::debug::print_board;
     use crate::game::game::Game;
     use crate::move_gen::move_gen::PieceType;
     use crate::move_gen::move_gen::PieceType::KING;
-    use crate::print_moves;
-    use crate::utils::utils::pop_lsb;
+    use crate::endgame::endgame::{EndgameTables, Outcome};
+    use crate::syzygy::syzygy::{Tablebase, Wdl};
+    use crate::time_manager::time_manager::TimeManager;
+    use crate::search_limits::search_limits::SearchLimits;
+    use crate::search_info::search_info::{SearchControl, SearchInfo, INFO_INTERVAL};
+    use crate::transposition_table::transposition_table::{Bound, TranspositionTable};
+    use crate::polyglot::polyglot::{encode_move, polyglot_key};
+    use crate::move_order::move_order::{is_quiet, mvv_lva, MoveOrdering, OrderingStats};
+    use crate::score::score::{mate_in, mate_moves, mated_in, Score, DRAW, INFINITE, MATE, MATE_BOUND};
+
+    // Deepest iteration the search goes to
+    const MAX_DEPTH: usize = 64;
+
+    // Deepest ply quiescence goes to before it settles for the static evaluation
+    const MAX_PLY: usize = 128;
+
+    // A capture is skipped in quiescence when even winning the piece plus this can't reach alpha
+    const DELTA_MARGIN: i32 = 200;
+
+    // Null move pruning and late move reductions start at this depth
+    const NULL_MOVE_MIN_DEPTH: usize = 3;
+    const LMR_MIN_DEPTH: usize = 3;
+    // Moves searched at full depth before the later ones are reduced
+    const LMR_FULL_DEPTH_MOVES: usize = 3;
+
+    // Iterations before this depth search the root with a full window
+    const ASPIRATION_MIN_DEPTH: usize = 4;
+
+    // Deepest remaining depth each forward pruning technique is tried at
+    const REVERSE_FUTILITY_MAX_DEPTH: usize = 6;
+    const RAZORING_MAX_DEPTH: usize = 2;
+    const FUTILITY_MAX_DEPTH: usize = 4;
+    const LATE_MOVE_PRUNING_MAX_DEPTH: usize = 4;
+    pub const PRUNING_DEPTHS: usize = REVERSE_FUTILITY_MAX_DEPTH + 1;
+
+    // Singular extensions are tried from this depth on, with a hash entry at most this much shallower
+    const SINGULAR_MIN_DEPTH: usize = 6;
+    const SINGULAR_TT_DEPTH_MARGIN: usize = 3;
+
+    // Nodes and moves cut by each forward pruning technique, indexed by the remaining depth
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct PruningStats {
+        pub reverse_futility: [usize; PRUNING_DEPTHS],
+        pub razoring: [usize; PRUNING_DEPTHS],
+        pub futility: [usize; PRUNING_DEPTHS],
+        pub late_move: [usize; PRUNING_DEPTHS],
+    }
+
+    // Why a move was searched a ply deeper
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Extension {
+        Check, // the move gives check
+        OneReply, // the only legal move
+        Singular, // the hash move, much better than every other move
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct ExtensionStats {
+        pub check: usize,
+        pub one_reply: usize,
+        pub singular: usize,
+        pub singular_searches: usize, // exclusion searches run to find singular moves
+        pub limited: usize, // extensions not given because the line had enough
+        pub most_on_a_line: [usize; 3], // most check, one reply and singular extensions on a single line
+    }
+
+    impl ExtensionStats {
+        fn record(&mut self, extension: Extension) {
+            match extension {
+                Extension::Check => self.check += 1,
+                Extension::OneReply => self.one_reply += 1,
+                Extension::Singular => self.singular += 1,
+            }
+        }
+    }
+
+    // Switches and parameters of the selective search, everything on by default. Margins are per ply of remaining depth.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct SearchOptions {
+        pub null_move: bool,
+        pub late_move_reductions: bool,
+        // A late move at depth d and move number n is reduced by lmr_base + ln(d) * ln(n) / lmr_divisor plies
+        pub lmr_base: f64,
+        pub lmr_divisor: f64,
+        // A node whose static evaluation is this far above beta fails high without a search
+        pub reverse_futility: bool,
+        pub reverse_futility_margin: Score,
+        // A node this far below alpha drops into quiescence
+        pub razoring: bool,
+        pub razoring_margin: Score,
+        // Quiet moves are skipped when the static evaluation plus this can't reach alpha
+        pub futility: bool,
+        pub futility_margin: Score,
+        // Only lmp_base + depth² quiet moves are searched
+        pub late_move_pruning: bool,
+        pub lmp_base: usize,
+        // Half width of the first root window around the previous score, doubled after every fail high or low
+        pub aspiration_window: Score,
+        // Search threads, the main thread and threads - 1 helpers
+        pub threads: usize,
+        // Root moves searched for an exact score and a line of their own, the best first
+        pub multi_pv: usize,
+        // Moves giving check and the only legal moves are searched a ply deeper
+        pub check_extension: bool,
+        pub one_reply_extension: bool,
+        // So is the hash move when a search without it at half the depth stays singular_margin per ply below its score
+        pub singular_extension: bool,
+        pub singular_margin: Score,
+        // Extensions of each kind on one line, none are given past twice the iteration's depth
+        pub max_check_extensions: usize,
+        pub max_one_reply_extensions: usize,
+        pub max_singular_extensions: usize,
+        // What a draw is worth less than equality to the side that started the search. Against a weaker opponent it
+        // is positive, and the engine keeps playing rather than repeat moves unless that costs more than contempt.
+        pub contempt: Score,
+        // Analysis scores draws as equal whatever the contempt
+        pub analysis: bool,
+    }
+
+    impl Default for SearchOptions {
+        fn default() -> Self {
+            SearchOptions {
+                null_move: true,
+                late_move_reductions: true,
+                lmr_base: 0.75,
+                lmr_divisor: 2.25,
+                reverse_futility: true,
+                reverse_futility_margin: 120,
+                razoring: true,
+                razoring_margin: 350,
+                futility: true,
+                futility_margin: 150,
+                late_move_pruning: true,
+                lmp_base: 3,
+                aspiration_window: 40,
+                threads: 1,
+                multi_pv: 1,
+                check_extension: true,
+                one_reply_extension: true,
+                singular_extension: true,
+                singular_margin: 4,
+                max_check_extensions: 16,
+                max_one_reply_extensions: 16,
+                max_singular_extensions: 6,
+                contempt: 0,
+                analysis: false,
+            }
+        }
+    }
+
+    impl SearchOptions {
+        pub fn effective_contempt(&self) -> Score {
+            if self.analysis { 0 } else { self.contempt }
+        }
+
+        fn max_extensions(&self, extension: Extension) -> usize {
+            match extension {
+                Extension::Check => self.max_check_extensions,
+                Extension::OneReply => self.max_one_reply_extensions,
+                Extension::Singular => self.max_singular_extensions,
+            }
+        }
+
+        // Plies a quiet move is reduced by, indexed by depth and move number
+        fn reduction_table(&self) -> Vec<[usize; 64]> {
+            (0..=MAX_DEPTH)
+                .map(|depth| {
+                    let mut row = [0; 64];
+                    for (number, reduction) in row.iter_mut().enumerate().skip(1) {
+                        if depth > 0 {
+                            let r = self.lmr_base + (depth as f64).ln() * (number as f64).ln() / self.lmr_divisor;
+                            *reduction = r.max(0.0) as usize;
+                        }
+                    }
+                    row
+                })
+                .collect()
+        }
+    }
 
     #[derive(Debug)]
     pub struct Branch {
         pub m: Move,
-        pub val: i32,
-        pub leafs: usize
+        pub val: Score, // from the side to move
+        pub leafs: usize,
+        pub pv: Vec<Move> // expected line, starting with m
     }
 
     impl Eq for Branch {}
@@ -28,7 +208,7 @@ pub(crate) mod engine {
 
     impl PartialOrd<Self> for Branch {
         fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-            other.val.partial_cmp(&self.val)
+            Some(self.cmp(other))
         }
     }
 
@@ -39,9 +219,7 @@ pub(crate) mod engine {
     }
 
     #[derive(Clone)]
-    pub(crate) struct Engine {
-        pub(crate) i: i32,
-    }
+    pub struct Engine;
 
     impl PartialOrd for PieceType {
         fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -118,189 +296,976 @@ pub(crate) mod engine {
         }
     }
 
+    // Result of the last completed iteration
+    #[derive(Debug)]
+    pub struct SearchResult {
+        pub branches: Vec<Branch>, // best move first
+        pub lines: usize, // leading branches with an exact score and line, the scores of the rest are only bounds
+        pub pv: Vec<Move>, // principal variation of the best move
+        pub depth: usize,
+        pub seldepth: usize,
+        pub leafs: usize,
+        pub nodes: usize, // main search nodes
+        pub qnodes: usize, // quiescence nodes
+        pub tbhits: usize, // positions found in the tablebases or endgame tables
+        pub ordering: OrderingStats,
+        pub null_move_cutoffs: usize,
+        pub lmr_researches: usize, // reduced moves searched again at full depth
+        pub pruning: PruningStats,
+        pub aspiration_researches: usize, // root searches repeated with a wider window
+        pub extensions: ExtensionStats,
+    }
+
+    impl SearchResult {
+        // None when there is no legal move
+        pub fn best_move(&self) -> Option<Move> {
+            self.branches.first().map(|branch| branch.m)
+        }
+
+        // The reply expected to the best move
+        pub fn ponder_move(&self) -> Option<Move> {
+            self.pv.get(1).copied()
+        }
+
+        pub fn score(&self) -> Option<Score> {
+            self.branches.first().map(|branch| branch.val)
+        }
+    }
+
+    // Triangular table of principal variations, row ply holds the best line found from that ply on.
+    // A new best move at a ply is followed by the line of the ply below it.
+    struct PvTable {
+        moves: Vec<Move>,
+        length: [usize; MAX_PLY + 1],
+    }
+
+    impl PvTable {
+        fn new() -> PvTable {
+            PvTable { moves: vec![Move::None; MAX_PLY * MAX_PLY], length: [0; MAX_PLY + 1] }
+        }
+
+        fn clear(&mut self, ply: usize) {
+            self.length[ply] = 0;
+        }
+
+        fn update(&mut self, ply: usize, m: Move) {
+            let child = self.length[ply + 1].min(MAX_PLY - 1);
+            let (row, rest) = self.moves[ply * MAX_PLY..].split_at_mut(MAX_PLY);
+            row[0] = m;
+            if ply + 1 < MAX_PLY {
+                row[1..=child].copy_from_slice(&rest[..child]);
+            }
+            self.length[ply] = child + 1;
+        }
+
+        fn line(&self, ply: usize) -> &[Move] {
+            &self.moves[ply * MAX_PLY..ply * MAX_PLY + self.length[ply]]
+        }
+    }
+
+    // What all threads of a search share
+    struct Shared<'a> {
+        tt: &'a TranspositionTable,
+        tablebase: Option<&'a Tablebase>,
+        endgames: Option<&'a EndgameTables>,
+        time: TimeManager,
+        limits: &'a SearchLimits,
+        options: &'a SearchOptions,
+        control: &'a SearchControl,
+        finished: AtomicBool, // set by the main thread when it is done, helpers stop with it
+        helper_nodes: AtomicUsize, // searched by the helpers so far, for the reports
+    }
+
+    // State of one search thread: the shared hash table, probing tables, limits and stop flags, and its own node
+    // count, PV table and move ordering
+    struct Search<'a> {
+        tt: &'a TranspositionTable,
+        tablebase: Option<&'a Tablebase>,
+        endgames: Option<&'a EndgameTables>,
+        time: &'a TimeManager,
+        limits: &'a SearchLimits,
+        control: &'a SearchControl,
+        finished: &'a AtomicBool,
+        helper_nodes: &'a AtomicUsize,
+        thread: usize, // 0 for the main thread
+        depth: usize, // of the running iteration
+        seldepth: usize,
+        nodes: usize,
+        qnodes: usize,
+        reported_nodes: usize, // a helper's nodes already added to helper_nodes
+        tbhits: usize,
+        current_move: Option<(Move, usize)>, // root move being searched and its number
+        last_info: Duration, // when the last report went out
+        stopped: bool,
+        pv: PvTable,
+        ordering: MoveOrdering,
+        options: SearchOptions,
+        reductions: Vec<[usize; 64]>,
+        null_move_cutoffs: usize,
+        lmr_researches: usize,
+        pruning: PruningStats,
+        aspiration_researches: usize,
+        extensions: ExtensionStats,
+        line_extensions: [usize; 3], // extensions of each kind on the line being searched
+        excluded: Vec<Move>, // by ply, the move left out by a singular extension search
+        root_white: bool, // side to move at the root, contempt is from its view
+        contempt_key: u64, // mixed into the hash table keys when draws score with contempt
+        keys: Vec<u64>, // of the positions before the current one, one per move in the game's move log
+        root_keys: usize, // keys of the game before the root, a position from there has to repeat twice
+    }
+
     impl Engine {
-        pub fn get_sorted_moves(game: &mut Game, is_maximizing: bool, depth: usize) -> (Vec<Branch>, usize) {
-            let mut sorted_moves: Vec<Branch> = vec![];
+        // Searches depth 1, 2, ... until one of the limits is reached or the search is stopped, and returns the last
+        // completed depth. With more than one thread the helpers search the same position and share what they find
+        // through the hash table (Lazy SMP). The main thread keeps the limits and its result is the one returned.
+        pub fn iterative_deepening(
+            game: &mut Game,
+            limits: &SearchLimits,
+            control: &SearchControl,
+            tt: &mut TranspositionTable,
+            tablebase: Option<&Tablebase>,
+            endgames: Option<&EndgameTables>,
+            options: &SearchOptions
+        ) -> SearchResult {
+            tt.new_search();
+            let shared = Shared {
+                tt,
+                tablebase,
+                endgames,
+                time: TimeManager::new(&limits.time_control(), game.is_white_turn),
+                limits,
+                options,
+                control,
+                finished: AtomicBool::new(false),
+                helper_nodes: AtomicUsize::new(0),
+            };
+            let shared = &shared;
 
-            let mut alpha = i32::MIN;
-            let mut beta = i32::MAX;
+            thread::scope(|scope| {
+                let helpers: Vec<_> = (1..options.threads.max(1))
+                    .map(|thread| {
+                        let mut game = game.clone();
+                        scope.spawn(move || {
+                            let mut search = Search::new(shared, thread);
+                            search.iterate(&mut game);
+                            (search.nodes, search.qnodes)
+                        })
+                    })
+                    .collect();
 
-            let mut all_leafs = 0;
+                let mut search = Search::new(shared, 0);
+                let mut result = search.iterate(game);
+                shared.finished.store(true, AtomicOrdering::Relaxed);
 
-            let (mut moves, _, _) = game.get_all_moves();
+                for helper in helpers {
+                    let (nodes, qnodes) = helper.join().expect("Search thread panicked");
+                    result.nodes += nodes;
+                    result.qnodes += qnodes;
+                }
+                result
+            })
+        }
+    }
 
-            moves.sort_by(Self::ordering_moves);
+    impl<'a> Search<'a> {
+        fn new(shared: &'a Shared<'a>, thread: usize) -> Search<'a> {
+            Search {
+                tt: shared.tt,
+                tablebase: shared.tablebase,
+                endgames: shared.endgames,
+                time: &shared.time,
+                limits: shared.limits,
+                control: shared.control,
+                finished: &shared.finished,
+                helper_nodes: &shared.helper_nodes,
+                thread,
+                depth: 0,
+                seldepth: 0,
+                nodes: 0,
+                qnodes: 0,
+                reported_nodes: 0,
+                tbhits: 0,
+                current_move: None,
+                last_info: Duration::ZERO,
+                stopped: false,
+                pv: PvTable::new(),
+                ordering: MoveOrdering::new(),
+                options: *shared.options,
+                reductions: shared.options.reduction_table(),
+                null_move_cutoffs: 0,
+                lmr_researches: 0,
+                pruning: PruningStats::default(),
+                aspiration_researches: 0,
+                extensions: ExtensionStats::default(),
+                line_extensions: [0; 3],
+                excluded: vec![Move::None; MAX_PLY + 1],
+                root_white: true,
+                contempt_key: 0,
+                keys: vec![],
+                root_keys: 0,
+            }
+        }
 
-            if is_maximizing {
-                let mut max_eval = i32::MIN;
-                let mut total_leafs = 0;
-                for m in moves {
-                    game.make_move(&m);
-                    let (eval, leaves) = Self::alpha_beta_from_internet(game, false, alpha, beta, depth - 1);
-                    total_leafs += leaves;
-                    game.undo_move();
-
-                    sorted_moves.push(Branch {
-                        m: m,
-                        val: eval,
-                        leafs: leaves
-                    });
-                    
-                    max_eval = i32::max(eval, max_eval);
-                    alpha = i32::max(alpha, eval);
-                    if beta <= alpha {
-                        break
-                    }
+        // Iterative deepening on this thread
+        fn iterate(&mut self, game: &mut Game) -> SearchResult {
+            let max_depth = self.limits.max_depth(MAX_DEPTH);
+            self.root_white = game.is_white_turn;
+            self.contempt_key = self.contempt_key();
+            self.keys = Self::game_keys(game);
+            self.root_keys = self.keys.len();
+            let mut result = SearchResult {
+                branches: vec![],
+                lines: 0,
+                pv: vec![],
+                depth: 0,
+                seldepth: 0,
+                leafs: 0,
+                nodes: 0,
+                qnodes: 0,
+                tbhits: 0,
+                ordering: OrderingStats::default(),
+                null_move_cutoffs: 0,
+                lmr_researches: 0,
+                pruning: PruningStats::default(),
+                aspiration_researches: 0,
+                extensions: ExtensionStats::default()
+            };
+            let mut order: Vec<Move> = vec![];
+
+            // Every other helper starts one ply deeper, so the threads aren't all on the same depth
+            let first_depth = 1 + self.thread % 2;
+            for depth in first_depth..=max_depth.max(first_depth) {
+                // The first iteration always completes so there is a move to play, helpers go on until stopped
+                if self.thread == 0 && depth > 1 && !self.time.can_start_iteration() {
+                    break
                 }
 
-                sorted_moves.sort();
+                self.depth = depth;
+                self.seldepth = 0;
+                let (branches, lines, leafs) = self.multi_pv(game, depth, &order, &result.branches);
+                if self.stopped {
+                    break
+                }
 
-                return (sorted_moves, total_leafs)
-            }else {
-                let mut min_eval = i32::MAX;
-                let mut total_leafs = 0;
-                for m in moves {
-                    game.make_move(&m);
-                    let (eval, leaves) = Self::alpha_beta_from_internet(game, true, alpha, beta, depth - 1);
-                    total_leafs += leaves;
-                    game.undo_move();
-
-                    sorted_moves.push(Branch {
-                        m: m,
-                        val: eval,
-                        leafs: leaves
-                    });
-                    
-                    min_eval = i32::min(eval, min_eval);
-                    beta = i32::min(beta, eval);
-                    if beta <= alpha {
-                        break
+                order = branches.iter().map(|branch| branch.m).collect();
+                let pv = branches.first().map(|branch| branch.pv.clone()).unwrap_or_default();
+                result = SearchResult {
+                    branches,
+                    lines,
+                    pv,
+                    depth,
+                    seldepth: self.seldepth,
+                    leafs: result.leafs + leafs,
+                    nodes: self.nodes,
+                    qnodes: self.qnodes,
+                    tbhits: self.tbhits,
+                    ordering: self.ordering.stats,
+                    null_move_cutoffs: self.null_move_cutoffs,
+                    lmr_researches: self.lmr_researches,
+                    pruning: self.pruning,
+                    aspiration_researches: self.aspiration_researches,
+                    extensions: self.extensions
+                };
+
+                if self.thread == 0 {
+                    for (line, branch) in result.branches[..result.lines].iter().enumerate() {
+                        self.control.report(&self.info(line + 1, Some(branch.val), branch.pv.clone(), None));
                     }
                 }
 
-                sorted_moves.sort();
-                sorted_moves.reverse();
+                // Mated or stalemated, there is nothing to search
+                let val = match result.branches.first() {
+                    Some(branch) => branch.val,
+                    None => break
+                };
+                if mate_moves(val).is_some_and(|moves| self.limits.mate_reached(moves)) {
+                    break
+                }
+
+                // An infinite search goes on until it is stopped, even with nothing left to find
+                if self.limits.infinite {
+                    continue
+                }
+
+                if order.len() <= 1 {
+                    break
+                }
 
-                return (sorted_moves, total_leafs)
+                // A mate found within the searched depth can't be beaten by searching deeper
+                if val >= MATE - depth as Score {
+                    break
+                }
             }
+
+            result
         }
+    }
 
-        fn check_if_over() {
+    impl Search<'_> {
+        // One iteration over the root. The best move is searched for an exact score, then the best of the moves
+        // left, until there are multi_pv lines. The other moves follow with the bounds of the last search.
+        // Returns the moves, how many of them are exact lines and the leaves.
+        fn multi_pv(&mut self, game: &mut Game, depth: usize, order: &[Move], previous: &[Branch]) -> (Vec<Branch>, usize, usize) {
+            // Helpers only fill the hash table, a single line does that best
+            let lines = if self.thread == 0 { self.options.multi_pv.max(1) } else { 1 };
+            let mut best: Vec<Branch> = vec![];
+            let mut rest = vec![];
+            let mut total_leafs = 0;
 
+            for line in 0..lines {
+                let excluded: Vec<Move> = best.iter().map(|branch| branch.m).collect();
+                let (mut branches, leafs) = self.aspiration(game, depth, order, &excluded, previous.get(line).map(|branch| branch.val));
+                total_leafs += leafs;
+                if self.stopped || branches.is_empty() {
+                    break
+                }
+                best.push(branches.remove(0));
+                rest = branches;
+            }
+
+            // A later line can come out a little better than an earlier one, the search isn't perfectly stable
+            best.sort();
+            let lines = best.len();
+            best.extend(rest);
+            (best, lines, total_leafs)
         }
 
+        // Searches the root in a narrow window around the previous score first, and again with the window widened
+        // on the side it failed until the score falls inside
+        fn aspiration(&mut self, game: &mut Game, depth: usize, order: &[Move], excluded: &[Move], previous: Option<Score>) -> (Vec<Branch>, usize) {
+            let mut delta = self.options.aspiration_window;
+            let (mut alpha, mut beta) = match previous {
+                Some(score) if delta > 0 && depth >= ASPIRATION_MIN_DEPTH && score.abs() < MATE_BOUND => {
+                    ((score - delta).max(-INFINITE), (score + delta).min(INFINITE))
+                }
+                _ => (-INFINITE, INFINITE)
+            };
+
+            let mut total_leafs = 0;
+            loop {
+                let (branches, leafs) = self.root(game, depth, order, excluded, alpha, beta);
+                total_leafs += leafs;
+                let best = match branches.first() {
+                    Some(branch) if !self.stopped => branch.val,
+                    _ => return (branches, total_leafs)
+                };
 
-        /*
-        if is_maximizing {
-                let shifted_position = 1_u64.overflowing_shl(game.board.black_king_board.leading_zeros()).0;
-                if attack_board_black & shifted_position > 1 {
-                    return (i32::MIN, 0)
+                delta *= 2;
+                if best <= alpha && alpha > -INFINITE {
+                    alpha = (best - delta).max(-INFINITE);
+                } else if best >= beta && beta < INFINITE {
+                    beta = (best + delta).min(INFINITE);
+                } else {
+                    return (branches, total_leafs)
                 }
-            } else {
-                let shifted_position = 1_u64.overflowing_shl(game.board.black_king_board.leading_zeros()).0;
-                if attack_board_white & shifted_position > 1 {
-                    return (i32::MAX, 0)
+                self.aspiration_researches += 1;
+            }
+        }
+
+        // Every root move but the excluded ones with its score and line, the moves in order are searched first. The
+        // first move gets the full window, the others a null window that is only widened when they beat the best so far.
+        fn root(&mut self, game: &mut Game, depth: usize, order: &[Move], excluded: &[Move], mut alpha: Score, beta: Score) -> (Vec<Branch>, usize) {
+            let mut sorted_moves: Vec<Branch> = vec![];
+            let can_stop = depth > 1 || self.thread > 0;
+
+            let mut moves = game.legal_moves();
+            moves.retain(|m| !excluded.contains(m));
+
+            // With the position in the tablebases only the moves that keep the best result are searched
+            if let Some(best_moves) = self.tablebase.and_then(|tb| tb.best_moves(game)) {
+                moves.retain(|m| best_moves.iter().any(|root_move| root_move.m == *m));
+            }
+
+            // Solved endgames keep only the moves that mate the fastest or hold out the longest
+            if let Some(root_moves) = self.endgames.and_then(|tables| tables.root_moves(game)) {
+                let best = root_moves[0].1;
+                moves.retain(|m| root_moves.iter().any(|(root_move, outcome)| root_move == m && *outcome == best));
+            }
+
+            moves.sort_by(Engine::ordering_moves);
+            // The previous iteration's ranking goes first, the sort is stable for the rest
+            moves.sort_by_key(|m| order.iter().position(|o| o == m).unwrap_or(order.len()));
+
+            let mut total_leafs = 0;
+            self.keys.push(polyglot_key(game));
+            for (i, m) in moves.into_iter().enumerate() {
+                self.current_move = Some((m, i + 1));
+                game.make_move(&m);
+                let (mut eval, mut leaves) = if i == 0 {
+                    self.negamax(game, -beta, -alpha, depth - 1, 1, can_stop)
+                } else {
+                    self.negamax(game, -alpha - 1, -alpha, depth - 1, 1, can_stop)
+                };
+                if i > 0 && -eval > alpha && -eval < beta && !self.stopped {
+                    let (full_eval, full_leaves) = self.negamax(game, -beta, -alpha, depth - 1, 1, can_stop);
+                    eval = full_eval;
+                    leaves += full_leaves;
+                }
+                // Behind a mate the null window only says a move mates no faster, searched again below the mates
+                // a move that doesn't mate gets an ordinary bound
+                if i > 0 && -eval >= MATE_BOUND && -eval <= alpha && !self.stopped {
+                    let (below_eval, below_leaves) = self.negamax(game, -alpha, -MATE_BOUND + 1, depth - 1, 1, can_stop);
+                    eval = below_eval;
+                    leaves += below_leaves;
+                }
+                total_leafs += leaves;
+                game.undo_move();
+
+                if self.stopped {
+                    break
+                }
+
+                let eval = -eval;
+                let mut pv = vec![m];
+                pv.extend_from_slice(self.pv.line(1));
+                sorted_moves.push(Branch {
+                    m,
+                    val: eval,
+                    leafs: leaves,
+                    pv
+                });
+
+                // Every root move gets a score, so the next iteration knows how many moves there are
+                alpha = alpha.max(eval);
+                // Failed high, the window is widened and the root searched again
+                if alpha >= beta {
+                    break
                 }
             }
-         */
+            self.keys.pop();
+
+            sorted_moves.sort();
 
-        pub fn alpha_beta_from_internet(
+            (sorted_moves, total_leafs)
+        }
+
+        // Scores the position for the side to move, the best line from here is left in the PV table at ply
+        fn negamax(
+            &mut self,
             game: &mut Game,
-            is_maximizing: bool,
-            mut alpha: i32,
-            mut beta: i32,
-            depth: usize
-        ) -> (i32, usize) {
+            mut alpha: Score,
+            mut beta: Score,
+            depth: usize,
+            ply: usize,
+            can_stop: bool
+        ) -> (Score, usize) {
+
+            self.nodes += 1;
+            self.seldepth = self.seldepth.max(ply);
+            self.pv.clear(ply);
+            if self.check_stop(can_stop) {
+                return (0, 0)
+            }
+
+            let key = polyglot_key(game);
+            if game.halfmove_clock >= 100 || self.is_repetition(game, key) {
+                return (self.draw_score(game), 1)
+            }
+
+            // Extensions can take a line this deep, there is no room in the tables for another ply
+            if ply >= MAX_PLY - 1 {
+                return (Self::static_eval(game), 1)
+            }
+
+            // Mate distance pruning: a mate further away than one already found can't raise the score
+            alpha = alpha.max(mated_in(ply));
+            beta = beta.min(mate_in(ply + 1));
+            if alpha >= beta {
+                return (alpha, 1)
+            }
+
+            // Solved endgames are exact with the distance to mate
+            if let Some(outcome) = self.endgames.and_then(|tables| tables.probe(game)) {
+                self.tbhits += 1;
+                let score = if outcome == Outcome::Draw { self.draw_score(game) } else { Self::outcome_score(outcome, ply) };
+                return (score, 1)
+            }
+
+            // Right after a capture or pawn move the tablebase result is exact, the 50 move count starts over
+            if let Some(tb) = self.tablebase {
+                if game.halfmove_clock == 0 {
+                    if let Some(wdl) = tb.probe_wdl(game) {
+                        self.tbhits += 1;
+                        let score = if wdl == Wdl::Draw { self.draw_score(game) } else { wdl.score() };
+                        return (score, 1)
+                    }
+                }
+            }
 
             if depth == 0 {
-                return (game.evaluate_board(), 1)
+                return (self.quiescence(game, alpha, beta, ply, can_stop), 1)
             }
 
-            let (mut moves, attack_board_white, attack_board_black) = game.get_all_moves();
+            // Only nodes searched with an open window can be on the principal variation, the rest have to prove a bound
+            let pv_node = beta - alpha > 1;
+
+            // A singular extension search leaves out one move, the table's result for the whole position doesn't apply
+            let excluded = self.excluded[ply];
+            let full_node = excluded == Move::None;
 
-            moves.sort_by(Self::ordering_moves);
+            let hit = self.tt.probe(key ^ self.contempt_key, ply);
+            if let Some(hit) = hit {
+                // The principal variation is searched out instead of cut short by the table
+                if !pv_node && full_node && hit.cutoff(depth, alpha, beta) {
+                    return (hit.score, 1)
+                }
+            }
+            let alpha_start = alpha;
+            let in_check = game.is_in_check();
+            let eval = Self::static_eval(game);
+            // Forward pruning trusts the static evaluation, which says nothing in check or about mates
+            let can_prune = !pv_node && !in_check && full_node && alpha.abs() < MATE_BOUND && beta.abs() < MATE_BOUND;
 
-            if is_maximizing {
-                let mut max_eval = i32::MIN;
-                let mut total_leafs = 0;
-                for m in moves {
-                    game.make_move(&m);
-                    let (eval, leaves) = Self::alpha_beta_from_internet(game, false, alpha, beta, depth - 1);
+            // Reverse futility pruning: far enough above beta the opponent won't get back in a few plies
+            if self.options.reverse_futility
+                && can_prune
+                && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && eval - self.options.reverse_futility_margin * depth as Score >= beta
+            {
+                self.pruning.reverse_futility[depth] += 1;
+                return (eval, 1)
+            }
+
+            // Razoring: far below alpha only captures can help, quiescence decides
+            if self.options.razoring
+                && can_prune
+                && depth <= RAZORING_MAX_DEPTH
+                && eval + self.options.razoring_margin * (depth as Score) < alpha
+            {
+                let score = self.quiescence(game, alpha, beta, ply, can_stop);
+                if score < alpha {
+                    self.pruning.razoring[depth] += 1;
+                    return (score, 1)
+                }
+            }
+
+            // Null move pruning: if passing still holds beta, a real move would too. Not in check, not twice in a
+            // row and not with only king and pawns, where passing could be better than any move (zugzwang).
+            if self.options.null_move
+                && !pv_node
+                && full_node
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && !in_check
+                && !matches!(game.move_log.last(), Some(Move::None))
+                && beta.abs() < MATE_BOUND
+                && Self::has_pieces(game)
+                && eval >= beta
+            {
+                let reduction = 2 + depth / 4;
+                self.keys.push(key);
+                game.make_null_move();
+                let (score, leaves) = self.negamax(game, -beta, -beta + 1, (depth - 1).saturating_sub(reduction), ply + 1, can_stop);
+                game.undo_null_move();
+                self.keys.pop();
+                if self.stopped {
+                    return (0, leaves)
+                }
+                // A mate after passing isn't proven, beta is
+                if -score >= beta {
+                    self.null_move_cutoffs += 1;
+                    return (beta, leaves)
+                }
+            }
+
+            let mut moves = game.legal_moves();
+            if moves.is_empty() {
+                let score = if in_check { mated_in(ply) } else { self.draw_score(game) };
+                return (score, 1)
+            }
+
+            let tt_move = hit.and_then(|hit| hit.best_move);
+            let previous = game.move_log.last().copied().unwrap_or(Move::None);
+            self.ordering.sort(game, &mut moves, ply, tt_move, &previous);
+
+            let mut total_leafs = 0;
+            let one_reply = moves.len() == 1 && full_node;
+            if !full_node {
+                moves.retain(|m| *m != excluded);
+                // The left out move was the only one, nothing else comes close
+                if moves.is_empty() {
+                    return (alpha, 1)
+                }
+            }
+
+            // Singular extension: the hash move is searched deeper when every other move falls well short of its
+            // score in a search at half the depth
+            let mut singular = Move::None;
+            if let Some(hit) = hit.filter(|hit| {
+                self.options.singular_extension
+                    && full_node
+                    && depth >= SINGULAR_MIN_DEPTH
+                    && hit.bound != Bound::Upper
+                    && hit.depth + SINGULAR_TT_DEPTH_MARGIN >= depth
+                    && hit.score.abs() < MATE_BOUND
+            }) {
+                if let Some(&tt_move) = moves.first().filter(|m| hit.best_move == Some(encode_move(m))) {
+                    let singular_beta = hit.score - self.options.singular_margin * depth as Score;
+                    self.extensions.singular_searches += 1;
+                    self.excluded[ply] = tt_move;
+                    let (score, leaves) = self.negamax(game, singular_beta - 1, singular_beta, (depth - 1) / 2, ply, can_stop);
+                    self.excluded[ply] = Move::None;
+                    self.pv.clear(ply);
                     total_leafs += leaves;
-                    game.undo_move();
-                    max_eval = i32::max(eval, max_eval);
-                    alpha = i32::max(alpha, eval);
-                    if beta <= alpha {
-                        break
+                    if self.stopped {
+                        return (0, total_leafs)
+                    }
+                    if score < singular_beta {
+                        singular = tt_move;
                     }
                 }
+            }
 
-                return (max_eval, total_leafs)
-            }else {
-                let mut min_eval = i32::MAX;
-                let mut total_leafs = 0;
-                for m in moves {
-                    game.make_move(&m);
-                    let (eval, leaves) = Self::alpha_beta_from_internet(game, true, alpha, beta, depth - 1);
-                    total_leafs += leaves;
-                    game.undo_move();
-                    min_eval = i32::min(eval, min_eval);
-                    beta = i32::min(beta, eval);
-                    if beta <= alpha {
-                        break
+            self.keys.push(key);
+            let mut best_move = None;
+            let mut best_eval = -INFINITE;
+            let mut quiets: Vec<Move> = vec![];
+            for (i, m) in moves.into_iter().enumerate() {
+                game.make_move(&m);
+                let gives_check = game.is_in_check();
+                let quiet = is_quiet(&m) && !gives_check;
+
+                // Near the leaves quiet moves that can't reach alpha, or come after enough others, aren't searched
+                if quiet && can_prune && i > 0 {
+                    if self.options.futility
+                        && depth <= FUTILITY_MAX_DEPTH
+                        && eval + self.options.futility_margin * (depth as Score) <= alpha
+                    {
+                        self.pruning.futility[depth] += 1;
+                        game.undo_move();
+                        continue
                     }
+                    if self.options.late_move_pruning
+                        && depth <= LATE_MOVE_PRUNING_MAX_DEPTH
+                        && quiets.len() >= self.options.lmp_base + depth * depth
+                    {
+                        self.pruning.late_move[depth] += 1;
+                        game.undo_move();
+                        continue
+                    }
+                }
+
+                let extension = self.extension(&m, singular, gives_check, one_reply, ply);
+                let new_depth = depth - 1 + extension.is_some() as usize;
+
+                // Late move reductions: quiet moves ordered late are searched shallower first, and again at full
+                // depth only if they turn out better than alpha
+                let mut reduction = 0;
+                if self.options.late_move_reductions
+                    && depth >= LMR_MIN_DEPTH
+                    && i >= LMR_FULL_DEPTH_MOVES
+                    && quiet
+                    && !in_check
+                {
+                    reduction = self.reductions[depth.min(MAX_DEPTH)][i.min(63)].min(depth - 2);
                 }
 
-                return (min_eval, total_leafs)
+                // Principal variation search: the first move gets the full window, the others a null window proving
+                // they are no better, searched again with the full window when they are
+                let (mut eval, mut leaves) = if i == 0 {
+                    self.negamax(game, -beta, -alpha, new_depth, ply + 1, can_stop)
+                } else {
+                    self.negamax(game, -alpha - 1, -alpha, new_depth - reduction, ply + 1, can_stop)
+                };
+                if reduction > 0 && -eval > alpha && !self.stopped {
+                    self.lmr_researches += 1;
+                    let (full_eval, full_leaves) = self.negamax(game, -alpha - 1, -alpha, new_depth, ply + 1, can_stop);
+                    eval = full_eval;
+                    leaves += full_leaves;
+                }
+                if i > 0 && -eval > alpha && -eval < beta && !self.stopped {
+                    let (full_eval, full_leaves) = self.negamax(game, -beta, -alpha, new_depth, ply + 1, can_stop);
+                    eval = full_eval;
+                    leaves += full_leaves;
+                }
+                total_leafs += leaves;
+                game.undo_move();
+                if let Some(extension) = extension {
+                    self.line_extensions[extension as usize] -= 1;
+                }
+
+                if self.stopped {
+                    self.keys.pop();
+                    return (0, total_leafs)
+                }
+
+                let eval = -eval;
+                if eval > best_eval || best_move.is_none() {
+                    best_eval = eval;
+                    best_move = Some(m);
+                }
+                if eval > alpha {
+                    alpha = eval;
+                    self.pv.update(ply, m);
+                }
+                if alpha >= beta {
+                    let kind = self.ordering.kind(&m, ply, tt_move, &previous);
+                    self.ordering.stats.record(kind, i);
+                    self.ordering.cutoff(&m, ply, depth, &previous, &quiets);
+                    break
+                }
+                if is_quiet(&m) {
+                    quiets.push(m);
+                }
             }
+            self.keys.pop();
+
+            let bound = if best_eval >= beta {
+                Bound::Lower
+            } else if best_eval <= alpha_start {
+                Bound::Upper
+            } else {
+                Bound::Exact
+            };
+            // After a fail low no move is known to be best
+            let best_move = if bound == Bound::Upper { None } else { best_move.as_ref() };
+            if full_node {
+                self.tt.store(key ^ self.contempt_key, depth, bound, best_eval, best_move, ply);
+            }
+
+            (best_eval, total_leafs)
         }
 
-        pub fn ordering_moves(a: &Move, b: &Move) -> Ordering {
-            match a {
-                Move::Standard(from, to, piece, color) => match b {
-                    Move::None => Ordering::Less,
-                    Move::Standard(_, _, _, _) => Ordering::Equal,
-                    Move::Capture(_, _, _, _, _) => Ordering::Greater,
-                    Move::Promotion(_, _, _, _, _) => Ordering::Greater,
-                    Move::Castle(_, _, _) => Ordering::Greater
-                }
-                Move::Capture(from, to, p, cp, color) => {
-                    match b {
-                        Move::Standard(_, _, _, _) => Ordering::Less,
-                        Move::Capture(_, _, p_, cp_, color_) => {
-                            let first_diff = p - cp;
-                            let second_diff = p_ - cp_;
+        // The extension m gets, if one applies and the line still has room for it
+        fn extension(&mut self, m: &Move, singular: Move, gives_check: bool, one_reply: bool, ply: usize) -> Option<Extension> {
+            let extension = if singular == *m {
+                Extension::Singular
+            } else if one_reply && self.options.one_reply_extension {
+                Extension::OneReply
+            } else if gives_check && self.options.check_extension {
+                Extension::Check
+            } else {
+                return None
+            };
 
-                            if first_diff > second_diff {
-                                return Ordering::Less
-                            } else if first_diff == second_diff {
-                                return Ordering::Equal
-                            } else {
-                                return Ordering::Greater
-                            }
-                        }
-                        Move::Promotion(_, _, _, _, _) => Ordering::Greater,
-                        _ => panic!("Move that cant be sorted with {:?} {:?}", a, b)
+            if ply >= 2 * self.depth || self.line_extensions[extension as usize] >= self.options.max_extensions(extension) {
+                self.extensions.limited += 1;
+                return None
+            }
+            self.line_extensions[extension as usize] += 1;
+            self.extensions.record(extension);
+            let most = &mut self.extensions.most_on_a_line[extension as usize];
+            *most = (*most).max(self.line_extensions[extension as usize]);
+            Some(extension)
+        }
+
+        // Static evaluation for the side to move
+        fn static_eval(game: &Game) -> Score {
+            let eval = game.evaluate_board();
+            if game.is_white_turn { eval } else { -eval }
+        }
+
+        // Whether the side to move has more than king and pawns
+        fn has_pieces(game: &Game) -> bool {
+            let board = &game.board;
+            let pieces = if game.is_white_turn {
+                board.white_knight_board | board.white_bishop_board | board.white_rook_board | board.white_queen_board
+            } else {
+                board.black_knight_board | board.black_bishop_board | board.black_rook_board | board.black_queen_board
+            };
+            pieces > 0
+        }
+
+        // A draw for the side to move, contempt from the root side's view. It is a point either way of that depending
+        // on the node count, so a draw is never quite the same as another and a repetition doesn't hide a better line.
+        // Bit 1 of the count flips every other node: (nodes & 2) - 1 is -1 or +1.
+        fn draw_score(&self, game: &Game) -> Score {
+            let contempt = self.options.effective_contempt();
+            let contempt = if game.is_white_turn == self.root_white { -contempt } else { contempt };
+            contempt + (self.nodes & 2) as Score - 1
+        }
+
+        // Scores with contempt only hold for the side the search started from and for that contempt, they are kept
+        // apart in the hash table from other searches'. Without contempt the keys are the positions' own.
+        fn contempt_key(&self) -> u64 {
+            let contempt = self.options.effective_contempt();
+            if contempt == 0 {
+                return 0
+            }
+            let side = if self.root_white { 0 } else { 0xD1B5_4A32_D192_ED03 };
+            (contempt as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ side
+        }
+
+        // Whether the position repeats one since the last capture or pawn move. Inside the search one repetition
+        // counts as a draw, a position from the game before the root has to have been there twice already.
+        fn is_repetition(&self, game: &Game, key: u64) -> bool {
+            let moves = game.move_log.len();
+            let mut repetitions = 0;
+            for back in 1..=(game.halfmove_clock as usize).min(moves) {
+                let i = moves - back;
+                // Positions before a null move aren't reached by moves
+                if game.move_log[i] == Move::None {
+                    return false
+                }
+                if back % 2 == 0 && self.keys[i] == key {
+                    if i >= self.root_keys {
+                        return true
+                    }
+                    repetitions += 1;
+                    if repetitions == 2 {
+                        return true
                     }
                 }
-                Move::Promotion(from, to, promotion_piece, cp, color) => {
-                    match b {
-                        Move::Standard(_, _, _, _) => Ordering::Less,
-                        Move::Capture(_, _, _, _, _) => Ordering::Less,
-                        Move::Promotion(from, to, promotion_piece_, cp_, color_) => {
-                            if promotion_piece > promotion_piece_ {
-                                return Ordering::Less
-                            } else if promotion_piece < promotion_piece_ {
-                                return Ordering::Greater
-                            } else {
-                                return Ordering::Equal
-                            }
+            }
+            false
+        }
+
+        // Keys of the positions the game went through, before each move of its move log. Only positions since the
+        // last capture or pawn move can repeat, the ones before are left at 0.
+        fn game_keys(game: &mut Game) -> Vec<u64> {
+            let moves = game.move_log.len();
+            let reversible = (game.halfmove_clock as usize).min(moves);
+            let mut keys = vec![0; moves];
+            let mut undone = vec![];
+            for i in (moves - reversible..moves).rev() {
+                let m = game.move_log[i];
+                game.undo_move();
+                keys[i] = polyglot_key(game);
+                undone.push(m);
+            }
+            for m in undone.iter().rev() {
+                game.make_move(m);
+            }
+            keys
+        }
+
+        // Distance to mate from the root for a solved endgame position at ply
+        fn outcome_score(outcome: Outcome, ply: usize) -> Score {
+            match outcome {
+                Outcome::Win(plies) => mate_in(ply + plies as usize),
+                Outcome::Draw => DRAW,
+                Outcome::Loss(plies) => mated_in(ply + plies as usize),
+            }
+        }
+    }
+
+    impl Search<'_> {
+        // The clock is looked at every few thousand nodes, a stopped search unwinds without a result
+        fn check_stop(&mut self, can_stop: bool) -> bool {
+            if !can_stop || self.stopped {
+                return self.stopped
+            }
+
+            // The node limit is exact on one thread so a node limited search always ends in the same place. The
+            // helpers' nodes are added as they report them.
+            let nodes = self.nodes + self.qnodes;
+            if self.thread == 0 && self.limits.nodes_reached(nodes + self.helper_nodes.load(AtomicOrdering::Relaxed)) {
+                self.stopped = true;
+            } else if nodes.is_multiple_of(2048) {
+                let finished = self.finished.load(AtomicOrdering::Relaxed);
+                self.stopped = self.control.stop.is_stopped() || finished || (self.thread == 0 && self.time.should_stop());
+                self.report_progress();
+            }
+            self.stopped
+        }
+
+        // Helpers count their nodes in with the others, the main thread says which root move it is on now and then
+        fn report_progress(&mut self) {
+            if self.thread > 0 {
+                let nodes = self.nodes + self.qnodes;
+                self.helper_nodes.fetch_add(nodes - self.reported_nodes, AtomicOrdering::Relaxed);
+                self.reported_nodes = nodes;
+            } else if self.control.wants_info() && self.time.elapsed() >= self.last_info + INFO_INTERVAL {
+                self.control.report(&self.info(1, None, vec![], self.current_move));
+            }
+        }
+
+        fn info(&mut self, multipv: usize, score: Option<Score>, pv: Vec<Move>, currmove: Option<(Move, usize)>) -> SearchInfo {
+            let time = self.time.elapsed();
+            self.last_info = time;
+            let nodes = self.nodes + self.qnodes + self.helper_nodes.load(AtomicOrdering::Relaxed);
+            SearchInfo {
+                depth: self.depth,
+                seldepth: self.seldepth,
+                multipv,
+                score,
+                nodes,
+                nps: (nodes as f64 / time.as_secs_f64().max(0.001)) as u64,
+                time,
+                hashfull: self.tt.hashfull(),
+                tbhits: self.tbhits,
+                pv,
+                currmove,
+            }
+        }
+
+        // Captures and promotions until the position is quiet, scored for the side to move.
+        // The side to move may stand pat on the static evaluation unless it is in check, then every move is tried.
+        fn quiescence(&mut self, game: &mut Game, mut alpha: Score, beta: Score, ply: usize, can_stop: bool) -> Score {
+            self.qnodes += 1;
+            self.seldepth = self.seldepth.max(ply);
+            if self.check_stop(can_stop) {
+                return 0
+            }
+
+            let stand_pat = Self::static_eval(game);
+            if ply >= MAX_PLY {
+                return stand_pat
+            }
+
+            let in_check = game.is_in_check();
+            let mut moves;
+            let mut best = -INFINITE;
+            if in_check {
+                moves = game.legal_moves();
+                if moves.is_empty() {
+                    return mated_in(ply)
+                }
+            } else {
+                if stand_pat >= beta {
+                    return stand_pat
+                }
+                alpha = alpha.max(stand_pat);
+                best = stand_pat;
+                (moves, _, _) = game.get_all_moves();
+                moves.retain(|m| matches!(m, Move::Capture(..) | Move::Promotion(..)));
+            }
+
+            moves.sort_by_key(|m| -mvv_lva(m));
+
+            for m in moves {
+                // Delta pruning: not even the captured piece and a margin would lift the score to alpha
+                if !in_check {
+                    if let Move::Capture(_, _, _, captured, _) = m {
+                        if stand_pat + Game::piece_value(captured) + DELTA_MARGIN < alpha {
+                            continue
                         }
-                        _ => panic!("Move that cant be sorted with {:?} {:?}", a, b)
                     }
+                    // Captures that lose material in the exchange are left out
+                    if !game.board.see_ge(&m, 0, &game.move_gen) || !game.leaves_king_safe(&m) {
+                        continue
+                    }
+                }
+
+                game.make_move(&m);
+                let score = -self.quiescence(game, -beta, -alpha, ply + 1, can_stop);
+                game.undo_move();
+
+                if self.stopped {
+                    return 0
+                }
+                if score > best {
+                    best = score;
+                }
+                if score > alpha {
+                    alpha = score;
+                }
+                if alpha >= beta {
+                    break
                 }
-                _ => panic!("Move that cant be sorted with {:?} {:?}", a, b)
             }
+
+            best
+        }
+    }
+
+    impl Engine {
+        // Promotions and captures first by MVV-LVA, then the quiet moves, Move::None last
+        pub(crate) fn ordering_moves(a: &Move, b: &Move) -> Ordering {
+            let key = |m: &Move| match m {
+                Move::None => i32::MIN,
+                _ => mvv_lva(m),
+            };
+            key(b).cmp(&key(a))
         }
     }
-}
\ No newline at end of file
+}
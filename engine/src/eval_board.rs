@@ -5,6 +5,7 @@ mod eval_board {
     use crate::game::game::Game;
     use crate::move_gen::move_gen::PieceType;
     use crate::move_gen::move_gen::PieceType::{BISHOP, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::score::score::MATE;
     use crate::utils::utils;
 
     impl Game {
@@ -81,6 +82,18 @@ mod eval_board {
         const BISHOP_VALUE: i32 = 825;
         const KNIGHT_VALUE: i32 = 781;
 
+        // Material value of a piece on the evaluation's scale, the king has none
+        pub fn piece_value(piece: PieceType) -> i32 {
+            match piece {
+                PAWN => Self::PAWN_VALUE,
+                KNIGHT => Self::KNIGHT_VALUE,
+                BISHOP => Self::BISHOP_VALUE,
+                ROOK => Self::ROOK_VALUE,
+                QUEEN => Self::QUEEN_VALUE,
+                _ => 0
+            }
+        }
+
         pub fn count_pieces_white(&self) -> i32 {
             let mut sum: i32 = 0;
 
@@ -110,11 +123,11 @@ mod eval_board {
         pub fn evaluate_board(&self) -> i32 {
             
             if self.board.white_king_board == 0 {
-                return i32::MIN
+                return -MATE
             }
             
             if self.board.black_king_board == 0 {
-                return i32::MAX
+                return MATE
             }
             
             
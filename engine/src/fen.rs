@@ -0,0 +1,283 @@
+pub mod fen {
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::Formatter;
+    use std::str::FromStr;
+    use crate::board::board::Board;
+    use crate::game::game::{CastlingRights, Game};
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::utils::utils;
+
+    pub const STARTING_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FenError {
+        Empty,
+        WrongRankCount(usize),
+        WrongRankLength(usize),
+        InvalidPiece(char),
+        InvalidSideToMove(String),
+        InvalidCastling(String),
+        InvalidEnPassant(String),
+        InvalidClock(String),
+        TooManyFields(usize),
+    }
+
+    impl fmt::Display for FenError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                FenError::Empty => write!(f, "empty FEN"),
+                FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+                FenError::WrongRankLength(rank) => write!(f, "rank {} does not describe 8 squares", rank),
+                FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+                FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+                FenError::InvalidCastling(s) => write!(f, "invalid castling field '{}'", s),
+                FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+                FenError::InvalidClock(s) => write!(f, "invalid move clock '{}'", s),
+                FenError::TooManyFields(n) => write!(f, "expected at most 6 fields, found {}", n),
+            }
+        }
+    }
+
+    impl Error for FenError {}
+
+    fn piece_from_char(c: char) -> Option<(PieceType, bool)> {
+        let piece = match c.to_ascii_lowercase() {
+            'p' => PAWN,
+            'n' => KNIGHT,
+            'b' => BISHOP,
+            'r' => ROOK,
+            'q' => QUEEN,
+            'k' => KING,
+            _ => return None
+        };
+
+        Some((piece, c.is_ascii_uppercase()))
+    }
+
+    fn parse_placement(placement: &str) -> Result<Board, FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()))
+        }
+
+        let mut board = Board::empty();
+
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut file = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    if skip == 0 || skip > 8 {
+                        return Err(FenError::InvalidPiece(c))
+                    }
+                    file += skip as usize;
+                    continue;
+                }
+
+                if file > 7 {
+                    return Err(FenError::WrongRankLength(8 - row))
+                }
+
+                let (piece, is_white) = piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                let pos = (row * 8 + file) as u8;
+                *board.get_board(piece, is_white) |= utils::POSITIONS[pos as usize];
+
+                if piece == KING {
+                    if is_white { board.white_king_position = pos } else { board.black_king_position = pos }
+                }
+
+                file += 1;
+            }
+
+            if file != 8 {
+                return Err(FenError::WrongRankLength(8 - row))
+            }
+        }
+
+        Ok(board)
+    }
+
+    fn parse_castling(field: &str) -> Result<CastlingRights, FenError> {
+        let mut rights = CastlingRights::none();
+        if field == "-" {
+            return Ok(rights)
+        }
+
+        for c in field.chars() {
+            match c {
+                'K' => rights.white_right = true,
+                'Q' => rights.white_left = true,
+                'k' => rights.black_right = true,
+                'q' => rights.black_left = true,
+                _ => return Err(FenError::InvalidCastling(field.to_string()))
+            }
+        }
+
+        Ok(rights)
+    }
+
+    fn parse_en_passant(field: &str) -> Result<Option<u8>, FenError> {
+        if field == "-" {
+            return Ok(None)
+        }
+
+        match utils::parse_square(field) {
+            // Only the third and sixth rank can hold an en passant target
+            Some(pos) if (16..24).contains(&pos) || (40..48).contains(&pos) => Ok(Some(pos)),
+            _ => Err(FenError::InvalidEnPassant(field.to_string()))
+        }
+    }
+
+    fn parse_clock(field: &str) -> Result<u32, FenError> {
+        field.parse::<u32>().map_err(|_| FenError::InvalidClock(field.to_string()))
+    }
+
+    // Accepts a full FEN or just its piece placement field
+    impl FromStr for Board {
+        type Err = FenError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let placement = s.split_whitespace().next().ok_or(FenError::Empty)?;
+            parse_placement(placement)
+        }
+    }
+
+    // Everything a FEN describes, before it is loaded into a game
+    struct FenFields {
+        board: Board,
+        is_white_turn: bool,
+        castling_rights: CastlingRights,
+        en_passant: Option<u8>,
+        halfmove_clock: u32,
+        fullmove_number: u32,
+    }
+
+    // Missing trailing fields default to "w - - 0 1"
+    fn parse_fields(s: &str) -> Result<FenFields, FenError> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.is_empty() {
+            return Err(FenError::Empty)
+        }
+        if fields.len() > 6 {
+            return Err(FenError::TooManyFields(fields.len()))
+        }
+
+        let board = parse_placement(fields[0])?;
+
+        let is_white_turn = match fields.get(1).copied().unwrap_or("w") {
+            "w" => true,
+            "b" => false,
+            other => return Err(FenError::InvalidSideToMove(other.to_string()))
+        };
+
+        Ok(FenFields {
+            board,
+            is_white_turn,
+            castling_rights: parse_castling(fields.get(2).copied().unwrap_or("-"))?,
+            en_passant: parse_en_passant(fields.get(3).copied().unwrap_or("-"))?,
+            halfmove_clock: parse_clock(fields.get(4).copied().unwrap_or("0"))?,
+            fullmove_number: parse_clock(fields.get(5).copied().unwrap_or("1"))?.max(1),
+        })
+    }
+
+    impl FromStr for Game {
+        type Err = FenError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let fields = parse_fields(s)?;
+            let mut game = Game::new(fields.is_white_turn);
+            game.load_fields(fields);
+            Ok(game)
+        }
+    }
+
+    impl Board {
+        pub fn to_fen_placement(&self) -> String {
+            let mut placement = String::with_capacity(72);
+
+            for row in 0..8 {
+                let mut empty = 0;
+                for file in 0..8 {
+                    match self.piece_at(row * 8 + file) {
+                        Some((piece, is_white)) => {
+                            if empty > 0 {
+                                placement.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            let letter = piece.to_string().chars().next().unwrap();
+                            placement.push(if is_white { letter } else { letter.to_ascii_lowercase() });
+                        }
+                        None => empty += 1
+                    }
+                }
+
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                }
+                if row < 7 {
+                    placement.push('/');
+                }
+            }
+
+            placement
+        }
+    }
+
+    impl Game {
+        pub fn from_fen(fen: &str) -> Result<Game, FenError> {
+            fen.parse()
+        }
+
+        // Loads a position into an existing game, keeping its move generator tables.
+        // The game is left untouched if the FEN does not parse.
+        pub fn set_fen(&mut self, fen: &str) -> Result<(), FenError> {
+            let fields = parse_fields(fen)?;
+            self.load_fields(fields);
+            self.move_log.clear();
+            self.state_log.clear();
+            Ok(())
+        }
+
+        fn load_fields(&mut self, fields: FenFields) {
+            self.board = fields.board;
+            self.is_white_turn = fields.is_white_turn;
+            self.castling_rights = fields.castling_rights;
+            self.en_passant = fields.en_passant;
+            self.halfmove_clock = fields.halfmove_clock;
+            self.fullmove_number = fields.fullmove_number;
+        }
+
+        fn castling_field(&self) -> String {
+            let rights = self.effective_castling_rights();
+            let mut field = String::new();
+
+            for (right, c) in [(rights.white_right, 'K'), (rights.white_left, 'Q'), (rights.black_right, 'k'), (rights.black_left, 'q')] {
+                if right {
+                    field.push(c);
+                }
+            }
+
+            if field.is_empty() {
+                field.push('-');
+            }
+
+            field
+        }
+
+        pub fn to_fen(&self) -> String {
+            format!(
+                "{} {} {} {} {} {}",
+                self.board.to_fen_placement(),
+                if self.is_white_turn { "w" } else { "b" },
+                self.castling_field(),
+                match self.en_passant {
+                    Some(pos) => utils::square_name(pos as usize),
+                    None => "-".to_string()
+                },
+                self.halfmove_clock,
+                self.fullmove_number
+            )
+        }
+    }
+}
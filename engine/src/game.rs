@@ -1,9 +1,11 @@
 pub mod game {
     use std::cmp::PartialEq;
+    use std::fmt;
+    use std::fmt::Formatter;
     use std::ptr::copy;
     use Move::{Capture, Promotion, Standard};
     use PieceType::{BISHOP, KNIGHT, PAWN, QUEEN};
-    use crate::board::board::{Board, BoardMove, Move};
+    use crate::board::board::{Board, BoardDiagram, BoardMove, DiagramOptions, Move};
     use crate::board::board::Move::Castle;
     use crate::board::board::Side::{Left, Right};
     use crate::debug::debug::print_board_from_board;
@@ -12,14 +14,72 @@ pub mod game {
     use crate::move_list::move_list::{AttackMoveList, MoveList};
     use crate::utils::utils;
 
-    #[derive(Clone)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CastlingRights {
+        pub white_left: bool,
+        pub white_right: bool,
+        pub black_left: bool,
+        pub black_right: bool,
+    }
+
+    impl CastlingRights {
+        pub fn all() -> CastlingRights {
+            CastlingRights { white_left: true, white_right: true, black_left: true, black_right: true }
+        }
+
+        pub fn none() -> CastlingRights {
+            CastlingRights { white_left: false, white_right: false, black_left: false, black_right: false }
+        }
+
+        pub fn any(&self) -> bool {
+            self.white_left || self.white_right || self.black_left || self.black_right
+        }
+    }
+
+    // The parts of a position that can't be recovered from the move alone when undoing it
+    #[derive(Debug, Clone, Copy)]
+    pub struct IrreversibleState {
+        pub castling_rights: CastlingRights,
+        pub en_passant: Option<u8>,
+        pub halfmove_clock: u32,
+    }
+
     pub struct Game {
         pub board: Board,
         pub is_white_turn: bool,
         pub move_gen: MoveGen,
         pub white_attack_boards: Vec<BoardMove>,
         pub black_attack_boards: Vec<BoardMove>,
-        pub move_log: Vec<Move>
+        pub move_log: Vec<Move>,
+        pub castling_rights: CastlingRights,
+        pub en_passant: Option<u8>,
+        pub halfmove_clock: u32,
+        pub fullmove_number: u32,
+        pub state_log: Vec<IrreversibleState>
+    }
+
+    // Move generation insists on attack board vectors of capacity 16, which a derived clone wouldn't keep
+    impl Clone for Game {
+        fn clone(&self) -> Game {
+            let mut white_attack_boards = Vec::with_capacity(16);
+            white_attack_boards.extend_from_slice(&self.white_attack_boards);
+            let mut black_attack_boards = Vec::with_capacity(16);
+            black_attack_boards.extend_from_slice(&self.black_attack_boards);
+
+            Game {
+                board: self.board.clone(),
+                is_white_turn: self.is_white_turn,
+                move_gen: self.move_gen.clone(),
+                white_attack_boards,
+                black_attack_boards,
+                move_log: self.move_log.clone(),
+                castling_rights: self.castling_rights,
+                en_passant: self.en_passant,
+                halfmove_clock: self.halfmove_clock,
+                fullmove_number: self.fullmove_number,
+                state_log: self.state_log.clone(),
+            }
+        }
     }
 
     impl PartialEq<PieceType> for &PieceType {
@@ -80,6 +140,11 @@ pub mod game {
                 white_attack_boards: Vec::with_capacity(16),
                 black_attack_boards: Vec::with_capacity(16),
                 move_log: Vec::with_capacity(2000),
+                castling_rights: CastlingRights::all(),
+                en_passant: None,
+                halfmove_clock: 0,
+                fullmove_number: 1,
+                state_log: Vec::with_capacity(2000),
             }
        }
 
@@ -92,6 +157,11 @@ pub mod game {
                 white_attack_boards: Vec::with_capacity(16),
                 black_attack_boards: Vec::with_capacity(16),
                 move_log: Vec::with_capacity(2000),
+                castling_rights: CastlingRights::all(),
+                en_passant: None,
+                halfmove_clock: 0,
+                fullmove_number: 1,
+                state_log: Vec::with_capacity(2000),
             }
         }
 
@@ -152,8 +222,9 @@ pub mod game {
         pub fn get_castling_rights_white(&self, occupancy: &u64, opponent_attacked_squares: &u64) -> (bool, bool) {
             let mut left_possible = true;
             let mut right_possible = true;
-            let check_left: u64 = 6_917_529_027_641_081_856;
-            let check_right: u64 = 864_691_128_455_135_232;
+            let check_left: u64 = 864_691_128_455_135_232;
+            let check_right: u64 = 6_917_529_027_641_081_856;
+            let knight_square: u64 = 144_115_188_075_855_872;
             let king_position: u64 = 1_152_921_504_606_846_976;
             //let rook_right_position: u64 = 9_223_372_036_854_775_808;
             //let rook_left_position: u64 = 72_057_594_037_927_936;
@@ -162,10 +233,13 @@ pub mod game {
                 return (false, false)
             }
 
-            if self.board.white_king_board & king_position == 0 {
+            if self.board.white_king_board & king_position == 0 || opponent_attacked_squares & king_position > 0 {
                 return (false, false)
             }
 
+            left_possible &= self.castling_rights.white_left && occupancy & knight_square == 0;
+            right_possible &= self.castling_rights.white_right;
+
             if opponent_attacked_squares & check_left > 0 || occupancy & check_left > 0  {
                 left_possible = false;
             }
@@ -213,16 +287,20 @@ pub mod game {
             let mut right_possible = true;
             let check_left: u64 = 12;
             let check_right: u64 = 96;
+            let knight_square: u64 = 2;
             let king_position: u64 = 16;
 
             if self.board.black_king_board == 0 {
                 return (false, false)
             }
 
-            if self.board.white_king_board & king_position == 0 {
+            if self.board.black_king_board & king_position == 0 || opponent_attacked_squares & king_position > 0 {
                 return (false, false)
             }
 
+            left_possible &= self.castling_rights.black_left && occupancy & knight_square == 0;
+            right_possible &= self.castling_rights.black_right;
+
             if opponent_attacked_squares & check_left > 0 || occupancy & check_left > 0 {
                 left_possible = false;
             }
@@ -274,9 +352,143 @@ pub mod game {
             return sum
         }
 
+        // The generated moves plus castling, minus the ones that leave the own king attacked
+        pub fn legal_moves(&mut self) -> Vec<Move> {
+            let (mut moves, white_attacks, black_attacks) = self.get_all_moves();
+
+            let is_white = self.is_white_turn;
+            let occupancy = self.board.get_board_value();
+            let (left, right) = if is_white {
+                self.get_castling_rights_white(&occupancy, &black_attacks)
+            } else {
+                self.get_castling_rights_black(&occupancy, &white_attacks)
+            };
+            let king_position = if is_white { 60 } else { 4 };
+            if left {
+                moves.push(Castle(king_position, Left, is_white));
+            }
+            if right {
+                moves.push(Castle(king_position, Right, is_white));
+            }
+
+            moves.retain(|m| self.leaves_king_safe(m));
+            moves
+        }
+
+        pub fn leaves_king_safe(&mut self, m: &Move) -> bool {
+            let is_white = self.is_white_turn;
+            self.make_move(m);
+            let safe = !self.king_attacked(is_white);
+            self.undo_move();
+            safe
+        }
+
+        pub fn is_in_check(&mut self) -> bool {
+            self.king_attacked(self.is_white_turn)
+        }
+
+        fn king_attacked(&mut self, is_white: bool) -> bool {
+            let (king, opponents) = if is_white {
+                (self.board.white_king_board, self.board.get_black_occupancy())
+            } else {
+                (self.board.black_king_board, self.board.get_white_occupancy())
+            };
+            if king == 0 {
+                return false
+            }
+            let attackers = self.move_gen.attackers_to(&self.board, king.trailing_zeros() as usize, self.board.get_board_value());
+            attackers & opponents > 0
+        }
+
+        // The castling rights that still matter, a right only counts while the king and rook are on their starting squares
+        pub fn effective_castling_rights(&self) -> CastlingRights {
+            let board = &self.board;
+            let home = |pieces: u64, pos: usize| pieces & utils::POSITIONS[pos] > 0;
+            let white_king = home(board.white_king_board, 60);
+            let black_king = home(board.black_king_board, 4);
+
+            CastlingRights {
+                white_left: self.castling_rights.white_left && white_king && home(board.white_rook_board, 56),
+                white_right: self.castling_rights.white_right && white_king && home(board.white_rook_board, 63),
+                black_left: self.castling_rights.black_left && black_king && home(board.black_rook_board, 0),
+                black_right: self.castling_rights.black_right && black_king && home(board.black_rook_board, 7),
+            }
+        }
+
+        // True when the en passant square can actually be taken by a pawn of the side to move
+        pub fn en_passant_capture_possible(&self) -> bool {
+            let target = match self.en_passant {
+                Some(target) => target as usize,
+                None => return false
+            };
+
+            let (row, pawns) = if self.is_white_turn {
+                (target + 8, self.board.white_pawn_board)
+            } else {
+                (target - 8, self.board.black_pawn_board)
+            };
+
+            let file = utils::get_file(target);
+            (file > 0 && pawns & utils::POSITIONS[row - 1] > 0) || (file < 7 && pawns & utils::POSITIONS[row + 1] > 0)
+        }
+
+        // Resolves a UCI move string ("e2e4", "e7e8q", "e1g1") against the legal moves of the side to move
+        pub fn move_from_uci(&mut self, uci: &str) -> Option<Move> {
+            if uci.len() != 4 && uci.len() != 5 {
+                return None
+            }
+
+            let from = utils::parse_square(uci.get(0..2)?)?;
+            let to = utils::parse_square(uci.get(2..4)?)?;
+            let promotion = match uci.get(4..)? {
+                "" => None,
+                "q" => Some(QUEEN),
+                "r" => Some(ROOK),
+                "b" => Some(BISHOP),
+                "n" => Some(KNIGHT),
+                _ => return None
+            };
+
+            let (moves, white_attacks, black_attacks) = self.get_all_moves();
+
+            let is_white = self.is_white_turn;
+            let king_position = if is_white { 60 } else { 4 };
+            if from == king_position && promotion.is_none() && (to == from + 2 || to + 2 == from) {
+                let occupancy = self.board.get_board_value();
+                let (left, right) = if is_white {
+                    self.get_castling_rights_white(&occupancy, &black_attacks)
+                } else {
+                    self.get_castling_rights_black(&occupancy, &white_attacks)
+                };
+
+                if to == from + 2 && right {
+                    return Some(Castle(from, Right, is_white))
+                }
+                if to + 2 == from && left {
+                    return Some(Castle(from, Left, is_white))
+                }
+                return None
+            }
+
+            let m = moves.into_iter().find(|m| match *m {
+                Standard(f, t, _, _) | Capture(f, t, _, _, _) => promotion.is_none() && f == from && t == to,
+                Promotion(f, t, p, _, _) => promotion == Some(p) && f == from && t == to,
+                _ => false
+            })?;
+            // The generated moves are pseudo legal, a pinned piece can't move off the pin
+            Some(m).filter(|m| self.leaves_king_safe(m))
+        }
+
         pub fn make_move(&mut self, m: &Move) -> () {
             //println!("{:?}", m);
 
+            self.state_log.push(IrreversibleState {
+                castling_rights: self.castling_rights,
+                en_passant: self.en_passant,
+                halfmove_clock: self.halfmove_clock,
+            });
+            self.update_irreversible_state(m);
+
             let x = self.board.make_move(&m, self.is_white_turn);
 
             let mut from = 0;
@@ -322,9 +534,93 @@ pub mod game {
                 None => panic!("Went for undo move too many times")
             }
 
+            if let Some(state) = self.state_log.pop() {
+                self.castling_rights = state.castling_rights;
+                self.en_passant = state.en_passant;
+                self.halfmove_clock = state.halfmove_clock;
+            }
+
+            self.is_white_turn = !self.is_white_turn;
+            if !self.is_white_turn {
+                self.fullmove_number -= 1;
+            }
+        }
+
+        // Passes the turn without moving, for null move pruning. The move log gets a Move::None.
+        pub fn make_null_move(&mut self) {
+            self.state_log.push(IrreversibleState {
+                castling_rights: self.castling_rights,
+                en_passant: self.en_passant,
+                halfmove_clock: self.halfmove_clock,
+            });
+            self.update_irreversible_state(&Move::None);
+            self.move_log.push(Move::None);
             self.is_white_turn = !self.is_white_turn;
         }
 
+        pub fn undo_null_move(&mut self) {
+            match self.move_log.pop() {
+                Some(Move::None) => {}
+                m => panic!("Undid a null move but the last move was {:?}", m)
+            }
+
+            if let Some(state) = self.state_log.pop() {
+                self.castling_rights = state.castling_rights;
+                self.en_passant = state.en_passant;
+                self.halfmove_clock = state.halfmove_clock;
+            }
+
+            self.is_white_turn = !self.is_white_turn;
+            if !self.is_white_turn {
+                self.fullmove_number -= 1;
+            }
+        }
+
+        // Castling rights, en passant square and the move clocks after m is played
+        fn update_irreversible_state(&mut self, m: &Move) {
+            self.en_passant = None;
+            self.halfmove_clock += 1;
+            if !self.is_white_turn {
+                self.fullmove_number += 1;
+            }
+
+            let (from, to) = match *m {
+                Standard(from, to, p, _) => {
+                    if p == PAWN {
+                        self.halfmove_clock = 0;
+                        if from.abs_diff(to) == 16 {
+                            self.en_passant = Some((from + to) / 2);
+                        }
+                    }
+                    (from, to)
+                }
+                Capture(from, to, _, _, _) | Promotion(from, to, _, _, _) => {
+                    self.halfmove_clock = 0;
+                    (from, to)
+                }
+                Castle(king_position, _, _) => (king_position, king_position),
+                Move::None => return
+            };
+
+            for square in [from, to] {
+                match square {
+                    56 => self.castling_rights.white_left = false,
+                    63 => self.castling_rights.white_right = false,
+                    60 => {
+                        self.castling_rights.white_left = false;
+                        self.castling_rights.white_right = false;
+                    }
+                    0 => self.castling_rights.black_left = false,
+                    7 => self.castling_rights.black_right = false,
+                    4 => {
+                        self.castling_rights.black_left = false;
+                        self.castling_rights.black_right = false;
+                    }
+                    _ => ()
+                }
+            }
+        }
+
         pub fn panic_with_trail(&self) {
 
             println!("--------------------------------------------------------------------\n--------------------------------------------------------------------");
@@ -345,6 +641,23 @@ pub mod game {
 
         }
 
+        pub fn diagram(&self, options: DiagramOptions) -> BoardDiagram<'_> {
+            BoardDiagram {
+                board: &self.board,
+                options,
+                white_to_move: Some(self.is_white_turn),
+                fen: self.to_fen(),
+            }
+        }
+
+    }
+
+    // Same layout as the Board diagram, with side to move and the full FEN in the footer
+    impl fmt::Display for Game {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            let options = DiagramOptions { unicode: f.alternate(), ..DiagramOptions::default() };
+            write!(f, "{}", self.diagram(options))
+        }
     }
 
-}
\ No newline at end of file
+}
@@ -1,12 +1,30 @@
 pub mod game;
 pub mod board;
 pub mod debug;
+pub mod fen;
 mod eval_board;
 mod utils;
 mod magic;
 mod move_gen;
 
 mod move_list;
+pub mod packed;
+pub mod polyglot;
+pub mod pgn;
+pub mod book_builder;
+pub mod syzygy;
+pub mod endgame;
+pub mod render;
+pub mod time_manager;
+pub mod move_order;
+pub mod score;
+pub mod transposition_table;
+pub mod search_limits;
+pub mod search_info;
+pub mod engine;
+pub mod searcher;
+#[cfg(feature = "serde")]
+pub mod serialization;
 
 /*
 Calculate moves for pieces only that are affected by the precious move.
@@ -95,6 +113,27 @@ mod tests {
     use crate::move_gen::move_gen::Direction::{East, North, South, West};
     use crate::move_gen::move_gen::{MoveGen, PieceType};
     use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::board::board::{Board, DiagramOptions};
+    use crate::fen::fen::{FenError, STARTING_POSITION};
+    use crate::packed::packed::{GameResult, PackedReader, PackedWriter, TrainingPosition, PACKED_SIZE};
+    use crate::polyglot::polyglot::{encode_move, polyglot_key, BookEntry, BookError, PolyglotBook, SelectionPolicy};
+    use crate::pgn::pgn::{PgnError, PgnGame, PgnReader};
+    use crate::book_builder::book_builder::{BookBuilder, BookBuilderOptions};
+    use crate::syzygy::syzygy;
+    use crate::syzygy::syzygy::{Tablebase, Wdl};
+    use crate::endgame::endgame::{EndgameError, EndgameTable, EndgameTables, Outcome, Signature};
+    use crate::render::render;
+    use crate::render::render::{Arrow, Highlight, RenderOptions, Rgba};
+    use crate::time_manager::time_manager::{TimeControl, TimeManager};
+    use crate::transposition_table::transposition_table;
+    use crate::transposition_table::transposition_table::{Bound, TranspositionTable};
+    use crate::score::score;
+    use crate::move_order::move_order;
+    use crate::move_order::move_order::{MoveKind, MoveOrdering, OrderingStats};
+    use crate::search_limits::search_limits::{SearchLimits, StopHandle};
+    use crate::search_info::search_info::{SearchControl, SearchInfo};
+    use crate::engine::engine::{PruningStats, SearchOptions, SearchResult};
+    use crate::searcher::searcher::{EngineHandle, EngineOptions, PositionError, SearchEvent, Searcher};
     use crate::utils::utils;
 
 
@@ -816,4 +855,1468 @@ mod tests {
         assert_eq!(moves, []);
     }
 
+    #[test]
+    fn board_display_ascii_diagram() {
+        let board: Board = "4k3/8/8/8/8/8/4P3/R3K2R".parse().unwrap();
+
+        let expected = "\
+8 . . . . k . . .
+7 . . . . . . . .
+6 . . . . . . . .
+5 . . . . . . . .
+4 . . . . . . . .
+3 . . . . . . . .
+2 . . . . P . . .
+1 R . . . K . . R
+  a b c d e f g h
+
+FEN: 4k3/8/8/8/8/8/4P3/R3K2R";
+
+        assert_eq!(board.to_string(), expected);
+    }
+
+    #[test]
+    fn board_display_flipped_unicode_without_footer() {
+        let board: Board = "4k3/8/8/8/8/8/4P3/R3K2R".parse().unwrap();
+        let options = DiagramOptions { unicode: true, flipped: true, footer: false, ..DiagramOptions::default() };
+
+        let rendered = board.diagram(options).to_string();
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "1 ♖ . . ♔ . . . ♖");
+        assert_eq!(lines[7], "8 . . . ♚ . . . .");
+        assert_eq!(lines[8], "  h g f e d c b a");
+        assert_eq!(lines.len(), 9);
+    }
+
+    #[test]
+    fn game_fen_round_trip() {
+        let fens = [
+            STARTING_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 3 41",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+        ];
+
+        for fen in fens {
+            let game: Game = fen.parse().unwrap();
+            assert_eq!(game.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn game_display_has_footer() {
+        let game: Game = "4k3/8/8/8/8/8/8/4K3 b - - 0 1".parse().unwrap();
+        let rendered = game.to_string();
+
+        assert!(rendered.ends_with("Black to move\nFEN: 4k3/8/8/8/8/8/8/4K3 b - - 0 1"));
+    }
+
+    #[test]
+    fn fen_rejects_malformed_input() {
+        assert_eq!("".parse::<Board>().unwrap_err(), FenError::Empty);
+        assert_eq!("8/8/8".parse::<Board>().unwrap_err(), FenError::WrongRankCount(3));
+        assert_eq!("9/8/8/8/8/8/8/8".parse::<Board>().unwrap_err(), FenError::InvalidPiece('9'));
+        assert_eq!("8/8/8/8/8/8/8/ppppppppp".parse::<Board>().unwrap_err(), FenError::WrongRankLength(1));
+        assert_eq!("8/8/8/8/8/8/8/7x".parse::<Board>().unwrap_err(), FenError::InvalidPiece('x'));
+        assert!("8/8/8/8/8/8/8/8 x".parse::<Game>().is_err());
+        assert!("8/8/8/8/8/8/8/8 w KX".parse::<Game>().is_err());
+        assert!("8/8/8/8/8/8/8/8 w - e4".parse::<Game>().is_err());
+    }
+
+    #[test]
+    fn make_and_undo_move_track_fen_state() {
+        let mut game: Game = STARTING_POSITION.parse().unwrap();
+
+        game.make_move(&Standard(52, 36, PAWN, true));
+        assert_eq!(game.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        game.make_move(&Standard(6, 21, KNIGHT, false));
+        game.make_move(&Standard(60, 52, KING, true));
+        assert_eq!(game.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2");
+
+        game.undo_move();
+        game.undo_move();
+        game.undo_move();
+        assert_eq!(game.to_fen(), STARTING_POSITION);
+    }
+
+    #[test]
+    fn castling_needs_empty_unattacked_squares_for_both_colours() {
+        let castling = |fen: &str| {
+            let mut game: Game = fen.parse().unwrap();
+            let (_, white_attacks, black_attacks) = game.get_all_moves();
+            let occupancy = game.board.get_board_value();
+            if game.is_white_turn {
+                game.get_castling_rights_white(&occupancy, &black_attacks)
+            } else {
+                game.get_castling_rights_black(&occupancy, &white_attacks)
+            }
+        };
+
+        assert_eq!(castling("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), (true, true));
+        assert_eq!(castling("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), (true, true));
+
+        // The knight's square on the long side has to be empty even though the king doesn't cross it
+        assert_eq!(castling("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1"), (false, true));
+        assert_eq!(castling("rn2k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), (false, true));
+
+        // An attacked square on the king's way only rules out that side
+        assert_eq!(castling("r3k2r/8/8/8/8/8/6r1/R3K2R w KQkq - 0 1"), (true, false));
+        assert_eq!(castling("r3k2r/2R5/8/8/8/8/8/R3K2R b KQkq - 0 1"), (false, true));
+
+        // No castling out of check
+        assert_eq!(castling("r3k2r/8/8/8/8/8/4r3/R3K2R w KQkq - 0 1"), (false, false));
+        assert_eq!(castling("r3k2r/4R3/8/8/8/8/8/R3K2R b KQkq - 0 1"), (false, false));
+    }
+
+    #[test]
+    fn rook_capture_in_the_corner_removes_the_castling_right() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut game: Game = fen.parse().unwrap();
+
+        game.make_move(&Capture(63, 7, ROOK, ROOK, true));
+        assert!(!game.castling_rights.black_right && !game.castling_rights.white_right);
+        assert!(game.castling_rights.black_left && game.castling_rights.white_left);
+        assert_eq!(game.to_fen(), "r3k2R/8/8/8/8/8/8/R3K3 b Qq - 0 1");
+
+        game.undo_move();
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn undo_castle_and_promotion_capture_restore_position() {
+        let boards = |b: &Board| [
+            b.white_pawn_board, b.white_knight_board, b.white_bishop_board, b.white_rook_board, b.white_queen_board, b.white_king_board,
+            b.black_pawn_board, b.black_knight_board, b.black_bishop_board, b.black_rook_board, b.black_queen_board, b.black_king_board,
+        ];
+
+        let cases = [
+            ("r3k2r/1P6/8/8/8/8/1p6/R3K2R w KQkq - 0 1", [Castle(60, Left, true), Castle(60, Right, true), Promotion(9, 0, QUEEN, ROOK, true)]),
+            ("r3k2r/1P6/8/8/8/8/1p6/R3K2R b KQkq - 0 1", [Castle(4, Left, false), Castle(4, Right, false), Promotion(49, 56, KNIGHT, ROOK, false)]),
+        ];
+        for (fen, moves) in cases {
+            let mut game: Game = fen.parse().unwrap();
+            let before = boards(&game.board);
+            for m in moves {
+                game.make_move(&m);
+                assert_ne!(boards(&game.board), before);
+                game.undo_move();
+                assert_eq!(boards(&game.board), before, "{:?}", m);
+                assert_eq!(game.to_fen(), fen);
+            }
+        }
+
+        let mut game: Game = "r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1".parse().unwrap();
+        game.make_move(&Castle(60, Left, true));
+        assert_eq!(game.to_fen(), "r3k2r/1P6/8/8/8/8/8/2KR3R b kq - 1 1");
+    }
+
+    #[test]
+    fn uci_moves_resolve_against_position() {
+        let mut game: Game = "r3k2r/8/8/8/8/8/4P3/R3K2R w KQkq - 0 1".parse().unwrap();
+
+        assert_eq!(game.move_from_uci("e2e4"), Some(Standard(52, 36, PAWN, true)));
+        assert_eq!(game.move_from_uci("e1g1"), Some(Castle(60, Right, true)));
+        assert_eq!(game.move_from_uci("e1c1"), Some(Castle(60, Left, true)));
+        assert_eq!(game.move_from_uci("e2e5"), None);
+        assert_eq!(game.move_from_uci("e2"), None);
+
+        let m = game.move_from_uci("e1g1").unwrap();
+        assert_eq!(m.to_uci(), "e1g1");
+        game.make_move(&m);
+        assert_eq!(game.move_from_uci("e8c8"), Some(Castle(4, Left, false)));
+
+        let mut no_rights: Game = "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1".parse().unwrap();
+        assert_eq!(no_rights.move_from_uci("e1g1"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_round_trips_public_types() {
+        use serde::de::DeserializeSeed;
+        use crate::serialization::serialization::MoveSeed;
+
+        let mut game: Game = STARTING_POSITION.parse().unwrap();
+        for uci in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+            let m = game.move_from_uci(uci).unwrap();
+            game.make_move(&m);
+        }
+
+        let json = serde_json::to_string(&game).unwrap();
+        assert_eq!(json, format!("{{\"version\":1,\"start_fen\":\"{}\",\"moves\":[\"e2e4\",\"e7e5\",\"g1f3\",\"b8c6\"]}}", STARTING_POSITION));
+        let restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_fen(), game.to_fen());
+
+        let json = serde_json::to_string(&game.board).unwrap();
+        let board: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(board.to_fen_placement(), game.board.to_fen_placement());
+
+        // A move is its UCI string, read back against the position it is played in
+        let m = game.move_from_uci("f3e5").unwrap();
+        assert_eq!(m, Capture(45, 28, KNIGHT, PAWN, true));
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(json, "\"f3e5\"");
+        let read = |game: &mut Game, json: &str| MoveSeed(game).deserialize(&mut serde_json::Deserializer::from_str(json));
+        assert_eq!(read(&mut game, &json).unwrap(), m);
+        assert_eq!(read(&mut game, "\"f1b5\"").unwrap(), Standard(61, 25, BISHOP, true));
+        assert!(read(&mut game, "\"f3e6\"").is_err());
+        assert!(serde_json::from_str::<Move>(&json).is_err());
+
+        assert!(serde_json::from_str::<Board>("{\"version\":99,\"fen\":\"8/8/8/8/8/8/8/8\"}").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn compact_move_encoding_round_trips() {
+        let moves = [
+            Move::None,
+            Standard(52, 36, PAWN, true),
+            Capture(35, 28, KNIGHT, PAWN, false),
+            Promotion(9, 0, QUEEN, ROOK, true),
+            Promotion(50, 58, KNIGHT, PieceType::None, false),
+            Castle(60, Left, true),
+            Castle(4, Right, false),
+        ];
+
+        for m in moves {
+            assert_eq!(Move::from_compact(m.to_compact()), Some(m));
+        }
+    }
+
+    #[test]
+    fn packed_position_round_trips() {
+        let fens = [
+            STARTING_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq - 12 40",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "8/8/8/8/8/8/8/4K2k w - - 99 300",
+        ];
+
+        for fen in fens {
+            let game: Game = fen.parse().unwrap();
+            let mut position = TrainingPosition::from_game(&game);
+            position.score = Some(-135);
+            position.result = Some(GameResult::Draw);
+
+            let bytes = position.pack().unwrap();
+            assert_eq!(bytes.len(), PACKED_SIZE);
+
+            let unpacked = TrainingPosition::unpack(&bytes).unwrap();
+            assert_eq!(unpacked.board.to_fen_placement(), game.board.to_fen_placement());
+            assert_eq!(unpacked.board.white_king_position, game.board.white_king_position);
+            assert_eq!(unpacked.board.black_king_position, game.board.black_king_position);
+            assert_eq!(unpacked.score, Some(-135));
+            assert_eq!(unpacked.result, Some(GameResult::Draw));
+
+            let mut restored: Game = STARTING_POSITION.parse().unwrap();
+            unpacked.apply_to(&mut restored);
+            assert_eq!(restored.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn packed_reader_streams_what_writer_wrote() {
+        let mut writer = PackedWriter::new(Vec::new());
+        let games: Vec<Game> = [STARTING_POSITION, "4k3/8/8/8/8/8/8/4K3 b - - 0 1"].iter().map(|fen| fen.parse().unwrap()).collect();
+
+        for game in games.iter() {
+            writer.write(&TrainingPosition::from_game(game)).unwrap();
+        }
+        assert_eq!(writer.written(), 2);
+
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes.len(), 2 * PACKED_SIZE);
+
+        let read: Vec<TrainingPosition> = PackedReader::new(bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].board.to_fen_placement(), "4k3/8/8/8/8/8/8/4K3");
+        assert!(!read[1].is_white_turn);
+        assert_eq!(read[1].score, None);
+        assert_eq!(read[1].result, None);
+
+        let truncated = &bytes[..PACKED_SIZE + 5];
+        let mut reader = PackedReader::new(truncated);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn polyglot_keys_match_reference_positions() {
+        let positions = [
+            (STARTING_POSITION, 0x463b96181691fc9c),
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823c9b50fd114196),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756b94461c50fb0),
+            ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662fafb965db29d4),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22a48b5a8e47ff78),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652a607ca3f242c1),
+            ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00fdd303c946bdd9),
+            ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3c8123ea7b067637),
+            ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5c3f9b829b279560),
+        ];
+
+        for (fen, key) in positions {
+            assert_eq!(polyglot_key(&Game::from_fen(fen).unwrap()), key, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn polyglot_book_lookup_and_selection() {
+        let mut game = Game::from_fen(STARTING_POSITION).unwrap();
+        let start_key = polyglot_key(&game);
+        let castle_fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+        let mut castle_game = Game::from_fen(castle_fen).unwrap();
+        let castle_key = polyglot_key(&castle_game);
+
+        let entries = [
+            BookEntry { key: castle_key, raw_move: encode_move(&Castle(60, Right, true)), weight: 1, learn: 0 },
+            BookEntry { key: start_key, raw_move: encode_move(&Standard(51, 35, PAWN, true)), weight: 5, learn: 0 },
+            BookEntry { key: start_key, raw_move: encode_move(&Standard(52, 36, PAWN, true)), weight: 10, learn: 0 },
+            BookEntry { key: start_key, raw_move: encode_move(&Standard(62, 45, KNIGHT, true)), weight: 0, learn: 0 },
+        ];
+        let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+
+        let book = PolyglotBook::from_bytes(&bytes).unwrap();
+        assert_eq!(book.len(), 4);
+        assert_eq!(PolyglotBook::from_bytes(&bytes[..20]).unwrap_err(), BookError::TruncatedEntry(20));
+
+        // e2e4 is stored as 0x031c
+        assert_eq!(encode_move(&Standard(52, 36, PAWN, true)), 0x031c);
+
+        let moves = book.moves(&mut game);
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[0].m, Standard(52, 36, PAWN, true));
+
+        assert_eq!(book.select(&mut game, &mut SelectionPolicy::Best).unwrap().m, Standard(52, 36, PAWN, true));
+        assert_eq!(book.select(&mut castle_game, &mut SelectionPolicy::Best).unwrap().m, Castle(60, Right, true));
+
+        let picks = |seed| {
+            let mut policy = SelectionPolicy::weighted_random(seed);
+            (0..20).map(|_| book.select(&mut Game::from_fen(STARTING_POSITION).unwrap(), &mut policy).unwrap().m).collect::<Vec<Move>>()
+        };
+        let first = picks(7);
+        assert_eq!(first, picks(7));
+        assert!(first.iter().all(|m| *m != Standard(62, 45, KNIGHT, true)));
+
+        assert!(book.select(&mut Game::from_fen("8/8/8/8/8/8/8/K6k w - - 0 1").unwrap(), &mut SelectionPolicy::Best).is_none());
+
+        // The knight on e2 is pinned, a book move for it isn't legal
+        let mut pinned = Game::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        let knight_move = Standard(52, 42, KNIGHT, true);
+        let entry = BookEntry { key: polyglot_key(&pinned), raw_move: encode_move(&knight_move), weight: 1, learn: 0 };
+        assert_eq!(entry.to_move(&mut pinned), None);
+        assert_eq!(pinned.move_from_uci("e2c3"), None);
+        assert_eq!(pinned.move_from_uci("e1d1"), Some(Standard(60, 59, KING, true)));
+    }
+
+    #[test]
+    fn pgn_games_parse_and_replay() {
+        let text = r#"[Event "Casual"]
+[White "A \"quoted\" name"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 3. Bb5 a6 $1 4. Ba4 Nf6 5.O-O Be7
+6. Re1 b5 7. Bb3 d6 8. c3 O-O 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "4k3/1P6/8/8/8/8/8/R3K2R w KQ - 0 1"]
+
+1. b8=Q+ Kd7 2. O-O-O+ *
+"#;
+
+        let games: Vec<PgnGame> = PgnReader::new(text.as_bytes()).map(|g| g.unwrap()).collect();
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("White"), Some("A \"quoted\" name"));
+        assert_eq!(games[0].result, Some(GameResult::WhiteWin));
+        assert_eq!(games[0].moves.len(), 16);
+        assert_eq!(games[0].moves[8], "O-O");
+
+        let mut game = Game::new(true);
+        games[0].replay(&mut game).unwrap();
+        assert_eq!(game.to_fen(), "r1bq1rk1/2p1bppp/p1np1n2/1p2p3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 w - - 1 9");
+
+        assert_eq!(games[1].result, None);
+        games[1].replay(&mut game).unwrap();
+        assert_eq!(game.to_fen(), "1Q6/3k4/8/8/8/8/8/2KR3R b - - 2 2");
+
+        // Both knights reach d2, so the file has to be given
+        let mut game = Game::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert_eq!(game.move_from_san("Nd2"), None);
+        assert_eq!(game.move_from_san("Nbd2"), Some(Standard(57, 51, KNIGHT, true)));
+        assert_eq!(game.move_from_san("Nfd2"), Some(Standard(45, 51, KNIGHT, true)));
+        assert_eq!(game.move_from_san("Ng5+"), Some(Standard(45, 30, KNIGHT, true)));
+
+        // The only knight that reaches c3 is pinned
+        let mut game = Game::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.move_from_san("Nc3"), None);
+        assert_eq!(game.move_from_san("Kd1"), Some(Standard(60, 59, KING, true)));
+
+        assert_eq!(PgnGame::parse("1. e4 (1. d4 e5"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(PgnGame::parse("1. e4 {unfinished"), Err(PgnError::UnterminatedComment));
+    }
+
+    #[test]
+    fn book_builder_weights_moves_by_result() {
+        let text = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+                    [Result \"1/2-1/2\"]\n\n1. e4 c5 1/2-1/2\n\n\
+                    [Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+                    [Result \"*\"]\n\n1. c4 *\n";
+
+        let options = BookBuilderOptions { max_ply: 2, min_games: 1, black_factor: 0.5, ..BookBuilderOptions::default() };
+        let mut builder = BookBuilder::new(options);
+        builder.add_pgn(PgnReader::new(text.as_bytes())).unwrap();
+
+        let stats = builder.stats();
+        assert_eq!(stats.games, 3);
+        assert_eq!(stats.skipped_games, 1);
+        assert_eq!(stats.plies, 6);
+
+        let mut bytes = vec![];
+        assert_eq!(builder.write_book(&mut bytes).unwrap(), 3);
+        let book = PolyglotBook::from_bytes(&bytes).unwrap();
+
+        // e4 won once and drew once, d4 lost, so only e4 is in the book for white
+        let mut game = Game::from_fen(STARTING_POSITION).unwrap();
+        let moves = book.moves(&mut game);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].m, Standard(52, 36, PAWN, true));
+        assert_eq!(moves[0].weight, 3);
+
+        // Black's win with d5 is halved, e5 lost and c5 drew
+        game.make_move(&Standard(51, 35, PAWN, true));
+        let moves = book.moves(&mut game);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].weight, 1);
+
+        let mut summary = vec![];
+        builder.write_summary(&mut summary).unwrap();
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(summary.starts_with(STARTING_POSITION));
+        assert!(summary.contains("e2e4   weight     3  games      2  +1 =1 -0"));
+
+        let strict = BookBuilderOptions { min_games: 2, ..options };
+        let mut builder = BookBuilder::new(strict);
+        builder.add_pgn(PgnReader::new(text.as_bytes())).unwrap();
+        assert_eq!(builder.entries().len(), 1);
+
+        // Nf3 comes back to the same position later in the game but only counts once
+        let text = "[Result \"1-0\"]\n\n1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 1-0\n";
+        let mut builder = BookBuilder::new(BookBuilderOptions { max_ply: 6, ..options });
+        builder.add_pgn(PgnReader::new(text.as_bytes())).unwrap();
+        assert_eq!(builder.stats().plies, 5);
+
+        let mut summary = vec![];
+        builder.write_summary(&mut summary).unwrap();
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(summary.contains("g1f3   weight     2  games      1  +1 =0 -0"));
+    }
+
+    #[test]
+    fn syzygy_probing_without_tables() {
+        assert_eq!(syzygy::king_pair_codes(), 462);
+
+        let dir = std::env::temp_dir().join(format!("syzygy_empty_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tablebase = Tablebase::open(&dir).unwrap();
+        assert_eq!(tablebase.table_count(), 0);
+        assert_eq!(tablebase.max_pieces(), 2);
+
+        // Bare kings need no file
+        let mut game = Game::from_fen("8/8/3k4/8/8/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&mut game), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&mut game), Some(0));
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert!(!tablebase.can_probe(&game));
+        assert_eq!(tablebase.probe_wdl(&mut game), None);
+        assert_eq!(tablebase.root_moves(&mut game), None);
+
+        assert_eq!(-Wdl::CursedWin, Wdl::BlessedLoss);
+        assert_eq!(-Wdl::Loss, Wdl::Win);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Needs real tables, run with SYZYGY_PATH pointing at a directory holding the 3 to 5 man files
+    #[test]
+    #[ignore = "needs SYZYGY_PATH"]
+    fn syzygy_probing_known_endgames() {
+        let tablebase = Tablebase::open(std::env::var("SYZYGY_PATH").unwrap()).unwrap();
+
+        let wdl = |fen: &str| tablebase.probe_wdl(&mut Game::from_fen(fen).unwrap());
+        let dtz = |fen: &str| tablebase.probe_dtz(&mut Game::from_fen(fen).unwrap()).unwrap();
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/4K2R w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/4K2R b - - 0 1"), Some(Wdl::Loss));
+        assert!((1..=32).contains(&dtz("4k3/8/8/8/8/8/8/4K2R w - - 0 1")));
+        assert!((-32..=-1).contains(&dtz("4k3/8/8/8/8/8/8/4K2R b - - 0 1")));
+        assert_eq!(wdl("4k2r/8/8/8/8/8/8/4K3 b - - 0 1"), Some(Wdl::Win));
+        // Black takes the undefended rook
+        assert_eq!(wdl("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(dtz("8/8/8/8/8/8/1k6/1R2K3 b - - 0 1"), 0);
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"), Some(Wdl::Draw));
+        // KPvK with the king on the sixth rank in front of its pawn
+        assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(Wdl::Win));
+
+        // Two bishops win on opposite colours and draw on the same one, the repeated piece is grouped in the index
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/2B1KB2 b - - 0 1"), Some(Wdl::Loss));
+        assert!(dtz("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1") > 0);
+        assert!(dtz("4k3/8/8/8/8/8/8/2B1KB2 b - - 0 1") < 0);
+        assert_eq!(wdl("4k3/8/8/8/8/8/8/B3K1B1 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(dtz("4k3/8/8/8/8/8/8/B3K1B1 w - - 0 1"), 0);
+
+        // Rh8 mates, Rb7 stalemates and every other move still wins
+        let mut game = Game::from_fen("k7/7R/1K6/8/8/8/8/8 w - - 0 1").unwrap();
+        let root_moves = tablebase.root_moves(&mut game).unwrap();
+        assert_eq!(root_moves.len(), game.legal_moves().len());
+        assert!(root_moves.windows(2).all(|pair| pair[0].rank >= pair[1].rank));
+        assert_eq!((root_moves[0].m.to_uci(), root_moves[0].dtz, root_moves[0].wdl), ("h7h8".to_string(), 1, Wdl::Win));
+        let last = root_moves.last().unwrap();
+        assert_eq!((last.m.to_uci(), last.dtz, last.wdl), ("h7b7".to_string(), 0, Wdl::Draw));
+        assert!(root_moves[..root_moves.len() - 1].iter().all(|root_move| root_move.wdl == Wdl::Win && root_move.dtz > 0));
+        let best = tablebase.best_moves(&mut game).unwrap();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].m.to_uci(), "h7h8");
+
+        // Mated, so there is nothing to rank
+        let mut game = Game::from_fen("k6R/8/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&mut game), Some(Wdl::Loss));
+        assert_eq!(tablebase.root_moves(&mut game), Some(vec![]));
+        assert_eq!(tablebase.best_move(&mut game), None);
+
+        // The bishop moves next to the king hang a bishop and only draw
+        let mut game = Game::from_fen("8/8/8/8/3k4/8/8/2B1KB2 w - - 0 1").unwrap();
+        let root_moves = tablebase.root_moves(&mut game).unwrap();
+        let draws: Vec<String> = root_moves.iter().filter(|root_move| root_move.wdl == Wdl::Draw).map(|root_move| root_move.m.to_uci()).collect();
+        assert_eq!(draws.len(), 3);
+        assert!(["c1e3", "f1c4", "f1d3"].iter().all(|uci| draws.contains(&uci.to_string())));
+        assert!(root_moves[root_moves.len() - 3..].iter().all(|root_move| root_move.wdl == Wdl::Draw && root_move.dtz == 0));
+        let best = tablebase.best_moves(&mut game).unwrap();
+        assert!(!best.is_empty());
+        assert!(best.iter().all(|root_move| root_move.wdl == Wdl::Win && root_move.rank == best[0].rank && root_move.dtz > 0));
+
+        let mut game = Game::from_fen("7k/8/6K1/8/8/8/Q7/8 w - - 0 1").unwrap();
+        let best = tablebase.best_move(&mut game).unwrap();
+        assert_eq!(best.dtz, 1);
+        assert_eq!(best.wdl, Wdl::Win);
+        assert_eq!(best.m, Standard(48, 0, QUEEN, true));
+        assert!(tablebase.probe_dtz(&mut game).unwrap() > 0);
+    }
+
+    #[test]
+    fn endgame_signatures() {
+        let signature = "KBNK".parse::<Signature>().unwrap();
+        assert_eq!(signature.to_string(), "KBNK");
+        assert_eq!(signature.piece_count(), 4);
+        assert_eq!("KNBvK".parse::<Signature>().unwrap(), signature);
+        assert!("KKQ".parse::<Signature>().unwrap().canonical() == "KQK".parse::<Signature>().unwrap());
+        assert!(!"KPKR".parse::<Signature>().unwrap().is_canonical());
+
+        assert_eq!("KQ".parse::<Signature>(), Err(EndgameError::InvalidSignature("KQ".to_string())));
+        assert_eq!("KXK".parse::<Signature>(), Err(EndgameError::InvalidSignature("KXK".to_string())));
+        assert_eq!("KQRKR".parse::<Signature>(), Err(EndgameError::TooManyPieces(5)));
+    }
+
+    #[test]
+    fn endgame_solver_known_results() {
+        let mut tables = EndgameTables::new();
+
+        // Longest forced mates: KQK is mate in 10, KRK mate in 16
+        assert_eq!(tables.generate(&"KQK".parse().unwrap()).unwrap().longest_win(true), Some(19));
+        assert_eq!(tables.generate(&"KRK".parse().unwrap()).unwrap().longest_win(true), Some(31));
+        tables.generate(&"KPK".parse().unwrap()).unwrap();
+        // Solving KPK needs every table a promotion leads to
+        assert_eq!(tables.len(), 5);
+
+        // Lone minor pieces can't mate
+        assert_eq!(tables.get(&"KBK".parse().unwrap()).unwrap().longest_win(true), None);
+        assert_eq!(tables.get(&"KNK".parse().unwrap()).unwrap().longest_win(true), None);
+
+        let probe = |fen: &str| tables.probe(&Game::from_fen(fen).unwrap());
+        assert_eq!(probe("7k/8/6K1/8/8/8/Q7/8 w - - 0 1"), Some(Outcome::Win(1)));
+        assert_eq!(probe("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"), Some(Outcome::Loss(0)));
+        assert_eq!(probe("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Some(Outcome::Draw));
+        assert_eq!(Outcome::Win(19).moves_to_mate(), Some(10));
+
+        // With the king behind the pawn black holds, with the king in front of it white wins
+        assert_eq!(probe("4k3/8/4P3/4K3/8/8/8/8 w - - 0 1"), Some(Outcome::Draw));
+        assert!(matches!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Some(Outcome::Win(_))));
+        assert!(matches!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Some(Outcome::Loss(_))));
+        // The pawn on the seventh stalemates when black has to move
+        assert!(matches!(probe("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"), Some(Outcome::Win(_))));
+        assert_eq!(probe("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(Outcome::Draw));
+
+        // Black's pieces are looked up in the mirrored table
+        assert_eq!(probe("8/8/8/8/8/8/4p3/4K1k1 b - - 0 1"), probe("4k1K1/4P3/8/8/8/8/8/8 w - - 0 1"));
+        assert_eq!(probe("8/q7/8/8/8/6k1/8/7K b - - 0 1"), Some(Outcome::Win(1)));
+
+        // Too many pieces, or castling rights the tables don't know about
+        assert_eq!(probe("4k3/8/8/8/8/8/8/R3K2R w - - 0 1"), None);
+        assert_eq!(probe("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"), None);
+
+        let mut game = Game::from_fen("7k/8/6K1/8/8/8/Q7/8 w - - 0 1").unwrap();
+        let (m, outcome) = tables.best_move(&mut game).unwrap();
+        assert_eq!(outcome, Outcome::Win(1));
+        game.make_move(&m);
+        assert_eq!(tables.probe(&game), Some(Outcome::Loss(0)));
+    }
+
+    #[test]
+    fn endgame_table_roundtrip() {
+        let mut tables = EndgameTables::new();
+        tables.generate(&"KRK".parse().unwrap()).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("endgame_tables_{}", std::process::id()));
+        assert_eq!(tables.save_dir(&dir).unwrap(), 1);
+        let loaded = EndgameTables::open_dir(&dir).unwrap();
+        let signature = "KRK".parse().unwrap();
+        assert!(loaded.get(&signature).unwrap().len() == tables.get(&signature).unwrap().len());
+        assert_eq!(loaded.get(&signature).unwrap().longest_win(true), Some(31));
+
+        let fen = "8/8/8/8/8/2k5/8/K6R w - - 0 1";
+        assert_eq!(loaded.probe(&Game::from_fen(fen).unwrap()), tables.probe(&Game::from_fen(fen).unwrap()));
+
+        let mut bytes = vec![];
+        tables.get(&signature).unwrap().write(&mut bytes).unwrap();
+        bytes[0] = b'X';
+        let error = EndgameTable::read(&mut bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn render_svg_diagram() {
+        let board = Game::from_fen(STARTING_POSITION).unwrap().board;
+        let svg = render::render_svg(&board, &RenderOptions::default());
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"510\" height=\"510\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<image ").count(), 32);
+        assert_eq!(svg.matches("<text ").count(), 16);
+        assert!(svg.contains("href=\"data:image/png;base64,iVBORw0KGgo"));
+
+        let options = RenderOptions {
+            coordinates: false,
+            last_move: Some(Standard(52, 36, PAWN, true)),
+            highlights: vec![Highlight::new(4)],
+            arrows: vec![Arrow::new(62, 45)],
+            ..RenderOptions::default()
+        };
+        let svg = render::render_svg(&board, &options);
+        assert!(svg.contains("width=\"480\" height=\"480\""));
+        assert_eq!(svg.matches("<text ").count(), 0);
+        assert_eq!(svg.matches("fill-opacity").count(), 4);
+        assert_eq!(svg.matches("<polygon ").count(), 1);
+
+        // a8 is drawn top left, or bottom right when flipped
+        let plain = render::render_svg(&board, &RenderOptions { coordinates: false, ..RenderOptions::default() });
+        let flipped = render::render_svg(&board, &RenderOptions { coordinates: false, flipped: true, ..RenderOptions::default() });
+        assert!(plain.contains("<image x=\"0\" y=\"0\" width=\"60\""));
+        assert!(flipped.contains("<image x=\"420\" y=\"420\" width=\"60\""));
+        assert!(!flipped.contains("<image x=\"0\" y=\"180\""));
+    }
+
+    #[test]
+    fn render_png_image() {
+        let board = Board::empty();
+        let options = RenderOptions { square_size: 40, ..RenderOptions::default() };
+        let image = render::render_image(&board, &options);
+        assert_eq!((image.width, image.height), (340, 340));
+        // a8 is light and b8 dark, right of the rank margin
+        assert_eq!(image.pixel(25, 5), Rgba::LIGHT_SQUARE);
+        assert_eq!(image.pixel(65, 5), Rgba::DARK_SQUARE);
+        assert_eq!(image.pixel(5, 330), Rgba::BACKGROUND);
+
+        let flipped = render::render_image(&board, &RenderOptions { coordinates: false, flipped: true, ..options.clone() });
+        assert_eq!(flipped.pixel(5, 5), Rgba::LIGHT_SQUARE);
+
+        // Last move tint on e2 and e4, an arrow through the middle of d5
+        let tinted = render::render_image(&board, &RenderOptions {
+            coordinates: false,
+            last_move: Some(Standard(52, 36, PAWN, true)),
+            arrows: vec![Arrow::new(19, 35)],
+            ..options.clone()
+        });
+        assert_ne!(tinted.pixel(4 * 40 + 20, 6 * 40 + 20), Rgba::LIGHT_SQUARE);
+        assert_ne!(tinted.pixel(4 * 40 + 20, 4 * 40 + 20), Rgba::DARK_SQUARE);
+        assert_eq!(tinted.pixel(4 * 40 + 20, 5 * 40 + 20), Rgba::DARK_SQUARE);
+        assert_ne!(tinted.pixel(3 * 40 + 20, 3 * 40 + 20), Rgba::LIGHT_SQUARE);
+
+        // Pieces cover the middle of their square
+        let start = Game::from_fen(STARTING_POSITION).unwrap().board;
+        let image = render::render_image(&start, &RenderOptions { coordinates: false, ..options.clone() });
+        assert_ne!(image.pixel(4 * 40 + 20, 7 * 40 + 20), Rgba::DARK_SQUARE);
+
+        let png = render::render_png(&start, &options).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let decoder = png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (340, 340));
+    }
+
+    #[test]
+    fn time_manager_limits() {
+        use std::time::Duration;
+
+        let infinite = TimeManager::new(&TimeControl::infinite(), true);
+        assert_eq!(infinite.soft_limit(), None);
+        assert!(infinite.can_start_iteration());
+        assert!(!infinite.should_stop());
+
+        let fixed = TimeManager::new(&TimeControl::movetime(Duration::from_millis(1000)), false);
+        assert_eq!(fixed.soft_limit(), Some(Duration::from_millis(970)));
+        assert_eq!(fixed.hard_limit(), Some(Duration::from_millis(970)));
+
+        // A minute without increment is spread over 30 moves, the hard limit allows three times that
+        let clock = TimeControl::parse("60").unwrap();
+        let time = TimeManager::new(&clock, true);
+        let soft = time.soft_limit().unwrap();
+        assert!(soft > Duration::from_millis(1900) && soft < Duration::from_millis(2000));
+        assert_eq!(time.hard_limit().unwrap(), soft * 3);
+
+        // The increment is mostly spent, black's clock is used for black
+        let clock = TimeControl { btime: Some(Duration::from_secs(10)), binc: Duration::from_secs(1), ..clock };
+        let time = TimeManager::new(&clock, false);
+        assert!(time.soft_limit().unwrap() > Duration::from_millis(1000));
+        assert!(time.hard_limit().unwrap() < Duration::from_secs(9));
+
+        // The last move before the time control may use most of the clock, but never all of it
+        let last = TimeControl { movestogo: Some(1), ..TimeControl::clock(Duration::from_secs(5), Duration::ZERO) };
+        let time = TimeManager::new(&last, true);
+        assert!(time.soft_limit().unwrap() >= Duration::from_secs(3));
+        assert!(time.hard_limit().unwrap() < Duration::from_secs(5));
+
+        // Almost out of time still leaves a limit that has passed quickly
+        let flagging = TimeManager::new(&TimeControl::clock(Duration::from_millis(10), Duration::ZERO), true);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(flagging.should_stop());
+        assert!(!flagging.can_start_iteration());
+
+        assert_eq!(TimeControl::parse("300+2"), Some(TimeControl::clock(Duration::from_secs(300), Duration::from_secs(2))));
+        assert_eq!(TimeControl::parse("fast"), None);
+        assert!(TimeControl::parse("0.5+0.1").is_some());
+    }
+
+    #[test]
+    fn transposition_table_store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.size_bytes(), 1024 * 1024);
+        assert!(tt.buckets().is_power_of_two());
+        assert!(TranspositionTable::new(3).buckets().is_power_of_two());
+        assert_eq!(tt.hashfull(), 0);
+
+        let key = 0x1234_5678_9abc_def0;
+        let m = Standard(52, 36, PAWN, true);
+        assert_eq!(tt.probe(key, 0), None);
+
+        tt.new_search();
+        tt.store(key, 5, Bound::Lower, 80, Some(&m), 0);
+        let hit = tt.probe(key, 0).unwrap();
+        assert_eq!((hit.depth, hit.bound, hit.score), (5, Bound::Lower, 80));
+        assert!(hit.is_best_move(&m));
+        assert!(!hit.is_best_move(&Standard(51, 35, PAWN, true)));
+
+        // A lower bound only cuts when it reaches beta, and only with enough depth
+        assert!(hit.cutoff(5, 0, 50));
+        assert!(!hit.cutoff(5, 0, 100));
+        assert!(!hit.cutoff(6, 0, 50));
+
+        // A different key in the same bucket doesn't match
+        assert_eq!(tt.probe(key ^ 1 << 60, 0), None);
+
+        // A fail low keeps the best move found before
+        tt.store(key, 6, Bound::Upper, -20, None, 0);
+        let hit = tt.probe(key, 0).unwrap();
+        assert_eq!(hit.bound, Bound::Upper);
+        assert!(hit.is_best_move(&m));
+
+        tt.clear();
+        assert_eq!(tt.probe(key, 0), None);
+    }
+
+    #[test]
+    fn transposition_table_mate_scores_and_replacement() {
+        // A mate 3 plies from the stored position is 7 plies from a root 4 plies above it
+        assert_eq!(transposition_table::score_to_tt(32_000 - 7, 4), 32_000 - 3);
+        assert_eq!(transposition_table::score_from_tt(32_000 - 3, 6), 32_000 - 9);
+        assert_eq!(transposition_table::score_to_tt(-32_000 + 7, 4), -32_000 + 3);
+        assert_eq!(transposition_table::score_to_tt(250, 9), 250);
+        assert_eq!(transposition_table::score_to_tt(i32::MAX, 0), i16::MAX as i32);
+
+        let mut tt = TranspositionTable::new(0);
+        assert_eq!(tt.buckets(), 1);
+        tt.new_search();
+
+        // Four entries fill the only bucket, the shallowest one makes room for a fifth
+        for depth in 1..=4 {
+            tt.store(depth << 48, depth as usize, Bound::Exact, 0, None, 0);
+        }
+        tt.store(9 << 48, 9, Bound::Exact, 0, None, 0);
+        assert_eq!(tt.probe(1 << 48, 0), None);
+        assert!(tt.probe(2 << 48, 0).is_some());
+        assert!(tt.probe(9 << 48, 0).is_some());
+
+        // Entries of an older search go before deeper ones of this search
+        tt.new_search();
+        tt.store(10 << 48, 1, Bound::Exact, 0, None, 0);
+        tt.store(11 << 48, 1, Bound::Exact, 0, None, 0);
+        assert!(tt.probe(10 << 48, 0).is_some());
+        assert!(tt.probe(11 << 48, 0).is_some());
+        assert!(tt.probe(9 << 48, 0).is_some());
+        assert_eq!(tt.hashfull(), 500);
+    }
+
+    #[test]
+    fn score_mate_distances() {
+        assert_eq!(score::mate_in(1), score::MATE - 1);
+        assert_eq!(score::mate_moves(score::mate_in(1)), Some(1));
+        assert_eq!(score::mate_moves(score::mate_in(3)), Some(2));
+        assert_eq!(score::mate_moves(score::mated_in(2)), Some(-1));
+        assert_eq!(score::mate_moves(250), None);
+        assert!(score::is_mate(score::mated_in(40)));
+        assert!(!score::is_mate(syzygy::TB_WIN_SCORE));
+        assert!(score::mate_in(1) > score::mate_in(5));
+        assert!(score::mated_in(6) > score::mated_in(2));
+        assert_eq!(score::to_uci(score::mate_in(5)), "mate 3");
+        assert_eq!(score::to_uci(score::mated_in(4)), "mate -2");
+        assert_eq!(score::to_uci(-37), "cp -37");
+    }
+
+    #[test]
+    fn move_ordering_heuristics() {
+        let mut game = Game::from_fen("r3k2r/ppp2ppp/2n5/3pp3/4P3/2N2N2/PPP2PPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut moves = game.legal_moves();
+        let find = |uci: &str| *moves.iter().find(|m| m.to_uci() == uci).unwrap();
+        let (a3, h3, castle, capture) = (find("a2a3"), find("h2h3"), find("e1g1"), find("e4d5"));
+        let previous = Standard(12, 28, PAWN, false);
+
+        let mut ordering = MoveOrdering::new();
+        ordering.cutoff(&h3, 2, 4, &previous, &[a3, castle]);
+        assert_eq!(ordering.killers(2)[0], h3);
+        assert_eq!(ordering.counter_move(&previous), h3);
+        assert!(ordering.history(&h3) > 0);
+        assert!(ordering.history(&a3) < 0 && ordering.history(&castle) < 0);
+        // Captures don't touch the quiet move tables
+        ordering.cutoff(&capture, 2, 4, &previous, &[]);
+        assert_eq!(ordering.killers(2), [h3, Move::None]);
+
+        // Hash move, captures that don't lose material, killer, the rest by history and the losing capture last
+        ordering.sort(&game, &mut moves, 2, Some(encode_move(&a3)), &previous);
+        let good_captures = moves.iter().filter(|m| matches!(m, Capture(..)) && game.board.see_ge(m, 0, &game.move_gen)).count();
+        assert_eq!(moves[0], a3);
+        assert_eq!(moves[1], capture); // the pawn takes first
+        assert!(moves[1..=good_captures].iter().all(|m| matches!(m, Capture(..))));
+        assert_eq!(moves[good_captures + 1], h3);
+        assert_eq!(moves[moves.len() - 2], castle);
+        assert_eq!(moves.last().unwrap().to_uci(), "f3e5"); // the knight is taken back
+        assert_eq!(ordering.kind(&h3, 2, None, &Move::None), MoveKind::Killer);
+        assert_eq!(ordering.kind(&h3, 3, None, &previous), MoveKind::Counter);
+        assert_eq!(ordering.kind(&h3, 3, None, &Move::None), MoveKind::Quiet);
+
+        // Gravity keeps the history bounded however often a move cuts off
+        for _ in 0..1000 {
+            ordering.cutoff(&h3, 2, 40, &previous, &[]);
+        }
+        assert!(ordering.history(&h3) <= move_order::HISTORY_MAX);
+
+        let mut stats = OrderingStats::default();
+        stats.record(MoveKind::TtMove, 0);
+        stats.record(MoveKind::Killer, 3);
+        assert_eq!(stats.cutoffs, 2);
+        assert_eq!(stats.killer_cutoffs, 1);
+        assert_eq!(stats.first_move_rate(), 0.5);
+    }
+
+    #[test]
+    fn static_exchange_evaluation() {
+        let see = |fen: &str, uci: &str| {
+            let mut game = Game::from_fen(fen).unwrap();
+            let m = *game.legal_moves().iter().find(|m| m.to_uci() == uci).unwrap();
+            (game.board.see(&m, &game.move_gen), game.board.see_ge(&m, 0, &game.move_gen))
+        };
+        let pawn = Game::piece_value(PAWN);
+
+        // A free pawn
+        assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), (pawn, true));
+        // The knight is lost for the pawn
+        assert_eq!(
+            see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"),
+            (pawn - Game::piece_value(KNIGHT), false)
+        );
+        // The rook behind joins in after the first one takes
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5"), (pawn, true));
+        // Without it the rook is lost
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/6K1 w - - 0 1", "e2e5"), (pawn - Game::piece_value(ROOK), false));
+        // The king takes back last while nothing else defends the square
+        assert_eq!(see("6k1/8/8/8/1n6/3r3R/4K3/8 w - - 0 1", "h3d3"), (Game::piece_value(KNIGHT), true));
+        assert_eq!(see("6k1/8/8/5b2/1n6/3r3R/4K3/8 w - - 0 1", "h3d3"), (0, true));
+        // A quiet move to an attacked square loses the piece
+        assert_eq!(see("6k1/8/4p3/8/8/2N5/8/6K1 w - - 0 1", "c3d5").0, -Game::piece_value(KNIGHT));
+    }
+
+    #[test]
+    fn null_move_roundtrip() {
+        let fen = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2";
+        let mut game = Game::from_fen(fen).unwrap();
+        let m = game.move_from_uci("f7f5").unwrap();
+        game.make_move(&m);
+        assert_eq!(game.en_passant, Some(21));
+        let before = game.to_fen();
+        let key = polyglot_key(&game);
+
+        game.make_null_move();
+        assert!(!game.is_white_turn);
+        assert_eq!(game.en_passant, None);
+        assert_ne!(polyglot_key(&game), key);
+        assert!(matches!(game.move_log.last(), Some(Move::None)));
+
+        game.undo_null_move();
+        assert_eq!(game.to_fen(), before);
+        assert_eq!(polyglot_key(&game), key);
+    }
+
+    #[test]
+    fn transposition_table_shared_between_threads() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        let tt = &tt;
+
+        // Every thread stores its own keys while the others probe and store theirs
+        std::thread::scope(|scope| {
+            for thread in 0..4u64 {
+                scope.spawn(move || {
+                    for i in 0..256u64 {
+                        let key = (thread << 56) | (i << 20) | i;
+                        tt.store(key, (i % 32) as usize, Bound::Exact, i as i32, None, 0);
+                        assert!(tt.probe(key ^ 1 << 40, 0).is_none());
+                    }
+                });
+            }
+        });
+
+        let key = (3 << 56) | (255 << 20) | 255;
+        let hit = tt.probe(key, 0).unwrap();
+        assert_eq!((hit.depth, hit.score), (31, 255));
+
+        // The search side of a game clone is ready for move generation
+        let mut game = Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+        let moves = game.legal_moves();
+        let mut clone = game.clone();
+        assert_eq!(clone.legal_moves(), moves);
+    }
+
+    #[test]
+    fn search_limits_and_stop_handle() {
+        use std::time::Duration;
+
+        let depth = SearchLimits::depth(6);
+        assert_eq!(depth.max_depth(64), 6);
+        assert_eq!(SearchLimits::depth(100).max_depth(64), 64);
+        assert_eq!(SearchLimits::default().max_depth(64), 64);
+        assert!(depth.time_control().is_infinite());
+
+        let nodes = SearchLimits::nodes(10_000);
+        assert!(!nodes.nodes_reached(9_999));
+        assert!(nodes.nodes_reached(10_000));
+        assert!(!depth.nodes_reached(usize::MAX));
+
+        // A fixed time per move goes before the clock, an infinite search has neither
+        let clock = TimeControl::clock(Duration::from_secs(60), Duration::from_secs(1));
+        let limits = SearchLimits { movetime: Some(Duration::from_millis(500)), ..SearchLimits::clock(clock) };
+        assert_eq!(limits.time_control().movetime, Some(Duration::from_millis(500)));
+        assert_eq!(limits.time_control().wtime, Some(Duration::from_secs(60)));
+        assert!(SearchLimits { infinite: true, ..limits }.time_control().is_infinite());
+
+        let mate = SearchLimits::mate(3);
+        assert!(mate.mate_reached(2));
+        assert!(mate.mate_reached(3));
+        assert!(!mate.mate_reached(4));
+        assert!(!mate.mate_reached(-1));
+        assert!(!SearchLimits::infinite().mate_reached(1));
+        assert_eq!(mate.max_depth(64), 6);
+        assert_eq!(SearchLimits { depth: Some(10), ..mate }.max_depth(64), 6);
+        assert_eq!(SearchLimits::mate(40).max_depth(64), 64);
+
+        // Clones share the flag, so the handle can be passed to another thread
+        let stop = StopHandle::new();
+        let remote = stop.clone();
+        assert!(!stop.is_stopped());
+        std::thread::spawn(move || remote.stop()).join().unwrap();
+        assert!(stop.is_stopped());
+        stop.reset();
+        assert!(!stop.is_stopped());
+    }
+
+    #[test]
+    fn search_info_reports() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let info = SearchInfo {
+            depth: 8,
+            seldepth: 14,
+            multipv: 1,
+            score: Some(31),
+            nodes: 120_000,
+            nps: 480_000,
+            time: Duration::from_millis(250),
+            hashfull: 12,
+            tbhits: 0,
+            pv: vec![Standard(52, 36, PAWN, true), Standard(12, 28, PAWN, false)],
+            currmove: None,
+        };
+        assert_eq!(
+            info.to_uci(),
+            "info depth 8 seldepth 14 multipv 1 score cp 31 nodes 120000 nps 480000 hashfull 12 tbhits 0 time 250 pv e2e4 e7e5"
+        );
+
+        // Reports during an iteration have the root move instead of a score and line
+        let progress = SearchInfo { score: None, pv: vec![], currmove: Some((Standard(62, 45, KNIGHT, true), 3)), ..info.clone() };
+        assert!(progress.to_uci().ends_with("time 250 currmove g1f3 currmovenumber 3"));
+        assert!(!progress.to_uci().contains("score"));
+
+        let (sender, receiver) = mpsc::channel();
+        let control = SearchControl::new().info_channel(sender);
+        assert!(control.wants_info());
+        control.report(&info);
+        assert_eq!(receiver.try_recv(), Ok(info.clone()));
+
+        // Without a listener reports go nowhere, a closed channel doesn't fail the search
+        let quiet = SearchControl::with_stop(StopHandle::new());
+        assert!(!quiet.wants_info());
+        quiet.report(&info);
+        drop(receiver);
+        control.report(&info);
+    }
+
+    #[test]
+    fn searcher_sets_positions_and_searches() {
+        let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+        searcher.set_position(STARTING_POSITION, &["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6"]).unwrap();
+        assert!(searcher.game().is_white_turn);
+
+        let result = searcher.search(&SearchLimits::depth(3), &SearchControl::new());
+        assert_eq!(result.best_move().map(|m| m.to_uci()), Some("h5f7".to_string()));
+        assert_eq!(result.score(), Some(score::MATE - 1));
+
+        // A bad position leaves the last one in place
+        let error = searcher.set_position(STARTING_POSITION, &["e2e4", "e2e4"]).unwrap_err();
+        assert_eq!(error, PositionError::IllegalMove { ply: 2, uci: "e2e4".to_string() });
+        assert!(matches!(searcher.set_position("8/8/8 w - - 0 1", &[]), Err(PositionError::InvalidFen(_))));
+        assert!(searcher.game().is_white_turn);
+
+        // Node limited searches from a cleared table repeat exactly
+        searcher.set_position(STARTING_POSITION, &[]).unwrap();
+        searcher.clear();
+        let first = searcher.search(&SearchLimits::nodes(20_000), &SearchControl::new());
+        searcher.clear();
+        let second = searcher.search(&SearchLimits::nodes(20_000), &SearchControl::new());
+        assert_eq!(first.best_move(), second.best_move());
+        assert_eq!(first.pv, second.pv);
+        assert_eq!(first.nodes + first.qnodes, second.nodes + second.qnodes);
+
+        searcher.set_options(EngineOptions { hash_mb: 2, search: SearchOptions { multi_pv: 3, ..SearchOptions::default() } });
+        let result = searcher.search(&SearchLimits::depth(3), &SearchControl::new());
+        assert_eq!(result.lines, 3);
+        assert!(result.branches[..3].windows(2).all(|pair| pair[0].val >= pair[1].val));
+    }
+
+    #[test]
+    fn engine_handle_searches_in_the_background() {
+        let mut engine = EngineHandle::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+        engine.set_position(STARTING_POSITION, &["e2e4"]).unwrap();
+
+        let events = engine.go(SearchLimits::infinite());
+        assert!(matches!(events.recv(), Ok(SearchEvent::Info(_))));
+        assert!(engine.is_searching());
+        engine.stop();
+        assert!(!engine.is_searching());
+
+        let events: Vec<SearchEvent> = events.iter().collect();
+        let Some(SearchEvent::Finished(result)) = events.last() else { panic!("search ended without a result") };
+        let best = result.best_move().unwrap();
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap().move_from_uci(&best.to_uci()).is_some());
+
+        // Changing the position stops a running search, the next one searches the new position
+        let _running = engine.go(SearchLimits::infinite());
+        engine.set_position("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", &[]).unwrap();
+        engine.clear();
+        let events = engine.go(SearchLimits::depth(3));
+        engine.wait();
+        let result = events.iter().find_map(|event| match event {
+            SearchEvent::Finished(result) => Some(result),
+            SearchEvent::Info(_) => None,
+        });
+        assert_eq!(result.and_then(|r| r.best_move()).map(|m| m.to_uci()), Some("h5f7".to_string()));
+    }
+
+    #[test]
+    fn quiescence_sees_past_the_horizon() {
+        // Taking the pawn looks best to the static evaluation, but the pawn on d6 takes back
+        let fen = "4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        let capture = game.move_from_uci("f3e5").unwrap();
+        let moves = game.legal_moves();
+        let evals: Vec<i32> = moves.iter().map(|m| {
+            game.make_move(m);
+            let eval = game.evaluate_board();
+            game.undo_move();
+            eval
+        }).collect();
+        let capture_eval = evals[moves.iter().position(|m| *m == capture).unwrap()];
+        assert_eq!(evals.iter().max(), Some(&capture_eval));
+
+        let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+        searcher.set_position(fen, &[]).unwrap();
+        let result = searcher.search(&SearchLimits::depth(1), &SearchControl::new());
+        assert_ne!(result.best_move(), Some(capture));
+        assert!(result.score().unwrap() < capture_eval);
+
+        // Every leaf of a one ply search goes into quiescence, which counts its nodes apart from the main search
+        assert!(result.qnodes >= result.nodes);
+        assert!(result.nodes <= 2 * moves.len());
+    }
+
+    #[test]
+    fn principal_variation_is_a_legal_line() {
+        let play_out = |fen: &str, depth: usize| {
+            let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+            searcher.set_position(fen, &[]).unwrap();
+            let result = searcher.search(&SearchLimits::depth(depth), &SearchControl::new());
+            assert_eq!(result.pv.first().copied(), result.best_move());
+            assert_eq!(result.pv, result.branches[0].pv);
+
+            let mut game = Game::from_fen(fen).unwrap();
+            for m in &result.pv {
+                assert!(game.legal_moves().contains(m), "{} is not legal in {}", m.to_uci(), game.to_fen());
+                game.make_move(m);
+            }
+            (result, game)
+        };
+
+        let (result, _) = play_out(STARTING_POSITION, 5);
+        assert!(result.pv.len() >= 2);
+        assert_eq!(result.ponder_move(), Some(result.pv[1]));
+
+        // Mate in two, the line ends with black mated
+        let (result, mut game) = play_out(MATE_IN_TWO, 4);
+        assert_eq!(result.score(), Some(score::mate_in(3)));
+        assert_eq!(result.pv.len(), 3);
+        assert!(game.is_in_check() && game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn losing_captures_order_after_quiet_moves() {
+        let mut game = Game::from_fen("r3k2r/ppp2ppp/2n5/3pp3/4P3/2N2N2/PPP2PPP/R3K2R w KQkq - 0 1").unwrap();
+        let [losing, winning, quiet, other] = ["f3e5", "e4d5", "h2h3", "a2a3"].map(|uci| game.move_from_uci(uci).unwrap());
+        assert!(!game.board.see_ge(&losing, 0, &game.move_gen));
+
+        // However often a quiet move was tried before the one that cut off, it stays ahead of a losing capture
+        let mut ordering = MoveOrdering::new();
+        for _ in 0..100 {
+            ordering.cutoff(&other, 2, 40, &Move::None, &[quiet]);
+        }
+        assert!(ordering.history(&quiet) < -move_order::HISTORY_MAX / 2);
+        let score = |m: &Move| ordering.score(&game, m, 2, None, &Move::None);
+        assert!(score(&losing) < score(&quiet));
+        assert!(score(&quiet) < score(&other));
+        assert!(score(&other) < score(&winning));
+    }
+
+    const ITALIAN: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    // White to move mates in two
+    const MATE_IN_TWO: &str = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+
+    // Searches a position to a fixed depth with a small hash table of its own
+    fn search_fen(fen: &str, depth: usize, options: SearchOptions) -> SearchResult {
+        search_fen_reports(fen, depth, options).0
+    }
+
+    // The same search, along with everything it reported
+    fn search_fen_reports(fen: &str, depth: usize, options: SearchOptions) -> (SearchResult, Vec<SearchInfo>) {
+        let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, search: options });
+        searcher.set_position(fen, &[]).unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let result = searcher.search(&SearchLimits::depth(depth), &SearchControl::new().info_channel(sender));
+        (result, receiver.try_iter().collect())
+    }
+
+    #[test]
+    fn null_move_and_reduction_switches() {
+        let on = search_fen(ITALIAN, 6, SearchOptions::default());
+        assert!(on.null_move_cutoffs > 0 && on.lmr_researches > 0);
+        let off = search_fen(ITALIAN, 6, SearchOptions { null_move: false, late_move_reductions: false, ..SearchOptions::default() });
+        assert_eq!((off.null_move_cutoffs, off.lmr_researches), (0, 0));
+        assert!(off.nodes > on.nodes);
+
+        // Each switch turns off only its own technique
+        let no_null_move = search_fen(ITALIAN, 6, SearchOptions { null_move: false, ..SearchOptions::default() });
+        assert!(no_null_move.null_move_cutoffs == 0 && no_null_move.lmr_researches > 0);
+        let no_reductions = search_fen(ITALIAN, 6, SearchOptions { late_move_reductions: false, ..SearchOptions::default() });
+        assert!(no_reductions.null_move_cutoffs > 0 && no_reductions.lmr_researches == 0);
+
+        // Whoever moves in the trebuchet loses their pawn. With only kings and pawns there is no null move to hide that.
+        for fen in ["8/8/8/2Kp4/3Pk3/8/8/8 w - - 0 1", "8/8/8/2Kp4/3Pk3/8/8/8 b - - 0 1"] {
+            let with = search_fen(fen, 8, SearchOptions::default());
+            let without = search_fen(fen, 8, SearchOptions { null_move: false, ..SearchOptions::default() });
+            assert_eq!(with.null_move_cutoffs, 0);
+            assert_eq!((with.best_move(), with.score()), (without.best_move(), without.score()));
+            assert!(with.score().unwrap() < 0);
+        }
+    }
+
+    #[test]
+    fn forward_pruning_switches_and_margins() {
+        let count = |stats: &[usize]| stats.iter().sum::<usize>();
+        let fired = |stats: &[usize]| count(stats) > 0;
+
+        // Every technique cuts something in a quiet position, and only as close to the leaves as it is allowed
+        let default = search_fen(ITALIAN, 4, SearchOptions::default()).pruning;
+        assert!(fired(&default.reverse_futility) && fired(&default.razoring));
+        assert!(fired(&default.futility) && fired(&default.late_move));
+        assert!(!fired(&default.razoring[3..]) && !fired(&default.futility[5..]) && !fired(&default.late_move[5..]));
+
+        let off = SearchOptions { reverse_futility: false, razoring: false, futility: false, late_move_pruning: false, ..SearchOptions::default() };
+        assert_eq!(search_fen(ITALIAN, 4, off).pruning, PruningStats::default());
+
+        // Each switch only turns off its own technique
+        let pruning = search_fen(ITALIAN, 4, SearchOptions { razoring: false, ..SearchOptions::default() }).pruning;
+        assert!(!fired(&pruning.razoring) && fired(&pruning.reverse_futility) && fired(&pruning.futility));
+        let pruning = search_fen(ITALIAN, 4, SearchOptions { futility: false, ..SearchOptions::default() }).pruning;
+        assert!(!fired(&pruning.futility) && fired(&pruning.razoring) && fired(&pruning.late_move));
+
+        // Wider margins prune less
+        let wide = SearchOptions { reverse_futility_margin: 1000, razoring_margin: 1000, futility_margin: 1000, ..SearchOptions::default() };
+        let pruning = search_fen(ITALIAN, 4, wide).pruning;
+        assert!(count(&pruning.reverse_futility) < count(&default.reverse_futility));
+        assert!(count(&pruning.razoring) < count(&default.razoring));
+        assert!(count(&pruning.futility) < count(&default.futility));
+
+        // Pruning doesn't change the answer to a tactic at the same depth
+        for fen in [MATE_IN_TWO, "r3k2r/ppp2ppp/8/3N4/8/8/PPP2PPP/4K3 w - - 0 1"] {
+            let pruned = search_fen(fen, 5, SearchOptions::default());
+            let full = search_fen(fen, 5, off);
+            assert!(fired(&pruned.pruning.futility) || fired(&pruned.pruning.reverse_futility));
+            assert_eq!(pruned.best_move(), full.best_move());
+        }
+    }
+
+    #[test]
+    fn aspiration_windows_search_again_outside_the_window() {
+        let search = |fen: &str, depth: usize, aspiration_window: score::Score| {
+            let (result, reports) = search_fen_reports(fen, depth, SearchOptions { aspiration_window, ..SearchOptions::default() });
+            let scores: Vec<score::Score> = reports.iter().filter_map(|info| info.score).collect();
+            (result, scores)
+        };
+
+        // The fork wins less than the fifth iteration thought, so the sixth fails low. The mate found by the fourth
+        // iteration fails high.
+        for (fen, depth, fails_low) in [("r3k2r/ppp2ppp/8/3N4/8/8/PPP2PPP/4K3 w - - 0 1", 6, true), (MATE_IN_TWO, 4, false)] {
+            let (result, scores) = search(fen, depth, 40);
+            let (previous, last) = (scores[depth - 2], scores[depth - 1]);
+            assert!(if fails_low { last < previous - 40 } else { last > previous + 40 });
+            assert!(result.aspiration_researches > 0);
+
+            // The same answer as searching every iteration with a full window
+            let (full, _) = search(fen, depth, 0);
+            assert_eq!(full.aspiration_researches, 0);
+            assert_eq!((result.best_move(), result.score()), (full.best_move(), full.score()));
+        }
+    }
+
+    #[test]
+    fn mate_and_node_limits_end_the_search() {
+        // A mate search stops at 2n plies whether or not it finds one
+        let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+        searcher.set_position(MATE_IN_TWO, &[]).unwrap();
+        let result = searcher.search(&SearchLimits::mate(2), &SearchControl::new());
+        assert!(result.depth <= 4);
+        assert_eq!(result.score(), Some(score::mate_in(3)));
+
+        searcher.set_position(STARTING_POSITION, &[]).unwrap();
+        let result = searcher.search(&SearchLimits::mate(1), &SearchControl::new());
+        assert_eq!(result.depth, 2);
+        assert!(result.best_move().is_some());
+
+        // Every thread's nodes count toward the limit, not only the main thread's. A thread checks in every 2048
+        // nodes, so it can run up to that many past the limit before it is counted and again before it stops.
+        let threads = 4;
+        let options = SearchOptions { threads, ..SearchOptions::default() };
+        let mut searcher = Searcher::new(EngineOptions { hash_mb: 1, search: options });
+        searcher.set_position(STARTING_POSITION, &[]).unwrap();
+        let result = searcher.search(&SearchLimits::nodes(50_000), &SearchControl::new());
+        assert!(result.nodes + result.qnodes <= 50_000 + threads * 2 * 2048, "{} nodes", result.nodes + result.qnodes);
+    }
+
+    #[test]
+    fn multi_pv_reports_exact_lines() {
+        // The reports of the last iteration
+        let search = |fen: &str, depth: usize, multi_pv: usize| {
+            let (result, reports) = search_fen_reports(fen, depth, SearchOptions { multi_pv, ..SearchOptions::default() });
+            let reports: Vec<SearchInfo> = reports.into_iter().filter(|info| info.depth == result.depth).collect();
+            (result, reports)
+        };
+
+        let (result, reports) = search(ITALIAN, 5, 3);
+        assert_eq!(result.lines, 3);
+        let lines = &result.branches[..3];
+        assert!(lines.iter().all(|line| lines.iter().filter(|other| other.m == line.m).count() == 1));
+        assert!(lines.windows(2).all(|pair| pair[0].val >= pair[1].val));
+        assert!(result.branches[3..].iter().all(|branch| branch.val <= lines[2].val));
+
+        // Only the exact lines are reported, each with its own line
+        assert_eq!(reports.len(), 3);
+        for (line, info) in reports.iter().enumerate() {
+            assert_eq!(info.multipv, line + 1);
+            assert_eq!(info.score, Some(lines[line].val));
+            assert_eq!(info.pv, lines[line].pv);
+            assert_eq!(info.pv[0], lines[line].m);
+        }
+
+        // The best line is the one a single line search finds
+        let (single, _) = search(ITALIAN, 5, 1);
+        assert_eq!(single.lines, 1);
+        assert_eq!((single.best_move(), single.score()), (result.best_move(), result.score()));
+
+        // Behind a mate the other moves don't take its score as their bound, the second line is an ordinary score
+        let mate = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        let (result, _) = search(mate, 3, 1);
+        assert_eq!(result.score(), Some(score::mate_in(1)));
+        assert!(result.branches[1..].iter().all(|branch| branch.val < score::MATE_BOUND));
+        let (result, reports) = search(mate, 3, 2);
+        assert_eq!(result.lines, 2);
+        assert!(result.branches[1].val.abs() < score::MATE_BOUND);
+        assert_eq!(reports.iter().map(|info| info.score).collect::<Vec<_>>(), vec![Some(score::mate_in(1)), Some(result.branches[1].val)]);
+    }
+
+    #[test]
+    fn extensions_switches_and_limits() {
+        // The mate in two checks, and most checks leave a single reply
+        let result = search_fen(MATE_IN_TWO, 4, SearchOptions::default());
+        assert!(result.extensions.check > 0);
+        assert!(result.extensions.one_reply > 0);
+
+        let extensions = search_fen(ITALIAN, 8, SearchOptions::default()).extensions;
+        assert!(extensions.check > 0 && extensions.one_reply > 0 && extensions.singular > 0);
+        assert!(extensions.most_on_a_line.iter().all(|&most| most > 0));
+
+        // A line gets no more extensions of a kind than its limit, the rest are counted as limited
+        let limits = SearchOptions {
+            max_check_extensions: 2,
+            max_one_reply_extensions: 1,
+            max_singular_extensions: 1,
+            ..SearchOptions::default()
+        };
+        let limited = search_fen(ITALIAN, 8, limits).extensions;
+        assert!(limited.most_on_a_line[0] <= 2 && limited.most_on_a_line[1] <= 1 && limited.most_on_a_line[2] <= 1);
+        assert!(limited.limited > extensions.limited);
+        let none = SearchOptions { max_check_extensions: 0, ..SearchOptions::default() };
+        assert_eq!(search_fen(ITALIAN, 8, none).extensions.check, 0);
+
+        // Without caps a line is still only extended in its first 2 * depth plies, and never gets past the ply limit
+        let uncapped = SearchOptions {
+            max_check_extensions: 1000,
+            max_one_reply_extensions: 1000,
+            max_singular_extensions: 1000,
+            ..SearchOptions::default()
+        };
+        for (fen, depth) in [(ITALIAN, 10), (MATE_IN_TWO, 6)] {
+            let result = search_fen(fen, depth, uncapped);
+            assert!(result.extensions.most_on_a_line.iter().all(|&most| most <= 2 * depth));
+            assert!(result.seldepth < 128);
+            assert_eq!(result.best_move(), search_fen(fen, depth, SearchOptions::default()).best_move());
+        }
+
+        // Each switch turned off leaves its counter at zero
+        let off = search_fen(ITALIAN, 8, SearchOptions { check_extension: false, ..SearchOptions::default() }).extensions;
+        assert_eq!((off.check, off.most_on_a_line[0]), (0, 0));
+        let off = search_fen(ITALIAN, 8, SearchOptions { one_reply_extension: false, ..SearchOptions::default() }).extensions;
+        assert_eq!((off.one_reply, off.most_on_a_line[1]), (0, 0));
+        let off = search_fen(ITALIAN, 8, SearchOptions { singular_extension: false, ..SearchOptions::default() }).extensions;
+        assert_eq!((off.singular, off.singular_searches, off.most_on_a_line[2]), (0, 0, 0));
+        let off = search_fen(MATE_IN_TWO, 4, SearchOptions { one_reply_extension: false, ..SearchOptions::default() }).extensions;
+        assert_eq!(off.one_reply, 0);
+    }
+
+    #[test]
+    fn contempt_and_draw_rules() {
+        let search = |searcher: &mut Searcher, moves: &[&str], depth: usize, contempt: score::Score, analysis| {
+            let options = SearchOptions { contempt, analysis, ..SearchOptions::default() };
+            searcher.set_options(EngineOptions { hash_mb: 1, search: options });
+            searcher.set_position(STARTING_POSITION, moves).unwrap();
+            let result = searcher.search(&SearchLimits::depth(depth), &SearchControl::new());
+            (result.best_move().map(|m| m.to_uci()), result.score().unwrap())
+        };
+        let fresh = || Searcher::new(EngineOptions { hash_mb: 1, ..EngineOptions::default() });
+        // A draw scores the contempt against the side at the root, give or take a point
+        let draw = |contempt: score::Score| -contempt - 1..=-contempt + 1;
+        let g1f3 = Some("g1f3".to_string());
+
+        // The knights went out and back twice, g1f3 repeats a position for the third time. Equality is a draw. With
+        // contempt the engine plays on at a cost, against a stronger opponent it takes the draw.
+        let cycle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let twice = [cycle, cycle].concat();
+        let (best, val) = search(&mut fresh(), &twice, 6, 0, false);
+        assert!(best == g1f3 && draw(0).contains(&val), "{:?} {}", best, val);
+        let (best, val) = search(&mut fresh(), &twice, 6, 500, false);
+        assert_ne!(best, g1f3);
+        assert!(val > -500);
+        let (best, val) = search(&mut fresh(), &twice, 6, -500, false);
+        assert!(best == g1f3 && draw(-500).contains(&val), "{:?} {}", best, val);
+        let (best, val) = search(&mut fresh(), &twice, 6, 500, true);
+        assert!(best == g1f3 && draw(0).contains(&val), "{:?} {}", best, val);
+
+        // Threefold: a position from before the root has to have been there twice, after one trip g1f3 isn't a draw yet
+        let (best, val) = search(&mut fresh(), &cycle, 1, -500, false);
+        assert_ne!(best, g1f3);
+        assert!(val < *draw(-500).start());
+        let (best, val) = search(&mut fresh(), &twice, 1, -500, false);
+        assert!(best == g1f3 && draw(-500).contains(&val), "{:?} {}", best, val);
+
+        // Fifty moves: with the clock at 99 every move but a capture or pawn move draws
+        let result = search_fen("8/8/8/4k3/8/8/8/4K2Q w - - 99 80", 6, SearchOptions::default());
+        assert!(draw(0).contains(&result.score().unwrap()));
+        let result = search_fen("8/8/8/4k3/8/8/8/4K2Q w - - 0 80", 6, SearchOptions::default());
+        assert!(result.score().unwrap() > 1000);
+        let result = search_fen("8/8/8/4k3/8/8/4P3/4K2Q w - - 99 80", 6, SearchOptions::default());
+        assert!(result.best_move().is_some_and(|m| m.to_uci().starts_with("e2")));
+        assert!(result.score().unwrap() > 1000);
+
+        // Scores with contempt stay out of the way of a later search without it
+        let mut searcher = fresh();
+        let (best, val) = search(&mut searcher, &twice, 6, -500, false);
+        assert!(best == g1f3 && draw(-500).contains(&val), "{:?} {}", best, val);
+        let (best, val) = search(&mut searcher, &twice, 6, 0, false);
+        assert!(best == g1f3 && draw(0).contains(&val), "{:?} {}", best, val);
+        // Black can repeat with f6g8, a search that avoided the draw doesn't keep the next one from taking it
+        let black = &twice[..7];
+        let f6g8 = Some("f6g8".to_string());
+        let (best, _) = search(&mut searcher, black, 6, 500, false);
+        assert_ne!(best, f6g8);
+        let (best, val) = search(&mut searcher, black, 6, -500, false);
+        assert!(best == f6g8 && draw(-500).contains(&val), "{:?} {}", best, val);
+    }
+
+    #[test]
+    fn engine_handle_stops_an_infinite_search_promptly() {
+        use std::time::{Duration, Instant};
+
+        let options = SearchOptions { threads: 2, ..SearchOptions::default() };
+        let mut engine = EngineHandle::new(EngineOptions { hash_mb: 1, search: options });
+        engine.set_position(ITALIAN, &[]).unwrap();
+
+        // Deep enough into the search that an iteration takes a while, stop still ends it within a few thousand nodes
+        let events = engine.go(SearchLimits::infinite());
+        std::thread::sleep(Duration::from_millis(300));
+        let start = Instant::now();
+        engine.stop();
+        assert!(start.elapsed() < Duration::from_secs(1), "stopping took {:?}", start.elapsed());
+
+        let events: Vec<SearchEvent> = events.iter().collect();
+        let Some(SearchEvent::Finished(result)) = events.last() else { panic!("search ended with {:?}", events.last()) };
+        assert!(events[..events.len() - 1].iter().all(|event| matches!(event, SearchEvent::Info(_))), "{:?}", events);
+        let best = result.best_move().unwrap();
+        assert!(Game::from_fen(ITALIAN).unwrap().legal_moves().contains(&best), "{:?}", result);
+    }
+
 }
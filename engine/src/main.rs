@@ -4,9 +4,26 @@ mod magic;
 mod game;
 mod board;
 mod debug;
+mod fen;
 mod eval_board;
 mod utils;
 mod move_list;
+mod packed;
+mod polyglot;
+mod pgn;
+mod book_builder;
+mod syzygy;
+mod endgame;
+mod render;
+mod time_manager;
+mod move_order;
+mod score;
+mod transposition_table;
+mod search_limits;
+mod search_info;
+mod searcher;
+#[cfg(feature = "serde")]
+mod serialization;
 
 use std::{env, thread};
 
@@ -15,7 +32,7 @@ use std::ops::Index;
 use std::str::Chars;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender, TryRecvError};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use minifb::{Key, Window, WindowOptions};
 use raylib::color::Color;
 use raylib::drawing::RaylibDraw;
@@ -24,8 +41,16 @@ use raylib::ffi::{MouseButton, Vector2};
 use raylib::prelude::Image;
 use crate::board::board::Move;
 use crate::board::board::Move::{Promotion, Standard};
-use crate::engine::engine::{Branch, Engine};
+use crate::engine::engine::{Branch, Engine, SearchOptions};
 use crate::game::game::Game;
+use crate::polyglot::polyglot::{PolyglotBook, SelectionPolicy};
+use crate::syzygy::syzygy::Tablebase;
+use crate::endgame::endgame::EndgameTables;
+use crate::search_limits::search_limits::SearchLimits;
+use crate::search_info::search_info::SearchControl;
+use crate::time_manager::time_manager::TimeControl;
+use crate::transposition_table::transposition_table::TranspositionTable;
+use crate::score::score::to_uci;
 
 
 const WIDTH: usize = 640;
@@ -34,7 +59,34 @@ const HEIGHT: usize = 360;
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let _depth = if args.len() > 1 {args[1].parse::<usize>().unwrap()} else {4};
+    let depth = if args.len() > 1 {args[1].parse::<usize>().unwrap()} else {4};
+
+    let book = args.get(2).map(|path| PolyglotBook::open(path).expect("Could not read opening book"));
+
+    // ENGINE_COLOR=black lets the engine play black, by default it plays white
+    let engine_white = env::var("ENGINE_COLOR").map_or(true, |color| color != "black");
+
+    // SYZYGY_PATH lists the tablebase directories, SYZYGY_PROBE_LIMIT caps the pieces of the probed positions
+    let tablebase = env::var("SYZYGY_PATH").ok().map(|path| {
+        let mut tablebase = Tablebase::open(path).expect("Could not read tablebase directory");
+        if let Some(limit) = env::var("SYZYGY_PROBE_LIMIT").ok().and_then(|limit| limit.parse().ok()) {
+            tablebase.set_probe_limit(limit);
+        }
+        println!("Found {} tablebases, probing up to {} pieces", tablebase.table_count(), tablebase.max_pieces());
+        tablebase
+    });
+
+    // ENDGAME_PATH is a directory of tables written by solve_endgame
+    let endgames = env::var("ENDGAME_PATH").ok().map(|path| {
+        let endgames = EndgameTables::open_dir(path).expect("Could not read endgame tables");
+        println!("Found {} endgame tables", endgames.len());
+        endgames
+    });
+
+    // ENGINE_CLOCK="300+2" gives the engine 300 seconds plus 2 per move, ENGINE_MOVETIME a fixed number of milliseconds per move
+    let time_control = env::var("ENGINE_MOVETIME").ok().and_then(|ms| ms.parse().ok()).map(|ms| TimeControl::movetime(Duration::from_millis(ms)))
+        .or_else(|| env::var("ENGINE_CLOCK").ok().map(|clock| TimeControl::parse(&clock).expect("ENGINE_CLOCK should look like 300+2")))
+        .unwrap_or_else(TimeControl::infinite);
 
     let (move_list_sender, move_list_reciever) = mpsc::channel();
 
@@ -44,7 +96,7 @@ fn main() {
 
 
     let handle = thread::spawn(move || {
-        do_game_white(&move_list_sender, &board_sender, &move_reciever, 6);
+        do_game(&move_list_sender, &board_sender, &move_reciever, depth, engine_white, time_control, book, tablebase, endgames);
     });
 
     graphics(&move_list_reciever, &board_reciever, &move_sender)
@@ -73,17 +125,24 @@ pub fn read_line() -> i32 {
     num
 }
 
-pub fn print_branches(branches: &Vec<Branch>) -> () {
+// The first lines branches have exact scores and their own lines, the scores of the rest are bounds
+pub fn print_branches(branches: &Vec<Branch>, lines: usize) -> () {
     for (i, branch) in branches.iter().enumerate() {
-        println!("{}: {} with: {}", i, branch.m.to_printable(), branch.val);
+        if i < lines {
+            let pv: Vec<String> = branch.pv.iter().map(|m| m.to_uci()).collect();
+            println!("{}: {} with: {} ({})", i, branch.m.to_printable(), to_uci(branch.val), pv.join(" "));
+        } else {
+            println!("{}: {} with: at most {}", i, branch.m.to_printable(), to_uci(branch.val));
+        }
     }
 }
 
-pub fn branch_to_moves(branches: &Vec<Branch>) -> Vec<(Move, Option<i32>)> {
+// Only exact scores are shown
+pub fn branch_to_moves(branches: &Vec<Branch>, lines: usize) -> Vec<(Move, Option<i32>)> {
     let mut moves = vec![];
 
-    for branch in branches.iter() {
-        moves.push((branch.m, Some(branch.val)))
+    for (i, branch) in branches.iter().enumerate() {
+        moves.push((branch.m, if i < lines { Some(branch.val) } else { None }))
     }
 
     return moves
@@ -99,7 +158,7 @@ pub fn move_list_to_val_move_list(m: &Vec<Move>) -> Vec<(Move, Option<i32>)> {
     return moves
 }
 
-pub fn do_game_white(move_list_sender: &Sender<Vec<(Move, Option<i32>)>>, board_sender: &Sender<[u64; 12]>, move_reciever: &Receiver<Move>, depth: usize) {
+pub fn do_game(move_list_sender: &Sender<Vec<(Move, Option<i32>)>>, board_sender: &Sender<[u64; 12]>, move_reciever: &Receiver<Move>, depth: usize, engine_white: bool, mut time_control: TimeControl, book: Option<PolyglotBook>, tablebase: Option<Tablebase>, endgames: Option<EndgameTables>) {
 
     // "r1bqkbnr/pppp1p1p/8/4P1p1/8/2N5/PPP1PPPP/R1BQKB1R"
     // "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"
@@ -108,6 +167,41 @@ pub fn do_game_white(move_list_sender: &Sender<Vec<(Move, Option<i32>)>>, board_
 
     let mut leaves = 0;
 
+    // ENGINE_HASH is the size of the transposition table in megabytes
+    let hash_mb = env::var("ENGINE_HASH").ok().and_then(|mb| mb.parse().ok()).unwrap_or(64);
+    let mut tt = TranspositionTable::new(hash_mb);
+
+    // ENGINE_NULL_MOVE=0 and ENGINE_LMR=0 switch off null move pruning and late move reductions,
+    // ENGINE_PRUNING=0 futility pruning, razoring and late move pruning
+    let enabled = |name: &str| env::var(name).map_or(true, |value| value != "0");
+    let pruning = enabled("ENGINE_PRUNING");
+    // ENGINE_THREADS is the number of search threads, ENGINE_MULTIPV the number of best moves with exact scores
+    let threads = env::var("ENGINE_THREADS").ok().and_then(|threads| threads.parse().ok()).unwrap_or(1);
+    let multi_pv = env::var("ENGINE_MULTIPV").ok().and_then(|lines| lines.parse().ok()).unwrap_or(1);
+    // ENGINE_CONTEMPT is what a draw is worth less than equality to the engine, ENGINE_ANALYSIS=1 scores draws as equal
+    let contempt = env::var("ENGINE_CONTEMPT").ok().and_then(|contempt| contempt.parse().ok()).unwrap_or(0);
+    let analysis = env::var("ENGINE_ANALYSIS").is_ok_and(|value| value == "1");
+    let options = SearchOptions {
+        null_move: enabled("ENGINE_NULL_MOVE"),
+        late_move_reductions: enabled("ENGINE_LMR"),
+        reverse_futility: pruning,
+        razoring: pruning,
+        futility: pruning,
+        late_move_pruning: pruning,
+        threads,
+        multi_pv,
+        contempt,
+        analysis,
+        ..SearchOptions::default()
+    };
+
+    // ENGINE_NODES limits every search to that many nodes, which makes the engine's moves reproducible
+    let nodes = env::var("ENGINE_NODES").ok().and_then(|nodes| nodes.parse().ok());
+    // Progress of every search is printed as UCI info lines
+    let control = SearchControl::new().on_info(|info| println!("{}", info.to_uci()));
+
+    let mut book_policy = SelectionPolicy::weighted_random(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64);
+
     loop {
         let boards = [
             game.board.white_pawn_board, game.board.white_knight_board, game.board.white_bishop_board, game.board.white_rook_board, game.board.white_queen_board, game.board.white_king_board,
@@ -117,19 +211,74 @@ pub fn do_game_white(move_list_sender: &Sender<Vec<(Move, Option<i32>)>>, board_
         board_sender.send(boards);
 
 
-        if game.is_white_turn {
+        let book_move = match &book {
+            Some(book) if game.is_white_turn == engine_white => book.select(&mut game, &mut book_policy),
+            _ => None
+        };
+
+        if let Some(book_move) = book_move {
+            debug::debug::print_board(&game);
+            println!("Book move: {} (weight {})", book_move.m.to_uci(), book_move.weight);
+
+            move_list_sender.send(vec![(book_move.m, None)]);
+
+            let m = move_reciever.recv().unwrap();
+
+            if m == Move::None {
+                game.undo_move()
+            } else {
+                game.make_move(&m);
+            }
+        } else if game.is_white_turn == engine_white {
             debug::debug::print_board(&game);
             let start = Instant::now();
-            let (moves, leafs) = Engine::get_sorted_moves(&mut game, true, depth);
-            println!("Leaves after {} moves: {}", depth, leafs);
-            leaves += leafs;
-            println!("miliseconds elapsed: {}", start.elapsed().as_millis());
+            // Under a clock or a node limit the depth is only bounded by those
+            let limits = SearchLimits {
+                depth: if time_control.is_infinite() && nodes.is_none() { Some(depth) } else { None },
+                nodes,
+                ..SearchLimits::clock(time_control)
+            };
+            let result = Engine::iterative_deepening(&mut game, &limits, &control, &mut tt, tablebase.as_ref(), endgames.as_ref(), &options);
+            let moves = result.branches;
+            println!(
+                "Leaves after {} moves ({} selective): {}, nodes {} + {} quiescence on {} threads, hash {} permille full, {} table hits",
+                result.depth, result.seldepth, result.leafs, result.nodes, result.qnodes, options.threads, tt.hashfull(), result.tbhits
+            );
+            println!(
+                "Cutoffs: {}, {:.1}% on the first move",
+                result.ordering.cutoffs, 100.0 * result.ordering.first_move_rate()
+            );
+            println!(
+                "Null move cutoffs: {}, reduced moves searched again: {}, root windows widened: {}",
+                result.null_move_cutoffs, result.lmr_researches, result.aspiration_researches
+            );
+            println!(
+                "Pruned by depth: reverse futility {:?}, razoring {:?}, futility {:?}, late moves {:?}",
+                result.pruning.reverse_futility, result.pruning.razoring, result.pruning.futility, result.pruning.late_move
+            );
+            println!(
+                "Extended: check {}, one reply {}, singular {} of {} tried, {} over the limit",
+                result.extensions.check, result.extensions.one_reply, result.extensions.singular,
+                result.extensions.singular_searches, result.extensions.limited
+            );
+            leaves += result.leafs;
+
+            let (clock, increment) = if engine_white {
+                (&mut time_control.wtime, time_control.winc)
+            } else {
+                (&mut time_control.btime, time_control.binc)
+            };
+            if let Some(left) = *clock {
+                let left = left.saturating_sub(start.elapsed()) + increment;
+                *clock = Some(left);
+                println!("Clock: {:.1}s", left.as_secs_f64());
+            }
 
-            print_branches(&moves);
+            print_branches(&moves, result.lines);
 
 
 
-            move_list_sender.send(branch_to_moves(&moves));
+            move_list_sender.send(branch_to_moves(&moves, result.lines));
 
             let m = move_reciever.recv().unwrap();
 
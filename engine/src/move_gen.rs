@@ -10,6 +10,7 @@ pub mod move_gen {
     
     use crate::magic::magic;
     use crate::magic::magic::Magic;
+    use crate::board::board::Board;
 
     pub enum Direction {
         South,
@@ -173,6 +174,21 @@ pub mod move_gen {
             }
         }
 
+        // Pieces of both colors attacking pos, sliders see through the squares missing from occupancy
+        pub fn attackers_to(&self, board: &Board, pos: usize, occupancy: u64) -> u64 {
+            let queens = board.white_queen_board | board.black_queen_board;
+            let rooks = board.white_rook_board | board.black_rook_board | queens;
+            let bishops = board.white_bishop_board | board.black_bishop_board | queens;
+
+            // A white pawn attacks pos from where a black pawn on pos would attack and the other way around
+            (self.black_pawn_attack_table[pos] & board.white_pawn_board)
+                | (self.white_pawn_attack_table[pos] & board.black_pawn_board)
+                | (self.knight_position_board[pos] & (board.white_knight_board | board.black_knight_board))
+                | (self.king_position_board[pos] & (board.white_king_board | board.black_king_board))
+                | (self.get_rook_moves(pos, occupancy) & rooks)
+                | (self.get_bishop_moves(pos, occupancy) & bishops)
+        }
+
         fn white_pawn_move(pos: usize, additive: usize) -> u64 {
             if additive > pos {
                 return 0
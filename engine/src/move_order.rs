@@ -0,0 +1,212 @@
+// Move ordering for the search.
+//
+// Moves are scored and searched best first: the hash move, captures and promotions that don't lose material by most
+// valuable victim and least valuable attacker, the two killer moves of the ply, the counter move to the opponent's
+// last move, the quiet moves by their history and last the captures that lose material in the exchange.
+//
+// The history is a butterfly table per side indexed by from and to square. A quiet move that causes a cutoff
+// gets a bonus and the quiet moves tried before it a malus. Updates are scaled by how far the entry already is
+// from zero (gravity), so entries stay within HISTORY_MAX and old results fade as new ones come in.
+// The tables belong to one search thread and start empty with every search.
+pub mod move_order {
+    use crate::board::board::{Move, Side};
+    use crate::game::game::Game;
+    use crate::move_gen::move_gen::PieceType::{KING, PAWN, QUEEN};
+    use crate::polyglot::polyglot::encode_move;
+
+    // Plies that have killer moves, deeper plies go without
+    pub const KILLER_PLIES: usize = 128;
+    pub const HISTORY_MAX: i32 = 16_384;
+
+    const TT_MOVE_SCORE: i32 = 4_000_000;
+    const CAPTURE_SCORE: i32 = 2_000_000;
+    const KILLER_SCORE: i32 = 1_000_000;
+    const COUNTER_SCORE: i32 = 999_000;
+    const BAD_CAPTURE_SCORE: i32 = -2_000_000;
+
+    // Why a move was ordered where it was
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MoveKind {
+        TtMove,
+        Capture, // captures and promotions
+        Killer,
+        Counter,
+        Quiet,
+    }
+
+    // Counts of the nodes that failed high and the move that caused it
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct OrderingStats {
+        pub cutoffs: u64,
+        pub first_move_cutoffs: u64,
+        pub tt_move_cutoffs: u64,
+        pub capture_cutoffs: u64,
+        pub killer_cutoffs: u64,
+        pub counter_cutoffs: u64,
+        pub quiet_cutoffs: u64,
+    }
+
+    impl OrderingStats {
+        // A fail high by the index-th move searched
+        pub fn record(&mut self, kind: MoveKind, index: usize) {
+            self.cutoffs += 1;
+            if index == 0 {
+                self.first_move_cutoffs += 1;
+            }
+            match kind {
+                MoveKind::TtMove => self.tt_move_cutoffs += 1,
+                MoveKind::Capture => self.capture_cutoffs += 1,
+                MoveKind::Killer => self.killer_cutoffs += 1,
+                MoveKind::Counter => self.counter_cutoffs += 1,
+                MoveKind::Quiet => self.quiet_cutoffs += 1,
+            }
+        }
+
+        // Share of the cutoffs caused by the first move searched, 1.0 is perfect ordering
+        pub fn first_move_rate(&self) -> f64 {
+            if self.cutoffs == 0 {
+                return 0.0
+            }
+            self.first_move_cutoffs as f64 / self.cutoffs as f64
+        }
+    }
+
+    // Most valuable victim, least valuable attacker: pawn takes queen comes first, queen takes pawn last
+    pub fn mvv_lva(m: &Move) -> i32 {
+        match m {
+            Move::Capture(_, _, piece, captured, _) => {
+                let victim = if *captured == KING { 10 * Game::piece_value(QUEEN) } else { Game::piece_value(*captured) };
+                10 * victim - Game::piece_value(*piece)
+            }
+            Move::Promotion(_, _, promotion, captured, _) => 10 * (Game::piece_value(*promotion) + Game::piece_value(*captured)),
+            _ => 0
+        }
+    }
+
+    // Quiet moves change no material, they are the ones killers, counters and the history are kept for
+    pub fn is_quiet(m: &Move) -> bool {
+        matches!(m, Move::Standard(..) | Move::Castle(..))
+    }
+
+    // Side, origin and destination of a move, castling as the king's move
+    fn butterfly(m: &Move) -> Option<(usize, usize, usize)> {
+        match *m {
+            Move::Standard(from, to, _, white) | Move::Capture(from, to, _, _, white) | Move::Promotion(from, to, _, _, white) => {
+                Some((white as usize, from as usize, to as usize))
+            }
+            Move::Castle(king, Side::Left, white) => Some((white as usize, king as usize, king as usize - 2)),
+            Move::Castle(king, Side::Right, white) => Some((white as usize, king as usize, king as usize + 2)),
+            Move::None => None
+        }
+    }
+
+    // Side, moved piece and destination of a move, the index of the counter move table
+    fn counter_index(m: &Move) -> Option<(usize, usize, usize)> {
+        let (white, _, to) = butterfly(m)?;
+        let piece = match *m {
+            Move::Standard(_, _, piece, _) | Move::Capture(_, _, piece, _, _) => piece,
+            Move::Promotion(..) => PAWN,
+            Move::Castle(..) => KING,
+            Move::None => return None
+        };
+        Some((white, piece as usize, to))
+    }
+
+    pub struct MoveOrdering {
+        killers: Vec<[Move; 2]>,
+        history: Vec<[[i32; 64]; 64]>, // by side, from and to
+        counter_moves: Vec<[[Move; 64]; 7]>, // by side, piece and destination of the previous move
+        pub stats: OrderingStats,
+    }
+
+    impl Default for MoveOrdering {
+        fn default() -> Self {
+            MoveOrdering::new()
+        }
+    }
+
+    impl MoveOrdering {
+        pub fn new() -> MoveOrdering {
+            MoveOrdering {
+                killers: vec![[Move::None; 2]; KILLER_PLIES],
+                history: vec![[[0; 64]; 64]; 2],
+                counter_moves: vec![[[Move::None; 64]; 7]; 2],
+                stats: OrderingStats::default(),
+            }
+        }
+
+        pub fn killers(&self, ply: usize) -> [Move; 2] {
+            self.killers.get(ply).copied().unwrap_or([Move::None; 2])
+        }
+
+        pub fn history(&self, m: &Move) -> i32 {
+            butterfly(m).map_or(0, |(white, from, to)| self.history[white][from][to])
+        }
+
+        // The move that refuted previous the last time it was played
+        pub fn counter_move(&self, previous: &Move) -> Move {
+            counter_index(previous).map_or(Move::None, |(white, piece, to)| self.counter_moves[white][piece][to])
+        }
+
+        pub fn kind(&self, m: &Move, ply: usize, tt_move: Option<u16>, previous: &Move) -> MoveKind {
+            if tt_move.is_some_and(|tt_move| tt_move == encode_move(m)) {
+                MoveKind::TtMove
+            } else if !is_quiet(m) {
+                MoveKind::Capture
+            } else if self.killers(ply).contains(m) {
+                MoveKind::Killer
+            } else if self.counter_move(previous) == *m {
+                MoveKind::Counter
+            } else {
+                MoveKind::Quiet
+            }
+        }
+
+        pub fn score(&self, game: &Game, m: &Move, ply: usize, tt_move: Option<u16>, previous: &Move) -> i32 {
+            match self.kind(m, ply, tt_move, previous) {
+                MoveKind::TtMove => TT_MOVE_SCORE,
+                MoveKind::Capture if game.board.see_ge(m, 0, &game.move_gen) => CAPTURE_SCORE + mvv_lva(m),
+                MoveKind::Capture => BAD_CAPTURE_SCORE + mvv_lva(m),
+                MoveKind::Killer => if self.killers(ply)[0] == *m { KILLER_SCORE } else { KILLER_SCORE - 1 },
+                MoveKind::Counter => COUNTER_SCORE,
+                MoveKind::Quiet => self.history(m),
+            }
+        }
+
+        // Best first, previous is the opponent's last move
+        pub fn sort(&self, game: &Game, moves: &mut [Move], ply: usize, tt_move: Option<u16>, previous: &Move) {
+            moves.sort_by_cached_key(|m| -self.score(game, m, ply, tt_move, previous));
+        }
+
+        // Learns from a fail high of m, quiets are the quiet moves searched before it
+        pub fn cutoff(&mut self, m: &Move, ply: usize, depth: usize, previous: &Move, quiets: &[Move]) {
+            if !is_quiet(m) {
+                return
+            }
+
+            if let Some(killers) = self.killers.get_mut(ply) {
+                if killers[0] != *m {
+                    killers[1] = killers[0];
+                    killers[0] = *m;
+                }
+            }
+
+            if let Some((white, piece, to)) = counter_index(previous) {
+                self.counter_moves[white][piece][to] = *m;
+            }
+
+            let bonus = (depth * depth).min(HISTORY_MAX as usize / 16) as i32;
+            self.update_history(m, bonus);
+            for quiet in quiets.iter().filter(|quiet| *quiet != m) {
+                self.update_history(quiet, -bonus);
+            }
+        }
+
+        fn update_history(&mut self, m: &Move, bonus: i32) {
+            if let Some((white, from, to)) = butterfly(m) {
+                let entry = &mut self.history[white][from][to];
+                *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Packed 32 byte position records for large training data sets.
+//
+// Layout (multi-byte fields little endian, squares use the engine's indexing with a8 = 0):
+//   0..8    occupancy bitboard
+//   8..24   one nibble per occupied square, in ascending square order, low nibble first.
+//           bits 0-2: 0 pawn, 1 knight, 2 bishop, 3 rook, 4 queen, 5 king, 6 rook that can still castle
+//           bit 3: black piece
+//   24      bit 7: black to move, bits 0-6: en passant square or 64 when there is none
+//   25      halfmove clock (rule 50), saturating at 255
+//   26..28  fullmove number
+//   28..30  score in centipawns from white's point of view
+//   30      game result: 0 black won, 1 draw, 2 white won
+//   31      flags: bit 0 score present, bit 1 result present
+pub mod packed {
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::Formatter;
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufReader, BufWriter, Read, Write};
+    use std::path::Path;
+    use crate::board::board::Board;
+    use crate::game::game::{CastlingRights, Game};
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::utils::utils;
+
+    pub const PACKED_SIZE: usize = 32;
+
+    const NO_EN_PASSANT: u8 = 64;
+    const CASTLING_ROOK: u8 = 6;
+    const HAS_SCORE: u8 = 1;
+    const HAS_RESULT: u8 = 2;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum GameResult {
+        BlackWin = 0,
+        Draw = 1,
+        WhiteWin = 2,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PackError {
+        TooManyPieces(u32),
+        InvalidPiece(u8),
+        InvalidEnPassant(u8),
+        InvalidResult(u8),
+    }
+
+    impl fmt::Display for PackError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                PackError::TooManyPieces(n) => write!(f, "{} pieces do not fit in a packed position (max 32)", n),
+                PackError::InvalidPiece(code) => write!(f, "invalid piece nibble {}", code),
+                PackError::InvalidEnPassant(pos) => write!(f, "invalid en passant square {}", pos),
+                PackError::InvalidResult(code) => write!(f, "invalid game result {}", code),
+            }
+        }
+    }
+
+    impl Error for PackError {}
+
+    impl From<PackError> for io::Error {
+        fn from(e: PackError) -> Self {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        }
+    }
+
+    // A position with everything the packed format stores
+    #[derive(Debug, Clone, Copy)]
+    pub struct TrainingPosition {
+        pub board: Board,
+        pub is_white_turn: bool,
+        pub castling_rights: CastlingRights,
+        pub en_passant: Option<u8>,
+        pub halfmove_clock: u32,
+        pub fullmove_number: u32,
+        pub score: Option<i16>,
+        pub result: Option<GameResult>,
+    }
+
+    fn piece_nibble(piece: PieceType) -> u8 {
+        match piece {
+            PAWN => 0,
+            KNIGHT => 1,
+            BISHOP => 2,
+            ROOK => 3,
+            QUEEN => 4,
+            KING => 5,
+            PieceType::None => panic!("Cant pack a None piece")
+        }
+    }
+
+    fn nibble_piece(code: u8) -> Option<PieceType> {
+        match code {
+            0 => Some(PAWN),
+            1 => Some(KNIGHT),
+            2 => Some(BISHOP),
+            3 | CASTLING_ROOK => Some(ROOK),
+            4 => Some(QUEEN),
+            5 => Some(KING),
+            _ => None
+        }
+    }
+
+    impl TrainingPosition {
+        pub fn from_game(game: &Game) -> TrainingPosition {
+            TrainingPosition {
+                board: game.board,
+                is_white_turn: game.is_white_turn,
+                castling_rights: game.castling_rights,
+                en_passant: game.en_passant,
+                halfmove_clock: game.halfmove_clock,
+                fullmove_number: game.fullmove_number,
+                score: None,
+                result: None,
+            }
+        }
+
+        // Loads the position into an existing game, so the move generator tables are reused
+        pub fn apply_to(&self, game: &mut Game) {
+            game.board = self.board;
+            game.is_white_turn = self.is_white_turn;
+            game.castling_rights = self.castling_rights;
+            game.en_passant = self.en_passant;
+            game.halfmove_clock = self.halfmove_clock;
+            game.fullmove_number = self.fullmove_number;
+            game.move_log.clear();
+            game.state_log.clear();
+        }
+
+        // (rook square, king square, white, right) for each castling right
+        fn castling_rooks(&self) -> [(usize, usize, bool, bool); 4] {
+            [
+                (56, 60, true, self.castling_rights.white_left),
+                (63, 60, true, self.castling_rights.white_right),
+                (0, 4, false, self.castling_rights.black_left),
+                (7, 4, false, self.castling_rights.black_right),
+            ]
+        }
+
+        pub fn pack(&self) -> Result<[u8; PACKED_SIZE], PackError> {
+            let mut bytes = [0_u8; PACKED_SIZE];
+            let occupancy = self.board.get_board_value();
+
+            if occupancy.count_ones() > 32 {
+                return Err(PackError::TooManyPieces(occupancy.count_ones()))
+            }
+
+            let mut castling_rooks = 0_u64;
+            for (rook, king, is_white, right) in self.castling_rooks() {
+                let rook_board = if is_white { self.board.white_rook_board } else { self.board.black_rook_board };
+                let king_board = if is_white { self.board.white_king_board } else { self.board.black_king_board };
+                if right && rook_board & utils::POSITIONS[rook] > 0 && king_board & utils::POSITIONS[king] > 0 {
+                    castling_rooks |= utils::POSITIONS[rook];
+                }
+            }
+
+            bytes[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+            let mut squares = occupancy;
+            for i in 0..(occupancy.count_ones() as usize) {
+                let pos = utils::pop_lsb(&mut squares);
+                let (piece, is_white) = self.board.piece_at(pos as u8).unwrap();
+
+                let mut nibble = if castling_rooks & utils::POSITIONS[pos] > 0 { CASTLING_ROOK } else { piece_nibble(piece) };
+                if !is_white {
+                    nibble |= 8;
+                }
+
+                bytes[8 + i / 2] |= nibble << (4 * (i % 2));
+            }
+
+            bytes[24] = self.en_passant.unwrap_or(NO_EN_PASSANT) | if self.is_white_turn { 0 } else { 0x80 };
+            bytes[25] = self.halfmove_clock.min(u8::MAX as u32) as u8;
+            bytes[26..28].copy_from_slice(&(self.fullmove_number.min(u16::MAX as u32) as u16).to_le_bytes());
+
+            if let Some(score) = self.score {
+                bytes[28..30].copy_from_slice(&score.to_le_bytes());
+                bytes[31] |= HAS_SCORE;
+            }
+
+            if let Some(result) = self.result {
+                bytes[30] = result as u8;
+                bytes[31] |= HAS_RESULT;
+            }
+
+            Ok(bytes)
+        }
+
+        pub fn unpack(bytes: &[u8; PACKED_SIZE]) -> Result<TrainingPosition, PackError> {
+            let occupancy = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+            if occupancy.count_ones() > 32 {
+                return Err(PackError::TooManyPieces(occupancy.count_ones()))
+            }
+
+            let mut board = Board::empty();
+            let mut castling_rights = CastlingRights::none();
+
+            let mut squares = occupancy;
+            for i in 0..(occupancy.count_ones() as usize) {
+                let pos = utils::pop_lsb(&mut squares);
+                let nibble = bytes[8 + i / 2] >> (4 * (i % 2)) & 0xf;
+                let code = nibble & 7;
+                let is_white = nibble & 8 == 0;
+
+                let piece = nibble_piece(code).ok_or(PackError::InvalidPiece(nibble))?;
+                *board.get_board(piece, is_white) |= utils::POSITIONS[pos];
+
+                if piece == KING {
+                    if is_white { board.white_king_position = pos as u8 } else { board.black_king_position = pos as u8 }
+                }
+
+                if code == CASTLING_ROOK {
+                    match (pos, is_white) {
+                        (56, true) => castling_rights.white_left = true,
+                        (63, true) => castling_rights.white_right = true,
+                        (0, false) => castling_rights.black_left = true,
+                        (7, false) => castling_rights.black_right = true,
+                        _ => return Err(PackError::InvalidPiece(nibble))
+                    }
+                }
+            }
+
+            let en_passant = match bytes[24] & 0x7f {
+                NO_EN_PASSANT => None,
+                pos if pos < 64 => Some(pos),
+                pos => return Err(PackError::InvalidEnPassant(pos))
+            };
+
+            let flags = bytes[31];
+            let result = if flags & HAS_RESULT > 0 {
+                Some(match bytes[30] {
+                    0 => GameResult::BlackWin,
+                    1 => GameResult::Draw,
+                    2 => GameResult::WhiteWin,
+                    code => return Err(PackError::InvalidResult(code))
+                })
+            } else {
+                None
+            };
+
+            Ok(TrainingPosition {
+                board,
+                is_white_turn: bytes[24] & 0x80 == 0,
+                castling_rights,
+                en_passant,
+                halfmove_clock: bytes[25] as u32,
+                fullmove_number: u16::from_le_bytes([bytes[26], bytes[27]]) as u32,
+                score: if flags & HAS_SCORE > 0 { Some(i16::from_le_bytes([bytes[28], bytes[29]])) } else { None },
+                result,
+            })
+        }
+    }
+
+    pub struct PackedWriter<W: Write> {
+        inner: W,
+        written: u64,
+    }
+
+    impl PackedWriter<BufWriter<File>> {
+        pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            Ok(PackedWriter::new(BufWriter::new(File::create(path)?)))
+        }
+    }
+
+    impl<W: Write> PackedWriter<W> {
+        pub fn new(inner: W) -> PackedWriter<W> {
+            PackedWriter { inner, written: 0 }
+        }
+
+        pub fn write(&mut self, position: &TrainingPosition) -> io::Result<()> {
+            self.inner.write_all(&position.pack()?)?;
+            self.written += 1;
+            Ok(())
+        }
+
+        pub fn written(&self) -> u64 {
+            self.written
+        }
+
+        pub fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+
+        pub fn into_inner(mut self) -> io::Result<W> {
+            self.inner.flush()?;
+            Ok(self.inner)
+        }
+    }
+
+    pub struct PackedReader<R: Read> {
+        inner: R,
+    }
+
+    impl PackedReader<BufReader<File>> {
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            Ok(PackedReader::new(BufReader::new(File::open(path)?)))
+        }
+    }
+
+    impl<R: Read> PackedReader<R> {
+        pub fn new(inner: R) -> PackedReader<R> {
+            PackedReader { inner }
+        }
+
+        // Ok(None) at a clean end of stream, an error if the stream stops inside a record
+        pub fn read_next(&mut self) -> io::Result<Option<TrainingPosition>> {
+            let mut bytes = [0_u8; PACKED_SIZE];
+            let mut filled = 0;
+
+            while filled < PACKED_SIZE {
+                match self.inner.read(&mut bytes[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e)
+                }
+            }
+
+            match filled {
+                0 => Ok(None),
+                PACKED_SIZE => Ok(Some(TrainingPosition::unpack(&bytes)?)),
+                _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated packed position"))
+            }
+        }
+    }
+
+    impl<R: Read> Iterator for PackedReader<R> {
+        type Item = io::Result<TrainingPosition>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.read_next().transpose()
+        }
+    }
+}
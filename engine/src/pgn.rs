@@ -0,0 +1,352 @@
+// Reading PGN game collections and replaying their SAN moves through Game.
+//
+// Only the main line is kept: comments, NAGs and variations are skipped.
+// The move generator has no en passant yet, so a game that plays one stops replaying at that move.
+pub mod pgn {
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::Formatter;
+    use std::fs::File;
+    use std::io;
+    use std::io::{BufRead, BufReader};
+    use std::iter::Peekable;
+    use std::path::Path;
+    use std::str::Chars;
+    use crate::board::board::Move;
+    use crate::board::board::Move::{Capture, Promotion, Standard};
+    use crate::fen::fen::{FenError, STARTING_POSITION};
+    use crate::game::game::Game;
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::packed::packed::GameResult;
+    use crate::utils::utils;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PgnError {
+        UnterminatedTag,
+        UnterminatedComment,
+        UnbalancedVariation,
+        InvalidFen(FenError),
+        IllegalMove { ply: usize, san: String },
+    }
+
+    impl fmt::Display for PgnError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                PgnError::UnterminatedTag => write!(f, "tag pair is missing its closing ']'"),
+                PgnError::UnterminatedComment => write!(f, "comment is missing its closing '}}'"),
+                PgnError::UnbalancedVariation => write!(f, "variation parentheses do not match"),
+                PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+                PgnError::IllegalMove { ply, san } => write!(f, "illegal move '{}' at ply {}", san, ply),
+            }
+        }
+    }
+
+    impl Error for PgnError {}
+
+    impl From<FenError> for PgnError {
+        fn from(e: FenError) -> Self {
+            PgnError::InvalidFen(e)
+        }
+    }
+
+    impl From<PgnError> for io::Error {
+        fn from(e: PgnError) -> Self {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct PgnGame {
+        pub tags: Vec<(String, String)>,
+        pub moves: Vec<String>,
+        pub result: Option<GameResult>,
+    }
+
+    fn parse_result(token: &str) -> Option<Option<GameResult>> {
+        match token {
+            "1-0" => Some(Some(GameResult::WhiteWin)),
+            "0-1" => Some(Some(GameResult::BlackWin)),
+            "1/2-1/2" => Some(Some(GameResult::Draw)),
+            "*" => Some(None),
+            _ => None
+        }
+    }
+
+    fn skip_line(chars: &mut Peekable<Chars>) {
+        for c in chars.by_ref() {
+            if c == '\n' {
+                break
+            }
+        }
+    }
+
+    fn read_tag(chars: &mut Peekable<Chars>) -> Result<(String, String), PgnError> {
+        let mut name = String::new();
+        let mut value = String::new();
+        let mut in_value = false;
+
+        loop {
+            match chars.next().ok_or(PgnError::UnterminatedTag)? {
+                '"' if in_value => {
+                    // Anything after the closing quote up to ']' is ignored
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            return Ok((name, value))
+                        }
+                    }
+                    return Err(PgnError::UnterminatedTag)
+                }
+                '\\' if in_value => value.push(chars.next().ok_or(PgnError::UnterminatedTag)?),
+                '"' => in_value = true,
+                ']' if !in_value => return Ok((name, value)),
+                c if in_value => value.push(c),
+                c if !c.is_whitespace() => name.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    impl PgnGame {
+        // Parses the text of a single game, tag pairs followed by movetext
+        pub fn parse(text: &str) -> Result<PgnGame, PgnError> {
+            let mut tags = vec![];
+            let mut moves = vec![];
+            let mut result = None;
+            let mut variation_depth = 0;
+            let mut chars = text.chars().peekable();
+
+            while let Some(&c) = chars.peek() {
+                match c {
+                    c if c.is_whitespace() => { chars.next(); }
+                    '[' => {
+                        chars.next();
+                        tags.push(read_tag(&mut chars)?);
+                    }
+                    '{' => {
+                        if !chars.by_ref().any(|c| c == '}') {
+                            return Err(PgnError::UnterminatedComment)
+                        }
+                    }
+                    ';' | '%' => skip_line(&mut chars),
+                    '(' => {
+                        chars.next();
+                        variation_depth += 1;
+                    }
+                    ')' => {
+                        chars.next();
+                        if variation_depth == 0 {
+                            return Err(PgnError::UnbalancedVariation)
+                        }
+                        variation_depth -= 1;
+                    }
+                    _ => {
+                        let mut token = String::new();
+                        while let Some(&c) = chars.peek() {
+                            if c.is_whitespace() || "{}()[];".contains(c) {
+                                break
+                            }
+                            token.push(c);
+                            chars.next();
+                        }
+
+                        if variation_depth > 0 || token.starts_with('$') {
+                            continue
+                        }
+
+                        if let Some(r) = parse_result(&token) {
+                            result = Some(r);
+                            continue
+                        }
+
+                        // Move numbers can be glued to the move, as in "12.Nf3" or "12...Nf3"
+                        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                        let san = san.trim_end_matches(['!', '?']);
+                        if !san.is_empty() {
+                            moves.push(san.to_string());
+                        }
+                    }
+                }
+            }
+
+            if variation_depth != 0 {
+                return Err(PgnError::UnbalancedVariation)
+            }
+
+            let mut game = PgnGame { tags, moves, result: None };
+            game.result = match result {
+                Some(r) => r,
+                None => game.tag("Result").and_then(parse_result).flatten()
+            };
+
+            Ok(game)
+        }
+
+        pub fn tag(&self, name: &str) -> Option<&str> {
+            self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+        }
+
+        // Loads the position the game starts from, the FEN tag if there is one
+        pub fn setup(&self, game: &mut Game) -> Result<(), PgnError> {
+            game.set_fen(self.tag("FEN").unwrap_or(STARTING_POSITION))?;
+            Ok(())
+        }
+
+        // Replays the whole game, failing on the first move the engine does not accept
+        pub fn replay(&self, game: &mut Game) -> Result<(), PgnError> {
+            self.setup(game)?;
+
+            for (ply, san) in self.moves.iter().enumerate() {
+                let m = game.move_from_san(san).ok_or_else(|| PgnError::IllegalMove { ply, san: san.clone() })?;
+                game.make_move(&m);
+            }
+
+            Ok(())
+        }
+    }
+
+    // Streams games out of a PGN file one at a time
+    pub struct PgnReader<R: BufRead> {
+        inner: R,
+        pending: Option<String>,
+    }
+
+    impl PgnReader<BufReader<File>> {
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            Ok(PgnReader::new(BufReader::new(File::open(path)?)))
+        }
+    }
+
+    impl<R: BufRead> PgnReader<R> {
+        pub fn new(inner: R) -> PgnReader<R> {
+            PgnReader { inner, pending: None }
+        }
+
+        // The raw text of the next game, a game ends where the tags of the next one start
+        fn read_game_text(&mut self) -> io::Result<Option<String>> {
+            let mut text = self.pending.take().unwrap_or_default();
+            let mut seen_moves = false;
+            let mut in_comment = false;
+
+            loop {
+                let mut line = String::new();
+                if self.inner.read_line(&mut line)? == 0 {
+                    break
+                }
+
+                let trimmed = line.trim_start();
+                if trimmed.starts_with('[') && seen_moves && !in_comment {
+                    self.pending = Some(line);
+                    break
+                }
+
+                if !in_comment && !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+                    seen_moves = true;
+                }
+
+                for c in line.chars() {
+                    match c {
+                        '{' => in_comment = true,
+                        '}' => in_comment = false,
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+
+                text.push_str(&line);
+            }
+
+            Ok(if text.trim().is_empty() { None } else { Some(text) })
+        }
+
+        pub fn read_next(&mut self) -> io::Result<Option<PgnGame>> {
+            match self.read_game_text()? {
+                Some(text) => Ok(Some(PgnGame::parse(&text)?)),
+                None => Ok(None)
+            }
+        }
+    }
+
+    impl<R: BufRead> Iterator for PgnReader<R> {
+        type Item = io::Result<PgnGame>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.read_next().transpose()
+        }
+    }
+
+    fn piece_from_letter(c: char) -> Option<PieceType> {
+        match c {
+            'N' => Some(KNIGHT),
+            'B' => Some(BISHOP),
+            'R' => Some(ROOK),
+            'Q' => Some(QUEEN),
+            'K' => Some(KING),
+            _ => None
+        }
+    }
+
+    impl Game {
+        // Resolves a SAN move like "Nbd7", "exd5", "e8=Q+" or "O-O" in the current position
+        pub fn move_from_san(&mut self, san: &str) -> Option<Move> {
+            let san = san.trim_end_matches(['+', '#', '!', '?']);
+            let (king, rank) = if self.is_white_turn { ("e1", '1') } else { ("e8", '8') };
+
+            match san {
+                "O-O" | "0-0" => return self.move_from_uci(&format!("{}g{}", king, rank)),
+                "O-O-O" | "0-0-0" => return self.move_from_uci(&format!("{}c{}", king, rank)),
+                _ => {}
+            }
+
+            let (piece, rest) = match san.chars().next().and_then(piece_from_letter) {
+                Some(piece) => (piece, &san[1..]),
+                None => (PAWN, san)
+            };
+
+            let (rest, promotion) = match rest.split_once('=') {
+                Some((rest, promo)) => (rest, Some(piece_from_letter(promo.chars().next()?)?)),
+                None => match rest.chars().last().and_then(piece_from_letter) {
+                    Some(promo) if piece == PAWN => (&rest[..rest.len() - 1], Some(promo)),
+                    _ => (rest, None)
+                }
+            };
+
+            let rest: String = rest.chars().filter(|c| !matches!(c, 'x' | ':' | '-')).collect();
+            if rest.len() < 2 || !rest.is_ascii() {
+                return None
+            }
+
+            let to = utils::parse_square(&rest[rest.len() - 2..])?;
+            let mut from_file = None;
+            let mut from_rank = None;
+            for c in rest[..rest.len() - 2].chars() {
+                match c {
+                    'a'..='h' => from_file = Some(c as usize - 'a' as usize),
+                    '1'..='8' => from_rank = Some(c as usize - '1' as usize),
+                    _ => return None
+                }
+            }
+
+            let (moves, _, _) = self.get_all_moves();
+            let mut candidates: Vec<Move> = moves.into_iter().filter(|m| {
+                let (from, target, moving, promote) = match *m {
+                    Standard(from, to, p, _) | Capture(from, to, p, _, _) => (from, to, p, None),
+                    Promotion(from, to, promote, _, _) => (from, to, PAWN, Some(promote)),
+                    _ => return false
+                };
+
+                moving == piece && target == to && promote == promotion
+                    && from_file.is_none_or(|file| utils::get_file(from as usize) == file)
+                    && from_rank.is_none_or(|rank| utils::get_rank(from as usize) == rank)
+            }).collect();
+
+            // The generated moves are pseudo legal. SAN also leaves out the disambiguation when the other piece is pinned.
+            candidates.retain(|m| self.leaves_king_safe(m));
+
+            match candidates[..] {
+                [m] => Some(m),
+                _ => None
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+// Renders positions to SVG or PNG without a window, for reports and test artifacts.
+//
+// Both outputs share one layout: the board with a8 in the top left corner (h1 when flipped), rank numbers in a
+// margin on the left and file letters in a margin below the board when coordinates are on. The pieces are the same
+// sprites the raylib window draws, built into the library so no files are read at runtime.
+pub mod render {
+    use std::fmt::Write as _;
+    use std::io;
+    use std::sync::OnceLock;
+    use crate::board::board::{Board, Move, Side};
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+    impl Rgba {
+        // The square colours of the window
+        pub const LIGHT_SQUARE: Rgba = Rgba(200, 200, 200, 255);
+        pub const DARK_SQUARE: Rgba = Rgba(0, 117, 44, 255);
+        pub const LAST_MOVE: Rgba = Rgba(255, 214, 0, 110);
+        pub const HIGHLIGHT: Rgba = Rgba(220, 40, 40, 110);
+        pub const ARROW: Rgba = Rgba(20, 90, 200, 180);
+        pub const TEXT: Rgba = Rgba(40, 40, 40, 255);
+        pub const BACKGROUND: Rgba = Rgba(255, 255, 255, 255);
+
+        fn hex(&self) -> String {
+            format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+        }
+
+        fn opacity(&self) -> f32 {
+            self.3 as f32 / 255.0
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Highlight {
+        pub square: u8,
+        pub color: Rgba,
+    }
+
+    impl Highlight {
+        pub fn new(square: u8) -> Highlight {
+            Highlight { square, color: Rgba::HIGHLIGHT }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Arrow {
+        pub from: u8,
+        pub to: u8,
+        pub color: Rgba,
+    }
+
+    impl Arrow {
+        pub fn new(from: u8, to: u8) -> Arrow {
+            Arrow { from, to, color: Rgba::ARROW }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct RenderOptions {
+        pub square_size: u32,
+        pub coordinates: bool, // rank and file labels in a margin
+        pub flipped: bool, // black at the bottom
+        pub last_move: Option<Move>, // tints the from and to squares
+        pub highlights: Vec<Highlight>,
+        pub arrows: Vec<Arrow>,
+    }
+
+    impl Default for RenderOptions {
+        fn default() -> Self {
+            RenderOptions {
+                square_size: 60,
+                coordinates: true,
+                flipped: false,
+                last_move: None,
+                highlights: vec![],
+                arrows: vec![],
+            }
+        }
+    }
+
+    // An RGBA image, rows from the top
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Image {
+        pub width: u32,
+        pub height: u32,
+        pub pixels: Vec<u8>,
+    }
+
+    impl Image {
+        fn new(width: u32, height: u32, color: Rgba) -> Image {
+            let pixels = [color.0, color.1, color.2, color.3].repeat((width * height) as usize);
+            Image { width, height, pixels }
+        }
+
+        pub fn pixel(&self, x: u32, y: u32) -> Rgba {
+            let i = ((y * self.width + x) * 4) as usize;
+            Rgba(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3])
+        }
+
+        // Alpha blends the colour over the pixel, coverage scales the colour's alpha
+        fn blend(&mut self, x: u32, y: u32, color: Rgba, coverage: f32) {
+            if x >= self.width || y >= self.height {
+                return
+            }
+
+            let alpha = color.opacity() * coverage;
+            let i = ((y * self.width + x) * 4) as usize;
+            for (channel, value) in [color.0, color.1, color.2].into_iter().enumerate() {
+                let old = self.pixels[i + channel] as f32;
+                self.pixels[i + channel] = (old + (value as f32 - old) * alpha).round() as u8;
+            }
+            let old = self.pixels[i + 3] as f32 / 255.0;
+            self.pixels[i + 3] = ((old + alpha * (1.0 - old)) * 255.0).round() as u8;
+        }
+
+        fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgba) {
+            for py in y..y + height {
+                for px in x..x + width {
+                    self.blend(px, py, color, 1.0);
+                }
+            }
+        }
+
+        pub fn to_png(&self) -> io::Result<Vec<u8>> {
+            let mut bytes = vec![];
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(io::Error::other)?;
+            writer.write_image_data(&self.pixels).map_err(io::Error::other)?;
+            writer.finish().map_err(io::Error::other)?;
+            Ok(bytes)
+        }
+    }
+
+    fn sprite_bytes(piece: PieceType, is_white: bool) -> &'static [u8] {
+        match (piece, is_white) {
+            (PAWN, true) => include_bytes!("../resources/white_pawn.png"),
+            (KNIGHT, true) => include_bytes!("../resources/white_knight.png"),
+            (BISHOP, true) => include_bytes!("../resources/white_bishop.png"),
+            (ROOK, true) => include_bytes!("../resources/white_rook.png"),
+            (QUEEN, true) => include_bytes!("../resources/white_queen.png"),
+            (KING, true) => include_bytes!("../resources/white_king.png"),
+            (PAWN, false) => include_bytes!("../resources/black_pawn.png"),
+            (KNIGHT, false) => include_bytes!("../resources/black_knight.png"),
+            (BISHOP, false) => include_bytes!("../resources/black_bishop.png"),
+            (ROOK, false) => include_bytes!("../resources/black_rook.png"),
+            (QUEEN, false) => include_bytes!("../resources/black_queen.png"),
+            (KING, false) => include_bytes!("../resources/black_king.png"),
+            (PieceType::None, _) => &[]
+        }
+    }
+
+    const SPRITE_PIECES: [PieceType; 6] = [PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING];
+
+    fn decode_sprite(bytes: &[u8]) -> Image {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().expect("Piece sprites are valid PNGs");
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).expect("Piece sprites are valid PNGs");
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|c| [c[0], c[1], c[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|c| [c[0], c[0], c[0], c[1]]).collect(),
+            _ => buffer.iter().flat_map(|g| [*g, *g, *g, 255]).collect()
+        };
+
+        Image { width: info.width, height: info.height, pixels }
+    }
+
+    // Decoded sprites, white pieces first in SPRITE_PIECES order
+    fn sprites() -> &'static [Image] {
+        static SPRITES: OnceLock<Vec<Image>> = OnceLock::new();
+        SPRITES.get_or_init(|| {
+            [true, false].iter()
+                .flat_map(|is_white| SPRITE_PIECES.iter().map(|piece| decode_sprite(sprite_bytes(*piece, *is_white))))
+                .collect()
+        })
+    }
+
+    fn sprite(piece: PieceType, is_white: bool) -> &'static Image {
+        let index = SPRITE_PIECES.iter().position(|p| *p == piece).unwrap();
+        &sprites()[index + if is_white { 0 } else { 6 }]
+    }
+
+    // 5x7 glyphs for the coordinates, one byte per row with the leftmost pixel in bit 4
+    fn glyph(c: char) -> [u8; 7] {
+        match c {
+            '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+            '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+            '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+            '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+            '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+            '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+            '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+            '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+            'a' => [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F],
+            'b' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E],
+            'c' => [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E],
+            'd' => [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F],
+            'e' => [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E],
+            'f' => [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08],
+            'g' => [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E],
+            'h' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+            _ => [0; 7]
+        }
+    }
+
+    // The squares a move touches, the king's squares for castling
+    fn move_squares(m: &Move) -> Option<(u8, u8)> {
+        match m {
+            Move::Standard(from, to, _, _) | Move::Capture(from, to, _, _, _) | Move::Promotion(from, to, _, _, _) => Some((*from, *to)),
+            Move::Castle(king_position, side, _) => {
+                let to = if *side == Side::Left { king_position - 2 } else { king_position + 2 };
+                Some((*king_position, to))
+            }
+            Move::None => None
+        }
+    }
+
+    // Where things go in the picture, shared by both outputs
+    struct Layout {
+        square: u32,
+        margin: u32,
+        flipped: bool,
+    }
+
+    impl Layout {
+        fn new(options: &RenderOptions) -> Layout {
+            let square = options.square_size.max(8);
+            Layout { square, margin: if options.coordinates { square / 2 } else { 0 }, flipped: options.flipped }
+        }
+
+        fn width(&self) -> u32 {
+            self.margin + 8 * self.square
+        }
+
+        fn height(&self) -> u32 {
+            8 * self.square + self.margin
+        }
+
+        // Column and row on screen, a8 is 0, 0 unless flipped
+        fn cell(&self, square: u8) -> (u32, u32) {
+            let (column, row) = (square as u32 % 8, square as u32 / 8);
+            if self.flipped { (7 - column, 7 - row) } else { (column, row) }
+        }
+
+        fn origin(&self, square: u8) -> (u32, u32) {
+            let (column, row) = self.cell(square);
+            (self.margin + column * self.square, row * self.square)
+        }
+
+        fn center(&self, square: u8) -> (f32, f32) {
+            let (x, y) = self.origin(square);
+            (x as f32 + self.square as f32 / 2.0, y as f32 + self.square as f32 / 2.0)
+        }
+
+        fn is_light(&self, square: u8) -> bool {
+            (square / 8 + square % 8).is_multiple_of(2)
+        }
+
+        // Rank numbers down the left margin and file letters along the bottom, with the centre of each label
+        fn labels(&self) -> Vec<(char, f32, f32)> {
+            let half = self.margin as f32 / 2.0;
+            let mut labels = vec![];
+            for i in 0..8_u8 {
+                let (_, y) = self.center(i * 8);
+                labels.push(((b'8' - i) as char, half, y));
+                let (x, _) = self.center(i);
+                labels.push(((b'a' + i) as char, x, (8 * self.square) as f32 + half));
+            }
+            labels
+        }
+
+        // Outline of an arrow from centre to centre, the shaft stops where the head starts
+        fn arrow(&self, arrow: &Arrow) -> Vec<(f32, f32)> {
+            let (x1, y1) = self.center(arrow.from);
+            let (x2, y2) = self.center(arrow.to);
+            let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt().max(1.0);
+            let (dx, dy) = ((x2 - x1) / length, (y2 - y1) / length);
+            let (nx, ny) = (-dy, dx);
+
+            let size = self.square as f32;
+            let shaft = size * 0.09;
+            let head = size * 0.25;
+            let head_length = (size * 0.45).min(length);
+            let (bx, by) = (x2 - dx * head_length, y2 - dy * head_length);
+
+            vec![
+                (x1 + nx * shaft, y1 + ny * shaft),
+                (bx + nx * shaft, by + ny * shaft),
+                (bx + nx * head, by + ny * head),
+                (x2, y2),
+                (bx - nx * head, by - ny * head),
+                (bx - nx * shaft, by - ny * shaft),
+                (x1 - nx * shaft, y1 - ny * shaft),
+            ]
+        }
+    }
+
+    // Square tints in drawing order: the last move, then the highlights
+    fn tints(options: &RenderOptions) -> Vec<(u8, Rgba)> {
+        let mut tints = vec![];
+        if let Some((from, to)) = options.last_move.as_ref().and_then(move_squares) {
+            tints.push((from, Rgba::LAST_MOVE));
+            tints.push((to, Rgba::LAST_MOVE));
+        }
+        tints.extend(options.highlights.iter().map(|h| (h.square, h.color)));
+        tints.retain(|(square, _)| *square < 64);
+        tints
+    }
+
+    fn arrows(options: &RenderOptions) -> impl Iterator<Item = &Arrow> {
+        options.arrows.iter().filter(|a| a.from < 64 && a.to < 64 && a.from != a.to)
+    }
+
+    const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    fn base64(bytes: &[u8]) -> String {
+        let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    pub fn render_svg(board: &Board, options: &RenderOptions) -> String {
+        let layout = Layout::new(options);
+        let size = layout.square;
+        let mut svg = String::new();
+
+        // Writing into a String can't fail
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            layout.width(),
+            layout.height()
+        );
+        let _ = writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, layout.width(), layout.height(), Rgba::BACKGROUND.hex());
+
+        for square in 0..64 {
+            let (x, y) = layout.origin(square);
+            let color = if layout.is_light(square) { Rgba::LIGHT_SQUARE } else { Rgba::DARK_SQUARE };
+            let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{2}" height="{2}" fill="{3}"/>"#, x, y, size, color.hex());
+        }
+
+        for (square, color) in tints(options) {
+            let (x, y) = layout.origin(square);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{2}" height="{2}" fill="{3}" fill-opacity="{4:.3}"/>"#,
+                x, y, size, color.hex(), color.opacity()
+            );
+        }
+
+        for square in 0..64 {
+            if let Some((piece, is_white)) = board.piece_at(square) {
+                let (x, y) = layout.origin(square);
+                let _ = writeln!(
+                    svg,
+                    r#"<image x="{}" y="{}" width="{2}" height="{2}" href="data:image/png;base64,{3}"/>"#,
+                    x, y, size, base64(sprite_bytes(piece, is_white))
+                );
+            }
+        }
+
+        for arrow in arrows(options) {
+            let points: Vec<String> = layout.arrow(arrow).iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+            let _ = writeln!(
+                svg,
+                r#"<polygon points="{}" fill="{}" fill-opacity="{:.3}"/>"#,
+                points.join(" "), arrow.color.hex(), arrow.color.opacity()
+            );
+        }
+
+        if options.coordinates {
+            for (label, x, y) in layout.labels() {
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="{}" fill="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    x, y, size / 3, Rgba::TEXT.hex(), label
+                );
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // Crossing test, the polygon may be concave
+    fn inside(polygon: &[(f32, f32)], x: f32, y: f32) -> bool {
+        let mut inside = false;
+        let mut j = polygon.len() - 1;
+        for i in 0..polygon.len() {
+            let (xi, yi) = polygon[i];
+            let (xj, yj) = polygon[j];
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+
+    // Fills the polygon with four samples per pixel for smooth edges
+    fn fill_polygon(image: &mut Image, polygon: &[(f32, f32)], color: Rgba) {
+        let min_x = polygon.iter().map(|p| p.0).fold(f32::MAX, f32::min).max(0.0) as u32;
+        let max_x = polygon.iter().map(|p| p.0).fold(f32::MIN, f32::max).min(image.width as f32 - 1.0) as u32;
+        let min_y = polygon.iter().map(|p| p.1).fold(f32::MAX, f32::min).max(0.0) as u32;
+        let max_y = polygon.iter().map(|p| p.1).fold(f32::MIN, f32::max).min(image.height as f32 - 1.0) as u32;
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let samples = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)].iter()
+                    .filter(|(sx, sy)| inside(polygon, x as f32 + sx, y as f32 + sy))
+                    .count();
+                if samples > 0 {
+                    image.blend(x, y, color, samples as f32 / 4.0);
+                }
+            }
+        }
+    }
+
+    // Scales the sprite bilinearly into the square
+    fn draw_sprite(image: &mut Image, sprite: &Image, x: u32, y: u32, size: u32) {
+        let scale = sprite.width as f32 / size as f32;
+        for py in 0..size {
+            for px in 0..size {
+                let sx = ((px as f32 + 0.5) * scale - 0.5).clamp(0.0, sprite.width as f32 - 1.0);
+                let sy = ((py as f32 + 0.5) * scale - 0.5).clamp(0.0, sprite.height as f32 - 1.0);
+                let (x0, y0) = (sx as u32, sy as u32);
+                let (x1, y1) = ((x0 + 1).min(sprite.width - 1), (y0 + 1).min(sprite.height - 1));
+                let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+
+                let mut channels = [0.0_f32; 4];
+                for (sx, sy, weight) in [(x0, y0, (1.0 - fx) * (1.0 - fy)), (x1, y0, fx * (1.0 - fy)), (x0, y1, (1.0 - fx) * fy), (x1, y1, fx * fy)] {
+                    let Rgba(r, g, b, a) = sprite.pixel(sx, sy);
+                    // Weighted by alpha so transparent pixels don't bleed their colour into the edges
+                    let alpha = a as f32 * weight;
+                    channels[0] += r as f32 * alpha;
+                    channels[1] += g as f32 * alpha;
+                    channels[2] += b as f32 * alpha;
+                    channels[3] += alpha;
+                }
+
+                if channels[3] > 0.0 {
+                    let color = Rgba(
+                        (channels[0] / channels[3]) as u8,
+                        (channels[1] / channels[3]) as u8,
+                        (channels[2] / channels[3]) as u8,
+                        channels[3].round() as u8
+                    );
+                    image.blend(x + px, y + py, color, 1.0);
+                }
+            }
+        }
+    }
+
+    fn draw_label(image: &mut Image, label: char, x: f32, y: f32, size: u32) {
+        let scale = (size / 30).max(1);
+        let (left, top) = ((x - 2.5 * scale as f32) as u32, (y - 3.5 * scale as f32) as u32);
+        for (row, bits) in glyph(label).iter().enumerate() {
+            for column in 0..5 {
+                if bits >> (4 - column) & 1 == 1 {
+                    image.fill_rect(left + column * scale, top + row as u32 * scale, scale, scale, Rgba::TEXT);
+                }
+            }
+        }
+    }
+
+    pub fn render_image(board: &Board, options: &RenderOptions) -> Image {
+        let layout = Layout::new(options);
+        let size = layout.square;
+        let mut image = Image::new(layout.width(), layout.height(), Rgba::BACKGROUND);
+
+        for square in 0..64 {
+            let (x, y) = layout.origin(square);
+            let color = if layout.is_light(square) { Rgba::LIGHT_SQUARE } else { Rgba::DARK_SQUARE };
+            image.fill_rect(x, y, size, size, color);
+        }
+
+        for (square, color) in tints(options) {
+            let (x, y) = layout.origin(square);
+            image.fill_rect(x, y, size, size, color);
+        }
+
+        for square in 0..64 {
+            if let Some((piece, is_white)) = board.piece_at(square) {
+                let (x, y) = layout.origin(square);
+                draw_sprite(&mut image, sprite(piece, is_white), x, y, size);
+            }
+        }
+
+        for arrow in arrows(options) {
+            fill_polygon(&mut image, &layout.arrow(arrow), arrow.color);
+        }
+
+        if options.coordinates {
+            for (label, x, y) in layout.labels() {
+                draw_label(&mut image, label, x, y, size);
+            }
+        }
+
+        image
+    }
+
+    pub fn render_png(board: &Board, options: &RenderOptions) -> io::Result<Vec<u8>> {
+        render_image(board, options).to_png()
+    }
+}
@@ -0,0 +1,48 @@
+// Search scores.
+//
+// A score is from the side to move and always within -INFINITE..=INFINITE, so it can be negated and adjusted
+// without overflowing. Being mated at ply n scores -MATE + n and mating at ply n scores MATE - n, a closer mate is
+// worth more and a later loss less. Everything at least MATE_BOUND away from zero is a mate.
+pub mod score {
+    pub type Score = i32;
+
+    pub const INFINITE: Score = 32_000;
+    pub const MATE: Score = 31_000;
+    // Longest mate in plies that still scores as a mate
+    pub const MAX_MATE_PLY: Score = 1_000;
+    pub const MATE_BOUND: Score = MATE - MAX_MATE_PLY;
+    pub const DRAW: Score = 0;
+
+    // Mating, the mated position is reached at ply
+    pub fn mate_in(ply: usize) -> Score {
+        MATE - ply as Score
+    }
+
+    // Mated in the position at ply
+    pub fn mated_in(ply: usize) -> Score {
+        -MATE + ply as Score
+    }
+
+    pub fn is_mate(score: Score) -> bool {
+        score.abs() >= MATE_BOUND
+    }
+
+    // Full moves to the mate, negative when the side to move gets mated
+    pub fn mate_moves(score: Score) -> Option<i32> {
+        if score >= MATE_BOUND {
+            Some((MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            Some(-(MATE + score) / 2)
+        } else {
+            None
+        }
+    }
+
+    // "mate 3", "mate -2" or "cp 41" as UCI frontends expect it
+    pub fn to_uci(score: Score) -> String {
+        match mate_moves(score) {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", score),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+// Progress reports of a running search.
+//
+// A search reports every completed iteration, one report per MultiPV line, and in between every so often the root
+// move it is working on. Reports go to a callback or a channel given with the search's control, which also holds the
+// handle that stops it. The GUI, protocol adapters and logging all read the same reports.
+pub mod search_info {
+    use std::sync::mpsc::Sender;
+    use std::time::Duration;
+    use crate::board::board::Move;
+    use crate::score::score::{to_uci, Score};
+    use crate::search_limits::search_limits::StopHandle;
+
+    // Time between the reports during an iteration
+    pub const INFO_INTERVAL: Duration = Duration::from_secs(1);
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SearchInfo {
+        pub depth: usize,
+        pub seldepth: usize, // deepest ply reached, quiescence included
+        pub multipv: usize, // 1 for the best line
+        pub score: Option<Score>, // none in the reports during an iteration
+        pub nodes: usize, // of all threads
+        pub nps: u64,
+        pub time: Duration,
+        pub hashfull: usize, // permille
+        pub tbhits: usize,
+        pub pv: Vec<Move>,
+        pub currmove: Option<(Move, usize)>, // root move being searched and its number, counting from 1
+    }
+
+    impl SearchInfo {
+        // "info depth 8 seldepth 14 multipv 1 score cp 31 nodes ... pv e2e4 e7e5" as UCI frontends expect it
+        pub fn to_uci(&self) -> String {
+            let mut line = format!("info depth {} seldepth {} multipv {}", self.depth, self.seldepth, self.multipv);
+            if let Some(score) = self.score {
+                line += &format!(" score {}", to_uci(score));
+            }
+            line += &format!(
+                " nodes {} nps {} hashfull {} tbhits {} time {}",
+                self.nodes, self.nps, self.hashfull, self.tbhits, self.time.as_millis()
+            );
+            if let Some((m, number)) = self.currmove {
+                line += &format!(" currmove {} currmovenumber {}", m.to_uci(), number);
+            }
+            if !self.pv.is_empty() {
+                let pv: Vec<String> = self.pv.iter().map(|m| m.to_uci()).collect();
+                line += &format!(" pv {}", pv.join(" "));
+            }
+            line
+        }
+    }
+
+    type InfoCallback = Box<dyn Fn(&SearchInfo) + Send + Sync>;
+
+    // How a caller controls a search and hears from it
+    #[derive(Default)]
+    pub struct SearchControl {
+        pub stop: StopHandle,
+        info: Option<InfoCallback>,
+    }
+
+    impl SearchControl {
+        pub fn new() -> SearchControl {
+            SearchControl::default()
+        }
+
+        pub fn with_stop(stop: StopHandle) -> SearchControl {
+            SearchControl { stop, info: None }
+        }
+
+        // Called on the search thread, so it should be quick
+        pub fn on_info(mut self, callback: impl Fn(&SearchInfo) + Send + Sync + 'static) -> SearchControl {
+            self.info = Some(Box::new(callback));
+            self
+        }
+
+        // Sends every report, a receiver that hung up doesn't stop the search
+        pub fn info_channel(self, sender: Sender<SearchInfo>) -> SearchControl {
+            self.on_info(move |info| {
+                let _ = sender.send(info.clone());
+            })
+        }
+
+        pub fn wants_info(&self) -> bool {
+            self.info.is_some()
+        }
+
+        pub fn report(&self, info: &SearchInfo) {
+            if let Some(callback) = &self.info {
+                callback(info);
+            }
+        }
+    }
+}
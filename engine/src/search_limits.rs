@@ -0,0 +1,98 @@
+// What ends a search.
+//
+// A search runs until the first of its limits is reached: a depth, a number of nodes, a fixed time, the clock or a
+// mate of the requested length. An infinite search ignores the clock and runs until its depth is done or it is
+// stopped. Any search can be stopped from another thread through its stop handle, the best move of the last
+// completed iteration is still returned.
+pub mod search_limits {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::time_manager::time_manager::TimeControl;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct SearchLimits {
+        pub depth: Option<usize>,
+        // Nodes including quiescence, checked at every node so a search on one thread is reproducible. With more
+        // threads the helpers' nodes count as they report them, every few thousand nodes.
+        pub nodes: Option<usize>,
+        pub movetime: Option<Duration>, // overrides the clock
+        pub clock: TimeControl,
+        pub mate: Option<u32>, // stop once a mate in at most this many moves is found
+        pub infinite: bool,
+    }
+
+    impl SearchLimits {
+        pub fn depth(depth: usize) -> SearchLimits {
+            SearchLimits { depth: Some(depth), ..SearchLimits::default() }
+        }
+
+        pub fn nodes(nodes: usize) -> SearchLimits {
+            SearchLimits { nodes: Some(nodes), ..SearchLimits::default() }
+        }
+
+        pub fn movetime(movetime: Duration) -> SearchLimits {
+            SearchLimits { movetime: Some(movetime), ..SearchLimits::default() }
+        }
+
+        pub fn clock(clock: TimeControl) -> SearchLimits {
+            SearchLimits { clock, ..SearchLimits::default() }
+        }
+
+        pub fn mate(moves: u32) -> SearchLimits {
+            SearchLimits { mate: Some(moves), ..SearchLimits::default() }
+        }
+
+        pub fn infinite() -> SearchLimits {
+            SearchLimits { infinite: true, ..SearchLimits::default() }
+        }
+
+        // The time the search may take, none when it is infinite
+        pub fn time_control(&self) -> TimeControl {
+            if self.infinite {
+                return TimeControl::infinite()
+            }
+            TimeControl { movetime: self.movetime.or(self.clock.movetime), ..self.clock }
+        }
+
+        // Deepest iteration, a mate in n moves is found within 2n plies
+        pub fn max_depth(&self, max_depth: usize) -> usize {
+            let max_depth = self.mate.map_or(max_depth, |moves| max_depth.min(2 * moves as usize).max(1));
+            self.depth.map_or(max_depth, |depth| depth.clamp(1, max_depth))
+        }
+
+        pub fn nodes_reached(&self, nodes: usize) -> bool {
+            self.nodes.is_some_and(|limit| nodes >= limit)
+        }
+
+        // Whether a mate in moves full moves, negative when getting mated, ends the search
+        pub fn mate_reached(&self, moves: i32) -> bool {
+            self.mate.is_some_and(|limit| moves > 0 && moves <= limit as i32)
+        }
+    }
+
+    // Stops a running search from another thread, clones share the flag
+    #[derive(Debug, Clone, Default)]
+    pub struct StopHandle {
+        stopped: Arc<AtomicBool>,
+    }
+
+    impl StopHandle {
+        pub fn new() -> StopHandle {
+            StopHandle::default()
+        }
+
+        pub fn stop(&self) {
+            self.stopped.store(true, Ordering::Relaxed);
+        }
+
+        pub fn is_stopped(&self) -> bool {
+            self.stopped.load(Ordering::Relaxed)
+        }
+
+        // Ready for the next search
+        pub fn reset(&self) {
+            self.stopped.store(false, Ordering::Relaxed);
+        }
+    }
+}
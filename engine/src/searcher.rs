@@ -0,0 +1,213 @@
+// Searching from another program.
+//
+// A Searcher keeps what lasts from one search to the next: the position, the hash table, the options and the
+// optional tablebases and endgame tables. It searches on the calling thread. An EngineHandle owns a searcher and
+// searches on a background thread: go starts a search and hands back a channel that receives its reports and last
+// its result, stop ends it early with the best move found so far. Between searches the position, options and hash
+// table can be changed; doing so stops a running search first.
+pub mod searcher {
+    use std::error::Error;
+    use std::fmt;
+    use std::fmt::Formatter;
+    use std::sync::mpsc::{self, Receiver};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::thread::{self, JoinHandle};
+    use crate::endgame::endgame::EndgameTables;
+    use crate::engine::engine::{Engine, SearchOptions, SearchResult};
+    use crate::fen::fen::{FenError, STARTING_POSITION};
+    use crate::game::game::Game;
+    use crate::search_info::search_info::{SearchControl, SearchInfo};
+    use crate::search_limits::search_limits::{SearchLimits, StopHandle};
+    use crate::syzygy::syzygy::Tablebase;
+    use crate::transposition_table::transposition_table::TranspositionTable;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct EngineOptions {
+        pub hash_mb: usize,
+        pub search: SearchOptions,
+    }
+
+    impl Default for EngineOptions {
+        fn default() -> Self {
+            EngineOptions { hash_mb: 64, search: SearchOptions::default() }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PositionError {
+        InvalidFen(FenError),
+        IllegalMove { ply: usize, uci: String },
+    }
+
+    impl fmt::Display for PositionError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                PositionError::InvalidFen(e) => write!(f, "invalid FEN: {}", e),
+                PositionError::IllegalMove { ply, uci } => write!(f, "illegal move '{}' at ply {}", uci, ply),
+            }
+        }
+    }
+
+    impl Error for PositionError {}
+
+    impl From<FenError> for PositionError {
+        fn from(e: FenError) -> Self {
+            PositionError::InvalidFen(e)
+        }
+    }
+
+    pub struct Searcher {
+        game: Game,
+        tt: TranspositionTable,
+        options: EngineOptions,
+        tablebase: Option<Tablebase>,
+        endgames: Option<EndgameTables>,
+    }
+
+    impl Searcher {
+        // Starts at the initial position
+        pub fn new(options: EngineOptions) -> Searcher {
+            Searcher {
+                game: Game::from_fen(STARTING_POSITION).expect("The starting position is valid"),
+                tt: TranspositionTable::new(options.hash_mb),
+                options,
+                tablebase: None,
+                endgames: None,
+            }
+        }
+
+        pub fn set_tablebase(&mut self, tablebase: Option<Tablebase>) {
+            self.tablebase = tablebase;
+        }
+
+        pub fn set_endgames(&mut self, endgames: Option<EndgameTables>) {
+            self.endgames = endgames;
+        }
+
+        pub fn options(&self) -> &EngineOptions {
+            &self.options
+        }
+
+        // A new hash size empties the table
+        pub fn set_options(&mut self, options: EngineOptions) {
+            if options.hash_mb != self.options.hash_mb {
+                self.tt.resize(options.hash_mb);
+            }
+            self.options = options;
+        }
+
+        // The position after playing the UCI moves from fen, on an error the position is left as it was
+        pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), PositionError> {
+            let mut game = Game::from_fen(fen)?;
+            for (ply, uci) in moves.iter().enumerate() {
+                let m = game.move_from_uci(uci)
+                    .ok_or_else(|| PositionError::IllegalMove { ply: ply + 1, uci: uci.to_string() })?;
+                game.make_move(&m);
+            }
+            self.game = game;
+            Ok(())
+        }
+
+        pub fn game(&self) -> &Game {
+            &self.game
+        }
+
+        pub fn search(&mut self, limits: &SearchLimits, control: &SearchControl) -> SearchResult {
+            Engine::iterative_deepening(
+                &mut self.game,
+                limits,
+                control,
+                &mut self.tt,
+                self.tablebase.as_ref(),
+                self.endgames.as_ref(),
+                &self.options.search,
+            )
+        }
+
+        // Forgets what earlier searches learned, as for a new game
+        pub fn clear(&mut self) {
+            self.tt.clear();
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum SearchEvent {
+        Info(SearchInfo),
+        Finished(Box<SearchResult>),
+    }
+
+    pub struct EngineHandle {
+        searcher: Arc<Mutex<Searcher>>,
+        stop: StopHandle,
+        search: Option<JoinHandle<()>>,
+    }
+
+    impl EngineHandle {
+        pub fn new(options: EngineOptions) -> EngineHandle {
+            EngineHandle::from_searcher(Searcher::new(options))
+        }
+
+        // Keeps the searcher's tablebases and endgame tables
+        pub fn from_searcher(searcher: Searcher) -> EngineHandle {
+            EngineHandle { searcher: Arc::new(Mutex::new(searcher)), stop: StopHandle::new(), search: None }
+        }
+
+        pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), PositionError> {
+            self.idle().set_position(fen, moves)
+        }
+
+        pub fn set_options(&mut self, options: EngineOptions) {
+            self.idle().set_options(options);
+        }
+
+        pub fn clear(&mut self) {
+            self.idle().clear();
+        }
+
+        // Searches the current position until a limit is reached or it is stopped, a running search is stopped first
+        pub fn go(&mut self, limits: SearchLimits) -> Receiver<SearchEvent> {
+            self.stop();
+            self.stop.reset();
+
+            let (sender, receiver) = mpsc::channel();
+            let info = sender.clone();
+            let control = SearchControl::with_stop(self.stop.clone()).on_info(move |i| {
+                let _ = info.send(SearchEvent::Info(i.clone()));
+            });
+            let searcher = Arc::clone(&self.searcher);
+            self.search = Some(thread::spawn(move || {
+                let result = searcher.lock().expect("Searcher lock poisoned").search(&limits, &control);
+                let _ = sender.send(SearchEvent::Finished(Box::new(result)));
+            }));
+            receiver
+        }
+
+        // Stops the running search and waits for it, its result still arrives on its channel
+        pub fn stop(&mut self) {
+            self.stop.stop();
+            self.wait();
+        }
+
+        // Waits for the running search to reach its limits
+        pub fn wait(&mut self) {
+            if let Some(search) = self.search.take() {
+                search.join().expect("Search thread panicked");
+            }
+        }
+
+        pub fn is_searching(&self) -> bool {
+            self.search.as_ref().is_some_and(|search| !search.is_finished())
+        }
+
+        fn idle(&mut self) -> MutexGuard<'_, Searcher> {
+            self.stop();
+            self.searcher.lock().expect("Searcher lock poisoned")
+        }
+    }
+
+    impl Drop for EngineHandle {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+}
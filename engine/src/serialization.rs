@@ -0,0 +1,310 @@
+// Serde support, enabled with the `serde` cargo feature.
+//
+// Human readable formats (JSON, ...) get FEN for positions and UCI strings for moves.
+// Binary formats get a compact representation. Boards and games carry a format version
+// so stored data can still be read after the in-memory types change.
+pub mod serialization {
+    use serde::de::{DeserializeSeed, Error};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use crate::board::board::{Board, Move};
+    use crate::board::board::Move::{Capture, Castle, Promotion, Standard};
+    use crate::board::board::Side::{Left, Right};
+    use crate::game::game::Game;
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::utils::utils;
+
+    pub const FORMAT_VERSION: u8 = 1;
+
+    // Stable codes, independent of the PieceType discriminants
+    fn piece_code(piece: PieceType) -> u32 {
+        match piece {
+            PieceType::None => 0,
+            PAWN => 1,
+            KNIGHT => 2,
+            BISHOP => 3,
+            ROOK => 4,
+            QUEEN => 5,
+            KING => 6,
+        }
+    }
+
+    fn piece_from_code(code: u32) -> Option<PieceType> {
+        match code {
+            0 => Some(PieceType::None),
+            1 => Some(PAWN),
+            2 => Some(KNIGHT),
+            3 => Some(BISHOP),
+            4 => Some(ROOK),
+            5 => Some(QUEEN),
+            6 => Some(KING),
+            _ => None
+        }
+    }
+
+    fn piece_letter(piece: PieceType) -> &'static str {
+        match piece {
+            PieceType::None => "-",
+            PAWN => "P",
+            KNIGHT => "N",
+            BISHOP => "B",
+            ROOK => "R",
+            QUEEN => "Q",
+            KING => "K",
+        }
+    }
+
+    fn piece_from_letter(letter: &str) -> Option<PieceType> {
+        match letter {
+            "-" => Some(PieceType::None),
+            "P" => Some(PAWN),
+            "N" => Some(KNIGHT),
+            "B" => Some(BISHOP),
+            "R" => Some(ROOK),
+            "Q" => Some(QUEEN),
+            "K" => Some(KING),
+            _ => None
+        }
+    }
+
+    fn check_version<E: Error>(version: u8) -> Result<(), E> {
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(E::custom(format!("unsupported format version {} (supported up to {})", version, FORMAT_VERSION)))
+        }
+        Ok(())
+    }
+
+    impl Serialize for PieceType {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                serializer.serialize_str(piece_letter(*self))
+            } else {
+                serializer.serialize_u8(piece_code(*self) as u8)
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for PieceType {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if deserializer.is_human_readable() {
+                let letter = String::deserialize(deserializer)?;
+                piece_from_letter(&letter).ok_or_else(|| D::Error::custom(format!("unknown piece '{}'", letter)))
+            } else {
+                let code = u8::deserialize(deserializer)?;
+                piece_from_code(code as u32).ok_or_else(|| D::Error::custom(format!("unknown piece code {}", code)))
+            }
+        }
+    }
+
+    // Compact move layout:
+    // bits 0-5 from, 6-11 to, 12-14 moving piece, 15-17 captured piece,
+    // 18-20 promotion piece, 21 white, 22-23 kind (0 none, 1 normal, 2 promotion, 3 castle)
+    impl Move {
+        pub fn to_compact(&self) -> u32 {
+            let (from, to, piece, captured, promotion, white, kind) = match *self {
+                Move::None => return 0,
+                Standard(from, to, piece, white) => (from, to, piece, PieceType::None, PieceType::None, white, 1),
+                Capture(from, to, piece, captured, white) => (from, to, piece, captured, PieceType::None, white, 1),
+                Promotion(from, to, promotion, captured, white) => (from, to, PAWN, captured, promotion, white, 2),
+                Castle(king_position, side, white) => {
+                    let to = if side == Left { king_position - 2 } else { king_position + 2 };
+                    (king_position, to, KING, PieceType::None, PieceType::None, white, 3)
+                }
+            };
+
+            from as u32
+                | (to as u32) << 6
+                | piece_code(piece) << 12
+                | piece_code(captured) << 15
+                | piece_code(promotion) << 18
+                | (white as u32) << 21
+                | kind << 22
+        }
+
+        pub fn from_compact(value: u32) -> Option<Move> {
+            let from = (value & 63) as u8;
+            let to = (value >> 6 & 63) as u8;
+            let piece = piece_from_code(value >> 12 & 7)?;
+            let captured = piece_from_code(value >> 15 & 7)?;
+            let promotion = piece_from_code(value >> 18 & 7)?;
+            let white = value >> 21 & 1 == 1;
+
+            match value >> 22 {
+                0 => Some(Move::None),
+                1 if captured == PieceType::None => Some(Standard(from, to, piece, white)),
+                1 => Some(Capture(from, to, piece, captured, white)),
+                2 => Some(Promotion(from, to, promotion, captured, white)),
+                3 => Some(Castle(from, if to < from { Left } else { Right }, white)),
+                _ => None
+            }
+        }
+    }
+
+    impl Serialize for Move {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                serializer.serialize_str(&self.to_uci())
+            } else {
+                serializer.serialize_u32(self.to_compact())
+            }
+        }
+    }
+
+    // A UCI string only names the squares, the move is rebuilt against a position with MoveSeed.
+    // On its own a move can only be read from the compact form.
+    impl<'de> Deserialize<'de> for Move {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if deserializer.is_human_readable() {
+                return Err(D::Error::custom("a UCI move needs its position, read it with MoveSeed"))
+            }
+
+            let value = u32::deserialize(deserializer)?;
+            Move::from_compact(value).ok_or_else(|| D::Error::custom(format!("invalid compact move {:#x}", value)))
+        }
+    }
+
+    // Reads a move of the game's side to move, in either form, and fails unless it is legal there
+    pub struct MoveSeed<'a>(pub &'a mut Game);
+
+    impl<'de> DeserializeSeed<'de> for MoveSeed<'_> {
+        type Value = Move;
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Move, D::Error> {
+            let uci = if deserializer.is_human_readable() {
+                String::deserialize(deserializer)?
+            } else {
+                Move::deserialize(deserializer)?.to_uci()
+            };
+            if uci == "0000" {
+                return Ok(Move::None)
+            }
+            self.0.move_from_uci(&uci).ok_or_else(|| D::Error::custom(format!("illegal move '{}'", uci)))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct BoardRepr {
+        version: u8,
+        fen: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct BoardBinary {
+        version: u8,
+        // white pawn, knight, bishop, rook, queen, king, then the same for black
+        boards: [u64; 12],
+    }
+
+    impl Serialize for Board {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                BoardRepr { version: FORMAT_VERSION, fen: self.to_fen_placement() }.serialize(serializer)
+            } else {
+                BoardBinary {
+                    version: FORMAT_VERSION,
+                    boards: [
+                        self.white_pawn_board, self.white_knight_board, self.white_bishop_board,
+                        self.white_rook_board, self.white_queen_board, self.white_king_board,
+                        self.black_pawn_board, self.black_knight_board, self.black_bishop_board,
+                        self.black_rook_board, self.black_queen_board, self.black_king_board,
+                    ],
+                }.serialize(serializer)
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Board {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if deserializer.is_human_readable() {
+                let repr = BoardRepr::deserialize(deserializer)?;
+                check_version(repr.version)?;
+                return repr.fen.parse().map_err(D::Error::custom)
+            }
+
+            let repr = BoardBinary::deserialize(deserializer)?;
+            check_version(repr.version)?;
+
+            let [wp, wn, wb, wr, wq, wk, bp, bn, bb, br, bq, bk] = repr.boards;
+            let mut board = Board::empty();
+            board.white_pawn_board = wp;
+            board.white_knight_board = wn;
+            board.white_bishop_board = wb;
+            board.white_rook_board = wr;
+            board.white_queen_board = wq;
+            board.white_king_board = wk;
+            board.black_pawn_board = bp;
+            board.black_knight_board = bn;
+            board.black_bishop_board = bb;
+            board.black_rook_board = br;
+            board.black_queen_board = bq;
+            board.black_king_board = bk;
+            board.white_king_position = utils::bitscan_forward(wk).unwrap_or(0) as u8;
+            board.black_king_position = utils::bitscan_forward(bk).unwrap_or(0) as u8;
+
+            Ok(board)
+        }
+    }
+
+    // Games are stored as their starting FEN plus the moves played, and replayed on load
+    // so every stored move is checked against the move generator.
+    #[derive(Serialize, Deserialize)]
+    struct GameRepr<M> {
+        version: u8,
+        start_fen: String,
+        moves: Vec<M>,
+    }
+
+    fn starting_position(game: &Game) -> Game {
+        let mut start = game.clone();
+        while !start.move_log.is_empty() {
+            start.undo_move();
+        }
+        start
+    }
+
+    impl Serialize for Game {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let start_fen = starting_position(self).to_fen();
+
+            if serializer.is_human_readable() {
+                GameRepr {
+                    version: FORMAT_VERSION,
+                    start_fen,
+                    moves: self.move_log.iter().map(|m| m.to_uci()).collect(),
+                }.serialize(serializer)
+            } else {
+                GameRepr {
+                    version: FORMAT_VERSION,
+                    start_fen,
+                    moves: self.move_log.iter().map(|m| m.to_compact()).collect(),
+                }.serialize(serializer)
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Game {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let (version, start_fen, moves) = if deserializer.is_human_readable() {
+                let repr = GameRepr::<String>::deserialize(deserializer)?;
+                (repr.version, repr.start_fen, repr.moves)
+            } else {
+                let repr = GameRepr::<u32>::deserialize(deserializer)?;
+                let moves = repr.moves.iter()
+                    .map(|value| Move::from_compact(*value).map(|m| m.to_uci()))
+                    .collect::<Option<Vec<String>>>()
+                    .ok_or_else(|| D::Error::custom("invalid compact move"))?;
+                (repr.version, repr.start_fen, moves)
+            };
+            check_version(version)?;
+
+            let mut game: Game = start_fen.parse().map_err(D::Error::custom)?;
+            for (ply, uci) in moves.iter().enumerate() {
+                let m = game.move_from_uci(uci)
+                    .ok_or_else(|| D::Error::custom(format!("illegal move '{}' at ply {}", uci, ply + 1)))?;
+                game.make_move(&m);
+            }
+
+            Ok(game)
+        }
+    }
+}
@@ -0,0 +1,1263 @@
+// Probing Syzygy endgame tablebases (.rtbw WDL and .rtbz DTZ files).
+//
+// The decoding follows the reference implementation in Stockfish's tbprobe.cpp. Squares in the files are numbered
+// from a1 = 0, which is the engine's a8 = 0 indexing with the rank flipped (pos ^ 56), and pieces are coded
+// white pawn..king = 1..6 and black pawn..king = 9..14.
+//
+// Files are read lazily, the first time a position with that material is probed, and kept in memory.
+// The tables hold nothing about castling or en passant, so positions where either is possible are never probed.
+pub mod syzygy {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::ops::Neg;
+    use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
+    use crate::board::board::Move;
+    use crate::game::game::Game;
+    use crate::move_gen::move_gen::PieceType;
+    use crate::move_gen::move_gen::PieceType::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+    use crate::utils::utils;
+
+    const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+    const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+    // Most pieces any table can have, the 7 man tables
+    const TB_PIECES: usize = 7;
+
+    const FLAG_STM: u8 = 1;
+    const FLAG_MAPPED: u8 = 2;
+    const FLAG_WIN_PLIES: u8 = 4;
+    const FLAG_LOSS_PLIES: u8 = 8;
+    const FLAG_WIDE: u8 = 16;
+    const FLAG_SINGLE_VALUE: u8 = 128;
+
+    const MAX_DTZ: i32 = 1 << 18;
+
+    // Score of a tablebase win for the side to move, above any evaluation and below the mate scores
+    pub const TB_WIN_SCORE: i32 = 20_000;
+
+    // Win, draw or loss for the side to move. Cursed wins and blessed losses are wins and losses
+    // that the 50 move rule turns into draws.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Wdl {
+        Loss = -2,
+        BlessedLoss = -1,
+        Draw = 0,
+        CursedWin = 1,
+        Win = 2,
+    }
+
+    impl Wdl {
+        fn from_value(value: i32) -> Wdl {
+            match value {
+                i32::MIN..=-2 => Wdl::Loss,
+                -1 => Wdl::BlessedLoss,
+                0 => Wdl::Draw,
+                1 => Wdl::CursedWin,
+                _ => Wdl::Win
+            }
+        }
+
+        // Search score for the side to move
+        pub fn score(&self) -> i32 {
+            match self {
+                Wdl::Loss => -TB_WIN_SCORE,
+                Wdl::BlessedLoss => -2,
+                Wdl::Draw => 0,
+                Wdl::CursedWin => 2,
+                Wdl::Win => TB_WIN_SCORE,
+            }
+        }
+    }
+
+    impl Neg for Wdl {
+        type Output = Wdl;
+
+        fn neg(self) -> Wdl {
+            Wdl::from_value(-(self as i32))
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ProbeState {
+        Fail,
+        Ok,
+        // DTZ tables only store one side to move, the other one needs a 1 ply search
+        ChangeStm,
+        // The best move is a capture or pawn move, so the DTZ stored for the position can't be used
+        ZeroingBestMove,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum TableKind {
+        Wdl,
+        Dtz,
+    }
+
+    // The lookup tables used to turn a position into an index, built once
+    struct Encoding {
+        map_pawns: [usize; 64],
+        map_b1h1h7: [usize; 64],
+        map_a1d1d4: [usize; 64],
+        map_kk: [[usize; 64]; 10],
+        binomial: [[u64; 64]; TB_PIECES],
+        lead_pawn_idx: [[u64; 64]; 6],
+        lead_pawns_size: [[u64; 4]; 6],
+    }
+
+    fn rank_of(s: usize) -> usize {
+        s >> 3
+    }
+
+    fn file_of(s: usize) -> usize {
+        s & 7
+    }
+
+    fn off_a1h8(s: usize) -> i32 {
+        rank_of(s) as i32 - file_of(s) as i32
+    }
+
+    fn king_distance(a: usize, b: usize) -> usize {
+        rank_of(a).abs_diff(rank_of(b)).max(file_of(a).abs_diff(file_of(b)))
+    }
+
+    impl Encoding {
+        fn new() -> Encoding {
+            let mut e = Encoding {
+                map_pawns: [0; 64],
+                map_b1h1h7: [0; 64],
+                map_a1d1d4: [0; 64],
+                map_kk: [[0; 64]; 10],
+                binomial: [[0; 64]; TB_PIECES],
+                lead_pawn_idx: [[0; 64]; 6],
+                lead_pawns_size: [[0; 4]; 6],
+            };
+
+            let mut code = 0;
+            for s in 0..64 {
+                if off_a1h8(s) < 0 {
+                    e.map_b1h1h7[s] = code;
+                    code += 1;
+                }
+            }
+
+            // The a1-d1-d4 triangle, the squares on the diagonal get the last codes
+            let mut diagonal = vec![];
+            code = 0;
+            for s in 0..28 {
+                if off_a1h8(s) < 0 && file_of(s) <= 3 {
+                    e.map_a1d1d4[s] = code;
+                    code += 1;
+                } else if off_a1h8(s) == 0 && file_of(s) <= 3 {
+                    diagonal.push(s);
+                }
+            }
+            for s in diagonal {
+                e.map_a1d1d4[s] = code;
+                code += 1;
+            }
+
+            // The 462 legal ways to place two kings with the first one in the triangle.
+            // A first king on the diagonal keeps the other one on or below it.
+            let mut both_on_diagonal = vec![];
+            code = 0;
+            for idx in 0..10 {
+                for s1 in 0..28 {
+                    if e.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                        continue
+                    }
+
+                    for s2 in 0..64 {
+                        if king_distance(s1, s2) <= 1 || (off_a1h8(s1) == 0 && off_a1h8(s2) > 0) {
+                            continue
+                        }
+
+                        if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                            both_on_diagonal.push((idx, s2));
+                        } else {
+                            e.map_kk[idx][s2] = code;
+                            code += 1;
+                        }
+                    }
+                }
+            }
+            for (idx, s2) in both_on_diagonal {
+                e.map_kk[idx][s2] = code;
+                code += 1;
+            }
+
+            e.binomial[0][0] = 1;
+            for n in 1..64 {
+                for k in 0..TB_PIECES.min(n + 1) {
+                    e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 }
+                        + if k < n { e.binomial[k][n - 1] } else { 0 };
+                }
+            }
+
+            // Squares a2-h7 numbered so the pawn nearest the edge and lowest on its file gets the highest code
+            let mut available = 48;
+            for lead_pawns in 1..6 {
+                for f in 0..4 {
+                    let mut idx = 0;
+                    for r in 1..7 {
+                        let s = r * 8 + f;
+                        if lead_pawns == 1 {
+                            e.map_pawns[s] = available - 1;
+                            e.map_pawns[s ^ 7] = available - 2;
+                            available -= 2;
+                        }
+                        e.lead_pawn_idx[lead_pawns][s] = idx;
+                        idx += e.binomial[lead_pawns - 1][e.map_pawns[s]];
+                    }
+                    e.lead_pawns_size[lead_pawns][f] = idx;
+                }
+            }
+
+            e
+        }
+    }
+
+    fn encoding() -> &'static Encoding {
+        static ENCODING: OnceLock<Encoding> = OnceLock::new();
+        ENCODING.get_or_init(Encoding::new)
+    }
+
+    // Readers that treat anything past the end of the file as zeros, so a damaged file gives wrong answers instead of a panic
+    fn byte(data: &[u8], at: usize) -> u8 {
+        data.get(at).copied().unwrap_or(0)
+    }
+
+    fn u16_le(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([byte(data, at), byte(data, at + 1)])
+    }
+
+    fn u32_le(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([byte(data, at), byte(data, at + 1), byte(data, at + 2), byte(data, at + 3)])
+    }
+
+    fn u32_be(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes([byte(data, at), byte(data, at + 1), byte(data, at + 2), byte(data, at + 3)])
+    }
+
+    // One compressed table: a side to move and, for pawn tables, a file of the leading pawn.
+    // The fields ending in a position are offsets into the file.
+    #[derive(Debug, Clone, Default)]
+    struct PairsData {
+        flags: u8,
+        max_sym_len: u8,
+        min_sym_len: u8,
+        num_blocks: u32,
+        block_size: usize,
+        span: usize,
+        lowest_sym: usize,
+        btree: usize,
+        block_length: usize,
+        block_length_size: usize,
+        sparse_index: usize,
+        sparse_index_size: usize,
+        data: usize,
+        base64: Vec<u64>,
+        symlen: Vec<u8>,
+        pieces: [u8; TB_PIECES],
+        group_idx: [u64; TB_PIECES + 1],
+        group_len: [usize; TB_PIECES + 1],
+        // Where the value maps of the four WDL results start, only for DTZ tables
+        map_idx: [usize; 4],
+    }
+
+    impl PairsData {
+        // Symbols are stored as 12 bit left/right pairs packed in 3 bytes
+        fn left(&self, data: &[u8], sym: usize) -> usize {
+            let at = self.btree + 3 * sym;
+            ((byte(data, at + 1) as usize & 0xF) << 8) | byte(data, at) as usize
+        }
+
+        fn right(&self, data: &[u8], sym: usize) -> usize {
+            let at = self.btree + 3 * sym;
+            ((byte(data, at + 2) as usize) << 4) | (byte(data, at + 1) as usize >> 4)
+        }
+
+        fn set_symlen(&mut self, data: &[u8], sym: usize, visited: &mut [bool]) -> u8 {
+            visited[sym] = true;
+
+            let right = self.right(data, sym);
+            if right == 0xFFF {
+                return 0
+            }
+
+            let left = self.left(data, sym);
+            for s in [left, right] {
+                if s < visited.len() && !visited[s] {
+                    let len = self.set_symlen(data, s, visited);
+                    self.symlen[s] = len;
+                }
+            }
+
+            let len = |s: usize| self.symlen.get(s).copied().unwrap_or(0);
+            len(left).wrapping_add(len(right)).wrapping_add(1)
+        }
+
+        // Reads the Huffman header, returns where the next section starts
+        fn set_sizes(&mut self, data: &[u8], mut at: usize) -> usize {
+            self.flags = byte(data, at);
+            at += 1;
+
+            if self.flags & FLAG_SINGLE_VALUE != 0 {
+                self.num_blocks = 0;
+                self.span = 0;
+                self.sparse_index_size = 0;
+                // The single value every position of the table has
+                self.min_sym_len = byte(data, at);
+                return at + 1
+            }
+
+            let groups = self.group_len.iter().position(|len| *len == 0).unwrap_or(TB_PIECES);
+            let table_size = self.group_idx[groups];
+
+            self.block_size = 1 << byte(data, at);
+            self.span = 1 << byte(data, at + 1);
+            self.sparse_index_size = table_size.div_ceil(self.span as u64) as usize;
+            let padding = byte(data, at + 2) as usize;
+            self.num_blocks = u32_le(data, at + 3);
+            self.block_length_size = self.num_blocks as usize + padding;
+            self.max_sym_len = byte(data, at + 7);
+            self.min_sym_len = byte(data, at + 8);
+            at += 9;
+            self.lowest_sym = at;
+
+            let lengths = (self.max_sym_len as usize + 1).saturating_sub(self.min_sym_len as usize);
+            self.base64 = vec![0; lengths];
+            for i in (0..lengths.saturating_sub(1)).rev() {
+                let lowest = u16_le(data, self.lowest_sym + 2 * i) as u64;
+                let lowest_next = u16_le(data, self.lowest_sym + 2 * (i + 1)) as u64;
+                self.base64[i] = self.base64[i + 1].wrapping_add(lowest).wrapping_sub(lowest_next) / 2;
+            }
+            for (i, base) in self.base64.iter_mut().enumerate() {
+                *base = base.checked_shl(64_u32.saturating_sub((i + self.min_sym_len as usize) as u32)).unwrap_or(0);
+            }
+
+            at += lengths * 2;
+            let symbols = u16_le(data, at) as usize;
+            at += 2;
+            self.symlen = vec![0; symbols];
+            self.btree = at;
+
+            let mut visited = vec![false; symbols];
+            for sym in 0..symbols {
+                if !visited[sym] {
+                    let len = self.set_symlen(data, sym, &mut visited);
+                    self.symlen[sym] = len;
+                }
+            }
+
+            at + symbols * 3 + (symbols & 1)
+        }
+
+        fn block_length(&self, data: &[u8], block: usize) -> i64 {
+            u16_le(data, self.block_length + 2 * block) as i64
+        }
+
+        // The stored value of the position with the given index
+        fn decompress(&self, data: &[u8], idx: u64) -> usize {
+            if self.flags & FLAG_SINGLE_VALUE != 0 {
+                return self.min_sym_len as usize
+            }
+
+            let span = self.span as u64;
+            let k = (idx / span) as usize;
+            let mut block = u32_le(data, self.sparse_index + 6 * k) as usize;
+            let mut offset = u16_le(data, self.sparse_index + 6 * k + 4) as i64;
+            offset += (idx % span) as i64 - (span / 2) as i64;
+
+            while offset < 0 {
+                if block == 0 {
+                    return 0
+                }
+                block -= 1;
+                offset += self.block_length(data, block) + 1;
+            }
+            while offset > self.block_length(data, block) {
+                offset -= self.block_length(data, block) + 1;
+                block += 1;
+                if block >= self.block_length_size {
+                    return 0
+                }
+            }
+
+            let mut ptr = self.data + block * self.block_size;
+            let mut buf64 = (u32_be(data, ptr) as u64) << 32 | u32_be(data, ptr + 4) as u64;
+            ptr += 8;
+            let mut buf64_size = 64;
+            let min_sym_len = self.min_sym_len as usize;
+
+            let mut sym;
+            loop {
+                let mut len = 0;
+                while len + 1 < self.base64.len() && buf64 < self.base64[len] {
+                    len += 1;
+                }
+
+                let shift = 64_u32.saturating_sub((len + min_sym_len) as u32);
+                sym = (buf64.wrapping_sub(self.base64[len]).checked_shr(shift).unwrap_or(0)) as usize;
+                sym += u16_le(data, self.lowest_sym + 2 * len) as usize;
+                let sym_len = self.symlen.get(sym).copied().unwrap_or(0) as i64;
+                if offset < sym_len + 1 {
+                    break
+                }
+                offset -= sym_len + 1;
+
+                len += min_sym_len;
+                buf64 = buf64.checked_shl(len as u32).unwrap_or(0);
+                buf64_size -= len as i32;
+                if buf64_size <= 32 {
+                    buf64_size += 32;
+                    buf64 |= (u32_be(data, ptr) as u64) << (64 - buf64_size);
+                    ptr += 4;
+                }
+            }
+
+            // Walk down the pair tree to the symbol that covers the offset
+            while self.symlen.get(sym).copied().unwrap_or(0) != 0 {
+                let left = self.left(data, sym);
+                let left_len = self.symlen.get(left).copied().unwrap_or(0) as i64;
+                if offset < left_len + 1 {
+                    sym = left;
+                } else {
+                    offset -= left_len + 1;
+                    sym = self.right(data, sym);
+                }
+            }
+
+            self.left(data, sym)
+        }
+    }
+
+    // What a table name like "KRPvKR" says about its material. The first side is the white one in the file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Material {
+        piece_count: usize,
+        has_pawns: bool,
+        has_unique_pieces: bool,
+        // Pawns of the leading colour first
+        pawn_count: [usize; 2],
+        symmetric: bool,
+    }
+
+    const PIECE_ORDER: [(char, PieceType); 6] = [('K', KING), ('Q', QUEEN), ('R', ROOK), ('B', BISHOP), ('N', KNIGHT), ('P', PAWN)];
+
+    impl Material {
+        fn from_name(name: &str) -> Option<Material> {
+            let (white, black) = name.split_once('v')?;
+            let count = |side: &str, c: char| side.chars().filter(|x| *x == c).count();
+            let valid = |side: &str| count(side, 'K') == 1 && side.chars().all(|c| PIECE_ORDER.iter().any(|(p, _)| *p == c));
+            if !valid(white) || !valid(black) {
+                return None
+            }
+
+            let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+            let has_unique_pieces = "QRBNP".chars().any(|c| count(white, c) == 1 || count(black, c) == 1);
+
+            // With pawns on both sides the side with fewer pawns leads, it compresses better
+            let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+            let pawn_count = if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] };
+
+            Some(Material {
+                piece_count: white.len() + black.len(),
+                has_pawns: white_pawns + black_pawns > 0,
+                has_unique_pieces,
+                pawn_count,
+                symmetric: white == black,
+            })
+        }
+    }
+
+    // A loaded table file
+    struct Table {
+        kind: TableKind,
+        bytes: Vec<u8>,
+        material: Material,
+        // Indexed by side to move, then file of the leading pawn
+        pairs: [[PairsData; 4]; 2],
+    }
+
+    impl Table {
+        fn load(path: &Path, kind: TableKind, material: Material) -> io::Result<Table> {
+            let bytes = fs::read(path)?;
+            let magic = if kind == TableKind::Wdl { WDL_MAGIC } else { DTZ_MAGIC };
+            if bytes.len() % 64 != 16 || bytes[..4] != magic {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a valid tablebase file", path.display())))
+            }
+
+            let mut table = Table { kind, bytes, material, pairs: Default::default() };
+            table.setup();
+            Ok(table)
+        }
+
+        fn sides(&self) -> usize {
+            if self.kind == TableKind::Wdl && !self.material.symmetric { 2 } else { 1 }
+        }
+
+        fn pairs(&self, stm: usize, file: usize) -> &PairsData {
+            &self.pairs[stm % self.sides()][if self.material.has_pawns { file } else { 0 }]
+        }
+
+        fn set_groups(&mut self, side: usize, file: usize, order: [usize; 2]) {
+            let encoding = encoding();
+            let material = &self.material;
+            let d = &mut self.pairs[side][file];
+
+            let mut n = 0;
+            let mut first_len: i32 = if material.has_pawns { 0 } else if material.has_unique_pieces { 3 } else { 2 };
+            d.group_len[n] = 1;
+
+            // Runs of equal pieces form groups, except for the leading group
+            for i in 1..material.piece_count {
+                first_len -= 1;
+                if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                    d.group_len[n] += 1;
+                } else {
+                    n += 1;
+                    d.group_len[n] = 1;
+                }
+            }
+            n += 1;
+            d.group_len[n] = 0;
+
+            // The groups are combined in the order stored in the file, the leading one at order[0]
+            // and the remaining pawns, when both sides have some, at order[1]
+            let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+            let mut next = if both_pawns { 2 } else { 1 };
+            let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+            let mut idx: u64 = 1;
+
+            let mut k = 0;
+            while next < n || k == order[0] || k == order[1] {
+                if k == order[0] {
+                    d.group_idx[0] = idx;
+                    idx *= if material.has_pawns {
+                        encoding.lead_pawns_size[d.group_len[0]][file]
+                    } else if material.has_unique_pieces {
+                        31332
+                    } else {
+                        462
+                    };
+                } else if k == order[1] {
+                    d.group_idx[1] = idx;
+                    idx *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+                } else {
+                    d.group_idx[next] = idx;
+                    idx *= encoding.binomial[d.group_len[next]][free_squares];
+                    free_squares -= d.group_len[next];
+                    next += 1;
+                }
+                k += 1;
+            }
+
+            d.group_idx[n] = idx;
+        }
+
+        // Reads the headers of all the sub tables
+        fn setup(&mut self) {
+            let bytes = std::mem::take(&mut self.bytes);
+            let data = &bytes[..];
+            let mut at = 5;
+
+            let sides = self.sides();
+            let files = if self.material.has_pawns { 4 } else { 1 };
+            let both_pawns = self.material.has_pawns && self.material.pawn_count[1] > 0;
+
+            for file in 0..files {
+                let first = byte(data, at);
+                let second = if both_pawns { byte(data, at + 1) } else { 0xFF };
+                let order = [
+                    [(first & 0xF) as usize, (second & 0xF) as usize],
+                    [(first >> 4) as usize, (second >> 4) as usize],
+                ];
+                at += 1 + both_pawns as usize;
+
+                for k in 0..self.material.piece_count {
+                    for side in 0..sides {
+                        let b = byte(data, at);
+                        self.pairs[side][file].pieces[k] = if side == 0 { b & 0xF } else { b >> 4 };
+                    }
+                    at += 1;
+                }
+
+                for (side, order) in order.into_iter().enumerate().take(sides) {
+                    self.set_groups(side, file, order);
+                }
+            }
+
+            at += at & 1;
+
+            for file in 0..files {
+                for side in 0..sides {
+                    at = self.pairs[side][file].set_sizes(data, at);
+                }
+            }
+
+            if self.kind == TableKind::Dtz {
+                for file in 0..files {
+                    let d = &mut self.pairs[0][file];
+                    if d.flags & FLAG_MAPPED == 0 {
+                        continue
+                    }
+
+                    if d.flags & FLAG_WIDE != 0 {
+                        at += at & 1;
+                        for i in 0..4 {
+                            d.map_idx[i] = at + 2;
+                            at += 2 * u16_le(data, at) as usize + 2;
+                        }
+                    } else {
+                        for i in 0..4 {
+                            d.map_idx[i] = at + 1;
+                            at += byte(data, at) as usize + 1;
+                        }
+                    }
+                }
+                at += at & 1;
+            }
+
+            for file in 0..files {
+                for side in 0..sides {
+                    let d = &mut self.pairs[side][file];
+                    d.sparse_index = at;
+                    at += d.sparse_index_size * 6;
+                }
+            }
+
+            for file in 0..files {
+                for side in 0..sides {
+                    let d = &mut self.pairs[side][file];
+                    d.block_length = at;
+                    at += d.block_length_size * 2;
+                }
+            }
+
+            for file in 0..files {
+                for side in 0..sides {
+                    at = (at + 0x3F) & !0x3F;
+                    let d = &mut self.pairs[side][file];
+                    d.data = at;
+                    at += d.num_blocks as usize * d.block_size;
+                }
+            }
+
+            self.bytes = bytes;
+        }
+
+        // Turns the stored value into a WDL result or a DTZ in plies
+        fn map_score(&self, file: usize, value: usize, wdl: Wdl) -> i32 {
+            if self.kind == TableKind::Wdl {
+                return value as i32 - 2
+            }
+
+            const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+            let d = self.pairs(0, file);
+            let mut value = value as i32;
+            if d.flags & FLAG_MAPPED != 0 {
+                let start = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]];
+                value = if d.flags & FLAG_WIDE != 0 {
+                    u16_le(&self.bytes, start + 2 * value as usize) as i32
+                } else {
+                    byte(&self.bytes, start + value as usize) as i32
+                };
+            }
+
+            // Tables store moves unless the flags say plies
+            if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0)
+                || (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0)
+                || wdl == Wdl::CursedWin
+                || wdl == Wdl::BlessedLoss {
+                value *= 2;
+            }
+
+            value + 1
+        }
+
+        // Looks the position up, None when the DTZ table only has the other side to move
+        fn probe(&self, pieces: &[(usize, u8)], white_to_move: bool, black_stronger: bool, wdl: Wdl) -> Option<i32> {
+            let encoding = encoding();
+            let pawns_order = |s: &usize| encoding.map_pawns[*s];
+
+            let flip = (self.material.symmetric && !white_to_move) || black_stronger;
+            let flip_color = if flip { 8 } else { 0 };
+            let flip_squares = if flip { 56 } else { 0 };
+            let stm = (flip ^ !white_to_move) as usize;
+
+            let mut squares = [0_usize; TB_PIECES];
+            let mut codes = [0_u8; TB_PIECES];
+            let mut size = 0;
+            let mut lead_pawns = 0;
+            let mut file = 0;
+
+            // Pawn tables are split by the file of the leading pawn, the one nearest the edge and lowest on its file
+            if self.material.has_pawns {
+                let lead = self.pairs(0, 0).pieces[0] ^ flip_color;
+                for (square, code) in pieces {
+                    if *code == lead {
+                        squares[size] = square ^ flip_squares;
+                        size += 1;
+                    }
+                }
+                lead_pawns = size;
+
+                let mut max = 0;
+                for i in 1..lead_pawns {
+                    if pawns_order(&squares[i]) > pawns_order(&squares[max]) {
+                        max = i;
+                    }
+                }
+                squares.swap(0, max);
+                file = file_of(squares[0]).min(7 - file_of(squares[0]));
+            }
+
+            let d = self.pairs(stm, file);
+            let both_sides_stored = self.material.symmetric && !self.material.has_pawns;
+            if self.kind == TableKind::Dtz && (d.flags & FLAG_STM) as usize != stm && !both_sides_stored {
+                return None
+            }
+
+            let lead = self.pairs(0, 0).pieces[0] ^ flip_color;
+            for (square, code) in pieces {
+                if self.material.has_pawns && *code == lead {
+                    continue
+                }
+                squares[size] = square ^ flip_squares;
+                codes[size] = code ^ flip_color;
+                size += 1;
+            }
+
+            // Put the pieces in the order the table was encoded with
+            for i in lead_pawns..size.saturating_sub(1) {
+                for j in (i + 1)..size {
+                    if d.pieces[i] == codes[j] {
+                        codes.swap(i, j);
+                        squares.swap(i, j);
+                        break
+                    }
+                }
+            }
+
+            // Mirror so the leading piece is on files a-d
+            if file_of(squares[0]) > 3 {
+                for s in squares[..size].iter_mut() {
+                    *s ^= 7;
+                }
+            }
+
+            let mut idx: u64;
+            if self.material.has_pawns {
+                idx = encoding.lead_pawn_idx[lead_pawns][squares[0]];
+                squares[1..lead_pawns].sort_by_key(pawns_order);
+                for (i, s) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                    idx += encoding.binomial[i][encoding.map_pawns[*s]];
+                }
+            } else {
+                // Without pawns the board can also be mirrored vertically and along the a1-h8 diagonal
+                if rank_of(squares[0]) > 3 {
+                    for s in squares[..size].iter_mut() {
+                        *s ^= 56;
+                    }
+                }
+
+                for i in 0..d.group_len[0] {
+                    let off = off_a1h8(squares[i]);
+                    if off == 0 {
+                        continue
+                    }
+                    if off > 0 {
+                        for s in squares[i..size].iter_mut() {
+                            *s = ((*s >> 3) | (*s << 3)) & 63;
+                        }
+                    }
+                    break
+                }
+
+                if self.material.has_unique_pieces {
+                    let s = &squares;
+                    let adjust1 = (s[1] > s[0]) as u64;
+                    let adjust2 = (s[2] > s[0]) as u64 + (s[2] > s[1]) as u64;
+                    let rank = |sq: usize| rank_of(sq) as u64;
+
+                    idx = if off_a1h8(s[0]) != 0 {
+                        (encoding.map_a1d1d4[s[0]] as u64 * 63 + (s[1] as u64 - adjust1)) * 62 + s[2] as u64 - adjust2
+                    } else if off_a1h8(s[1]) != 0 {
+                        (6 * 63 + rank(s[0]) * 28 + encoding.map_b1h1h7[s[1]] as u64) * 62 + s[2] as u64 - adjust2
+                    } else if off_a1h8(s[2]) != 0 {
+                        6 * 63 * 62 + 4 * 28 * 62 + rank(s[0]) * 7 * 28 + (rank(s[1]) - adjust1) * 28
+                            + encoding.map_b1h1h7[s[2]] as u64
+                    } else {
+                        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(s[0]) * 7 * 6 + (rank(s[1]) - adjust1) * 6
+                            + (rank(s[2]) - adjust2)
+                    };
+                } else {
+                    idx = encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]] as u64;
+                }
+            }
+
+            // The remaining groups, each one encoded as a combination of the squares the earlier groups left free
+            idx *= d.group_idx[0];
+            let mut group_start = d.group_len[0];
+            let mut remaining_pawns = self.material.has_pawns && self.material.pawn_count[1] > 0;
+            let mut next = 1;
+            while d.group_len[next] != 0 {
+                let group_end = group_start + d.group_len[next];
+                squares[group_start..group_end].sort();
+
+                let mut n = 0;
+                for i in 0..d.group_len[next] {
+                    let s = squares[group_start + i];
+                    let adjust = squares[..group_start].iter().filter(|earlier| s > **earlier).count();
+                    let free = s - adjust - if remaining_pawns { 8 } else { 0 };
+                    n += encoding.binomial[i + 1][free];
+                }
+
+                remaining_pawns = false;
+                idx += n * d.group_idx[next];
+                group_start = group_end;
+                next += 1;
+            }
+
+            Some(self.map_score(file, d.decompress(&self.bytes, idx), wdl))
+        }
+    }
+
+    // The WDL and DTZ files found for one material signature, read the first time they are needed
+    struct TableEntry {
+        material: Material,
+        wdl_path: Option<PathBuf>,
+        dtz_path: Option<PathBuf>,
+        wdl: OnceLock<Option<Table>>,
+        dtz: OnceLock<Option<Table>>,
+    }
+
+    impl TableEntry {
+        fn table(&self, kind: TableKind) -> Option<&Table> {
+            let (path, cell) = match kind {
+                TableKind::Wdl => (&self.wdl_path, &self.wdl),
+                TableKind::Dtz => (&self.dtz_path, &self.dtz),
+            };
+
+            cell.get_or_init(|| {
+                let path = path.as_ref()?;
+                match Table::load(path, kind, self.material.clone()) {
+                    Ok(table) => Some(table),
+                    Err(e) => {
+                        log::warn!("Could not load tablebase {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            }).as_ref()
+        }
+    }
+
+    fn piece_code(piece: PieceType, is_white: bool) -> u8 {
+        let code = match piece {
+            PAWN => 1,
+            KNIGHT => 2,
+            BISHOP => 3,
+            ROOK => 4,
+            QUEEN => 5,
+            KING => 6,
+            PieceType::None => 0
+        };
+
+        if is_white { code } else { code + 8 }
+    }
+
+    // The pieces of one side in table name order, like "KRP"
+    fn side_name(game: &Game, is_white: bool) -> String {
+        PIECE_ORDER.iter()
+            .map(|(c, piece)| {
+                let board = match (*piece, is_white) {
+                    (KING, true) => game.board.white_king_board,
+                    (QUEEN, true) => game.board.white_queen_board,
+                    (ROOK, true) => game.board.white_rook_board,
+                    (BISHOP, true) => game.board.white_bishop_board,
+                    (KNIGHT, true) => game.board.white_knight_board,
+                    (PAWN, true) => game.board.white_pawn_board,
+                    (KING, false) => game.board.black_king_board,
+                    (QUEEN, false) => game.board.black_queen_board,
+                    (ROOK, false) => game.board.black_rook_board,
+                    (BISHOP, false) => game.board.black_bishop_board,
+                    (KNIGHT, false) => game.board.black_knight_board,
+                    (PAWN, false) => game.board.black_pawn_board,
+                    _ => 0
+                };
+                c.to_string().repeat(board.count_ones() as usize)
+            })
+            .collect()
+    }
+
+    fn is_zeroing(m: &Move) -> bool {
+        match *m {
+            Move::Capture(..) => true,
+            Move::Standard(_, _, piece, _) => piece == PAWN,
+            Move::Promotion(..) => true,
+            _ => false
+        }
+    }
+
+    fn is_capture(m: &Move) -> bool {
+        match *m {
+            Move::Capture(..) => true,
+            Move::Promotion(_, _, _, captured, _) => captured != PieceType::None,
+            _ => false
+        }
+    }
+
+    fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+        match wdl {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+
+    fn is_mate(game: &mut Game) -> bool {
+        game.is_in_check() && game.legal_moves().is_empty()
+    }
+
+    // A root move with its distance to zeroing and how good it is, higher ranks are better
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RootMove {
+        pub m: Move,
+        pub dtz: i32,
+        pub rank: i32,
+        pub wdl: Wdl,
+    }
+
+    pub struct Tablebase {
+        tables: HashMap<String, TableEntry>,
+        max_pieces: usize,
+        probe_limit: usize,
+    }
+
+    impl Tablebase {
+        // Opens every table in a directory, or in a list of directories separated like the PATH variable
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Tablebase> {
+            let mut tables: HashMap<String, TableEntry> = HashMap::new();
+
+            for dir in env::split_paths(path.as_ref()) {
+                for file in fs::read_dir(&dir)? {
+                    let path = file?.path();
+                    let kind = match path.extension().and_then(|e| e.to_str()) {
+                        Some("rtbw") => TableKind::Wdl,
+                        Some("rtbz") => TableKind::Dtz,
+                        _ => continue
+                    };
+                    let name = match path.file_stem().and_then(|s| s.to_str()) {
+                        Some(name) => name.to_string(),
+                        None => continue
+                    };
+                    let material = match Material::from_name(&name) {
+                        Some(material) if material.piece_count <= TB_PIECES => material,
+                        _ => continue
+                    };
+
+                    let entry = tables.entry(name).or_insert_with(|| TableEntry {
+                        material,
+                        wdl_path: None,
+                        dtz_path: None,
+                        wdl: OnceLock::new(),
+                        dtz: OnceLock::new(),
+                    });
+                    match kind {
+                        TableKind::Wdl => entry.wdl_path.get_or_insert(path),
+                        TableKind::Dtz => entry.dtz_path.get_or_insert(path),
+                    };
+                }
+            }
+
+            let max_pieces = tables.values()
+                .filter(|entry| entry.wdl_path.is_some())
+                .map(|entry| entry.material.piece_count)
+                .max()
+                .unwrap_or(2);
+
+            Ok(Tablebase { tables, max_pieces, probe_limit: TB_PIECES })
+        }
+
+        // Only positions with at most this many pieces, kings included, are probed
+        pub fn set_probe_limit(&mut self, limit: usize) {
+            self.probe_limit = limit;
+        }
+
+        // The largest number of pieces that can be probed
+        pub fn max_pieces(&self) -> usize {
+            self.max_pieces.min(self.probe_limit)
+        }
+
+        pub fn table_count(&self) -> usize {
+            self.tables.values().filter(|entry| entry.wdl_path.is_some()).count()
+        }
+
+        // Whether the position is small enough and has no castling or en passant the tables know nothing about
+        pub fn can_probe(&self, game: &Game) -> bool {
+            let board = &game.board;
+            if board.get_board_value().count_ones() as usize > self.max_pieces() {
+                return false
+            }
+
+            !game.effective_castling_rights().any() && !game.en_passant_capture_possible()
+        }
+
+        fn probe_table(&self, game: &Game, kind: TableKind, wdl: Wdl, state: &mut ProbeState) -> i32 {
+            let occupancy = game.board.get_board_value();
+            if occupancy.count_ones() == 2 {
+                return 0
+            }
+
+            let (white, black) = (side_name(game, true), side_name(game, false));
+            let (entry, black_stronger) = match self.tables.get(&format!("{}v{}", white, black)) {
+                Some(entry) => (entry, false),
+                None => match self.tables.get(&format!("{}v{}", black, white)) {
+                    Some(entry) => (entry, true),
+                    None => {
+                        *state = ProbeState::Fail;
+                        return 0
+                    }
+                }
+            };
+
+            let table = match entry.table(kind) {
+                Some(table) => table,
+                None => {
+                    *state = ProbeState::Fail;
+                    return 0
+                }
+            };
+
+            let mut pieces = Vec::with_capacity(TB_PIECES);
+            let mut remaining = occupancy;
+            while remaining > 0 {
+                let pos = utils::pop_lsb(&mut remaining);
+                let (piece, is_white) = game.board.piece_at(pos as u8).unwrap();
+                pieces.push((pos ^ 56, piece_code(piece, is_white)));
+            }
+            // The reference implementation walks the squares from a1
+            pieces.sort();
+
+            match table.probe(&pieces, game.is_white_turn, black_stronger, wdl) {
+                Some(value) => value,
+                None => {
+                    *state = ProbeState::ChangeStm;
+                    0
+                }
+            }
+        }
+
+        // Best WDL result over the captures (and pawn moves when check_zeroing is set) against the stored result.
+        // The tables only know the score of the position, so a zeroing move that is better has to be found by searching.
+        fn search(&self, game: &mut Game, check_zeroing: bool, state: &mut ProbeState) -> Wdl {
+            let moves = game.legal_moves();
+            let mut best = Wdl::Loss;
+            let mut searched = 0;
+
+            for m in moves.iter() {
+                let zeroing = is_capture(m) || (check_zeroing && is_zeroing(m));
+                if !zeroing {
+                    continue
+                }
+                searched += 1;
+
+                game.make_move(m);
+                let value = -self.search(game, false, state);
+                game.undo_move();
+
+                if *state == ProbeState::Fail {
+                    return Wdl::Draw
+                }
+
+                if value > best {
+                    best = value;
+                    if value >= Wdl::Win {
+                        *state = ProbeState::ZeroingBestMove;
+                        return value
+                    }
+                }
+            }
+
+            // With every move searched the stored value is not needed, and could even be wrong
+            let no_more_moves = searched > 0 && searched == moves.len();
+            let value = if no_more_moves {
+                best
+            } else {
+                let value = Wdl::from_value(self.probe_table(game, TableKind::Wdl, Wdl::Draw, state));
+                if *state == ProbeState::Fail {
+                    return Wdl::Draw
+                }
+                value
+            };
+
+            if best >= value {
+                *state = if best > Wdl::Draw || no_more_moves { ProbeState::ZeroingBestMove } else { ProbeState::Ok };
+                return best
+            }
+
+            *state = ProbeState::Ok;
+            value
+        }
+
+        // Win, draw or loss for the side to move, None if the position can't be probed
+        pub fn probe_wdl(&self, game: &mut Game) -> Option<Wdl> {
+            if !self.can_probe(game) {
+                return None
+            }
+
+            let mut state = ProbeState::Ok;
+            let wdl = self.search(game, false, &mut state);
+            if state == ProbeState::Fail { None } else { Some(wdl) }
+        }
+
+        fn dtz(&self, game: &mut Game, state: &mut ProbeState) -> i32 {
+            *state = ProbeState::Ok;
+            let wdl = self.search(game, true, state);
+
+            if *state == ProbeState::Fail || wdl == Wdl::Draw {
+                return 0
+            }
+
+            if *state == ProbeState::ZeroingBestMove {
+                return dtz_before_zeroing(wdl)
+            }
+
+            let dtz = self.probe_table(game, TableKind::Dtz, wdl, state);
+            if *state == ProbeState::Fail {
+                return 0
+            }
+
+            let sign = (wdl as i32).signum();
+            if *state != ProbeState::ChangeStm {
+                let cursed = wdl == Wdl::BlessedLoss || wdl == Wdl::CursedWin;
+                return (dtz + if cursed { 100 } else { 0 }) * sign
+            }
+
+            // The table has the other side to move, so search one ply and take the fastest win
+            let mut min_dtz = 0xFFFF;
+            for m in game.legal_moves() {
+                let zeroing = is_zeroing(&m);
+
+                game.make_move(&m);
+                let mut dtz = if zeroing {
+                    -dtz_before_zeroing(self.search(game, false, state))
+                } else {
+                    -self.dtz(game, state)
+                };
+
+                if dtz == 1 && is_mate(game) {
+                    min_dtz = 1;
+                }
+
+                if !zeroing {
+                    dtz += dtz.signum();
+                }
+
+                if dtz < min_dtz && dtz.signum() == sign {
+                    min_dtz = dtz;
+                }
+                game.undo_move();
+
+                if *state == ProbeState::Fail {
+                    return 0
+                }
+            }
+
+            if min_dtz == 0xFFFF { -1 } else { min_dtz }
+        }
+
+        // Plies to the next capture or pawn move that keeps the result, positive when winning and negative when losing.
+        // 0 is a draw, and 100 is added for results the 50 move rule spoils.
+        pub fn probe_dtz(&self, game: &mut Game) -> Option<i32> {
+            if !self.can_probe(game) {
+                return None
+            }
+
+            let mut state = ProbeState::Ok;
+            let dtz = self.dtz(game, &mut state);
+            if state == ProbeState::Fail { None } else { Some(dtz) }
+        }
+
+        // Every legal move ranked by the tables, counting the 50 move rule from the current halfmove clock
+        pub fn root_moves(&self, game: &mut Game) -> Option<Vec<RootMove>> {
+            if !self.can_probe(game) {
+                return None
+            }
+
+            let cnt50 = game.halfmove_clock as i32;
+            let mut state = ProbeState::Ok;
+            let mut root_moves = vec![];
+
+            for m in game.legal_moves() {
+                game.make_move(&m);
+
+                let mut dtz = if is_zeroing(&m) {
+                    dtz_before_zeroing(-self.search(game, false, &mut state))
+                } else if game.halfmove_clock >= 100 {
+                    0
+                } else {
+                    let dtz = -self.dtz(game, &mut state);
+                    dtz + dtz.signum()
+                };
+
+                if dtz == 2 && is_mate(game) {
+                    dtz = 1;
+                }
+                game.undo_move();
+
+                if state == ProbeState::Fail {
+                    return None
+                }
+
+                // Faster wins and slower losses rank higher, unless the 50 move rule gets in the way
+                let rank = if dtz > 0 {
+                    if dtz + cnt50 <= 99 { MAX_DTZ - dtz } else { MAX_DTZ / 2 - (dtz + cnt50) }
+                } else if dtz < 0 {
+                    if -dtz * 2 + cnt50 < 100 { -MAX_DTZ - dtz } else { -MAX_DTZ / 2 + (-dtz + cnt50) }
+                } else {
+                    0
+                };
+
+                let wdl = if rank >= MAX_DTZ / 2 - 100 {
+                    Wdl::Win
+                } else if rank > 0 {
+                    Wdl::CursedWin
+                } else if rank == 0 {
+                    Wdl::Draw
+                } else if rank > -(MAX_DTZ / 2 - 100) {
+                    Wdl::BlessedLoss
+                } else {
+                    Wdl::Loss
+                };
+
+                root_moves.push(RootMove { m, dtz, rank, wdl });
+            }
+
+            root_moves.sort_by_key(|root_move| std::cmp::Reverse(root_move.rank));
+            Some(root_moves)
+        }
+
+        // The moves that keep the best result the tables can promise
+        pub fn best_moves(&self, game: &mut Game) -> Option<Vec<RootMove>> {
+            let root_moves = self.root_moves(game)?;
+            let best_rank = root_moves.first()?.rank;
+            Some(root_moves.into_iter().filter(|root_move| root_move.rank == best_rank).collect())
+        }
+
+        pub fn best_move(&self, game: &mut Game) -> Option<RootMove> {
+            self.best_moves(game)?.into_iter().next()
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn king_pair_codes() -> usize {
+        let encoding = encoding();
+        encoding.map_kk.iter().flat_map(|row| row.iter()).max().map_or(0, |max| max + 1)
+    }
+}
@@ -0,0 +1,116 @@
+// Turns a clock into time limits for one search.
+//
+// The soft limit is what a move should normally take, a new iteration isn't started after it has passed. The hard
+// limit is the most a move may take, the search checks it while running and stops in the middle of an iteration.
+pub mod time_manager {
+    use std::time::{Duration, Instant};
+
+    // Kept back from every clock budget for the time it takes to send the move
+    pub const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+
+    // Moves the remaining time is spread over when the clock doesn't say
+    const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct TimeControl {
+        pub wtime: Option<Duration>,
+        pub btime: Option<Duration>,
+        pub winc: Duration,
+        pub binc: Duration,
+        pub movestogo: Option<u32>,
+        pub movetime: Option<Duration>, // fixed time for this move, overrides the clock
+    }
+
+    impl TimeControl {
+        // No limit, the search runs until its depth is done
+        pub fn infinite() -> TimeControl {
+            TimeControl::default()
+        }
+
+        pub fn movetime(movetime: Duration) -> TimeControl {
+            TimeControl { movetime: Some(movetime), ..TimeControl::default() }
+        }
+
+        // The same clock and increment for both sides
+        pub fn clock(time: Duration, increment: Duration) -> TimeControl {
+            TimeControl { wtime: Some(time), btime: Some(time), winc: increment, binc: increment, ..TimeControl::default() }
+        }
+
+        // "300+2" is 300 seconds with a 2 second increment, "60" is 60 seconds without one
+        pub fn parse(s: &str) -> Option<TimeControl> {
+            let (time, increment) = s.trim().split_once('+').unwrap_or((s.trim(), "0"));
+            let time = time.parse::<f64>().ok().filter(|t| *t >= 0.0)?;
+            let increment = increment.parse::<f64>().ok().filter(|i| *i >= 0.0)?;
+            Some(TimeControl::clock(Duration::from_secs_f64(time), Duration::from_secs_f64(increment)))
+        }
+
+        pub fn is_infinite(&self) -> bool {
+            self.movetime.is_none() && self.wtime.is_none() && self.btime.is_none()
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct TimeManager {
+        start: Instant,
+        soft: Option<Duration>,
+        hard: Option<Duration>,
+    }
+
+    impl TimeManager {
+        // Starts the clock for a search by the given side
+        pub fn new(time_control: &TimeControl, is_white: bool) -> TimeManager {
+            let (soft, hard) = Self::allocate(time_control, is_white);
+            TimeManager { start: Instant::now(), soft, hard }
+        }
+
+        pub fn infinite() -> TimeManager {
+            TimeManager::new(&TimeControl::infinite(), true)
+        }
+
+        fn allocate(time_control: &TimeControl, is_white: bool) -> (Option<Duration>, Option<Duration>) {
+            if let Some(movetime) = time_control.movetime {
+                let limit = movetime.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1));
+                return (Some(limit), Some(limit))
+            }
+
+            let (time, increment) = if is_white {
+                (time_control.wtime, time_control.winc)
+            } else {
+                (time_control.btime, time_control.binc)
+            };
+            let time = match time {
+                Some(time) => time,
+                None => return (None, None)
+            };
+
+            let available = time.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1));
+            let moves_to_go = time_control.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, DEFAULT_MOVES_TO_GO);
+
+            let soft = (available / moves_to_go + increment * 3 / 4).min(available * 8 / 10);
+            let hard = (soft * 3).min(available * 9 / 10).max(soft);
+            (Some(soft), Some(hard))
+        }
+
+        pub fn soft_limit(&self) -> Option<Duration> {
+            self.soft
+        }
+
+        pub fn hard_limit(&self) -> Option<Duration> {
+            self.hard
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            self.start.elapsed()
+        }
+
+        // Whether there is time for another iteration
+        pub fn can_start_iteration(&self) -> bool {
+            self.soft.is_none_or(|soft| self.elapsed() < soft)
+        }
+
+        // Whether the running search has to stop now
+        pub fn should_stop(&self) -> bool {
+            self.hard.is_some_and(|hard| self.elapsed() >= hard)
+        }
+    }
+}
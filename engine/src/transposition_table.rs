@@ -0,0 +1,246 @@
+// Fixed size hash table of searched positions.
+//
+// The table is a power of two number of buckets with four entries each, the low bits of the position key pick the
+// bucket. An entry keeps the depth, the kind of bound, the score and the best move as Polyglot encoded by
+// `encode_move`, packed into one word.
+//
+// Search threads share the table without locking. An entry is two atomic words, the key xor the data and the data.
+// Two threads writing the same entry at once can leave halves that don't belong together, then the key doesn't
+// come back out of the xor and the entry reads as a miss.
+//
+// Mate scores are stored as the distance from the stored position rather than from the root, so a mate found
+// through one path scores right when the position is reached at another ply.
+pub mod transposition_table {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use crate::board::board::Move;
+    use crate::polyglot::polyglot::encode_move;
+    pub use crate::score::score::MATE_BOUND;
+
+    pub const ENTRIES_PER_BUCKET: usize = 4;
+    const BUCKET_BYTES: usize = ENTRIES_PER_BUCKET * size_of::<AtomicEntry>();
+
+    // Stored scores are clamped to what an i16 holds, mate distances are adjusted by the ply
+    const SCORE_LIMIT: i32 = i16::MAX as i32;
+
+    const AGE_CYCLE: u8 = 64;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Bound {
+        Exact,
+        Lower, // the score is at least this, the search failed high
+        Upper, // the score is at most this, the search failed low
+    }
+
+    #[derive(Debug, Clone, Copy, Default)]
+    struct Entry {
+        key: u64,
+        best_move: u16,
+        score: i16,
+        depth: u8,
+        age_bound: u8, // age in the upper six bits, bound in the lower two, 0 while empty
+    }
+
+    impl Entry {
+        fn is_empty(&self) -> bool {
+            self.age_bound == 0
+        }
+
+        fn age(&self) -> u8 {
+            self.age_bound >> 2
+        }
+
+        fn bound(&self) -> Bound {
+            match self.age_bound & 3 {
+                1 => Bound::Exact,
+                2 => Bound::Lower,
+                _ => Bound::Upper
+            }
+        }
+
+        fn data(&self) -> u64 {
+            self.best_move as u64 | (self.score as u16 as u64) << 16 | (self.depth as u64) << 32 | (self.age_bound as u64) << 40
+        }
+
+        fn from_data(key: u64, data: u64) -> Entry {
+            Entry { key, best_move: data as u16, score: (data >> 16) as u16 as i16, depth: (data >> 32) as u8, age_bound: (data >> 40) as u8 }
+        }
+    }
+
+    #[derive(Default)]
+    struct AtomicEntry {
+        key_xor_data: AtomicU64,
+        data: AtomicU64,
+    }
+
+    impl AtomicEntry {
+        fn load(&self) -> Entry {
+            let data = self.data.load(Ordering::Relaxed);
+            Entry::from_data(self.key_xor_data.load(Ordering::Relaxed) ^ data, data)
+        }
+
+        fn save(&self, entry: &Entry) {
+            let data = entry.data();
+            self.key_xor_data.store(entry.key ^ data, Ordering::Relaxed);
+            self.data.store(data, Ordering::Relaxed);
+        }
+
+        fn reset(&self) {
+            self.save(&Entry::default());
+        }
+    }
+
+    // What the table knows about a position, the score already adjusted to the probing ply
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TtHit {
+        pub depth: usize,
+        pub bound: Bound,
+        pub score: i32,
+        pub best_move: Option<u16>,
+    }
+
+    impl TtHit {
+        // Whether the stored result settles the search of this node at the given depth and window
+        pub fn cutoff(&self, depth: usize, alpha: i32, beta: i32) -> bool {
+            self.depth >= depth && match self.bound {
+                Bound::Exact => true,
+                Bound::Lower => self.score >= beta,
+                Bound::Upper => self.score <= alpha,
+            }
+        }
+
+        // Whether m is the stored best move
+        pub fn is_best_move(&self, m: &Move) -> bool {
+            self.best_move.is_some_and(|best| best == encode_move(m))
+        }
+    }
+
+    pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+        let score = score.clamp(-SCORE_LIMIT, SCORE_LIMIT);
+        if score >= MATE_BOUND {
+            (score + ply as i32).min(SCORE_LIMIT)
+        } else if score <= -MATE_BOUND {
+            (score - ply as i32).max(-SCORE_LIMIT)
+        } else {
+            score
+        }
+    }
+
+    pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+        if score >= MATE_BOUND {
+            score - ply as i32
+        } else if score <= -MATE_BOUND {
+            score + ply as i32
+        } else {
+            score
+        }
+    }
+
+    pub struct TranspositionTable {
+        entries: Vec<AtomicEntry>,
+        mask: usize,
+        age: u8,
+    }
+
+    impl TranspositionTable {
+        // A table of at most size_mb megabytes, at least one bucket
+        pub fn new(size_mb: usize) -> TranspositionTable {
+            let buckets = (size_mb * 1024 * 1024 / BUCKET_BYTES).max(1);
+            let buckets = if buckets.is_power_of_two() { buckets } else { buckets.next_power_of_two() / 2 };
+            let entries = (0..buckets * ENTRIES_PER_BUCKET).map(|_| AtomicEntry::default()).collect();
+            TranspositionTable { entries, mask: buckets - 1, age: 0 }
+        }
+
+        pub fn resize(&mut self, size_mb: usize) {
+            *self = TranspositionTable::new(size_mb);
+        }
+
+        pub fn clear(&mut self) {
+            self.entries.iter().for_each(AtomicEntry::reset);
+            self.age = 0;
+        }
+
+        pub fn size_bytes(&self) -> usize {
+            self.entries.len() * size_of::<AtomicEntry>()
+        }
+
+        pub fn buckets(&self) -> usize {
+            self.mask + 1
+        }
+
+        // Called before every search, entries from earlier searches are replaced first
+        pub fn new_search(&mut self) {
+            self.age = (self.age + 1) % AGE_CYCLE;
+        }
+
+        fn bucket(&self, key: u64) -> &[AtomicEntry] {
+            let start = (key as usize & self.mask) * ENTRIES_PER_BUCKET;
+            &self.entries[start..start + ENTRIES_PER_BUCKET]
+        }
+
+        pub fn probe(&self, key: u64, ply: usize) -> Option<TtHit> {
+            self.bucket(key).iter()
+                .map(AtomicEntry::load)
+                .find(|entry| !entry.is_empty() && entry.key == key)
+                .map(|entry| TtHit {
+                    depth: entry.depth as usize,
+                    bound: entry.bound(),
+                    score: score_from_tt(entry.score as i32, ply),
+                    best_move: if entry.best_move == 0 { None } else { Some(entry.best_move) },
+                })
+        }
+
+        // Generations since the entry was written
+        fn relative_age(&self, entry: &Entry) -> i32 {
+            ((AGE_CYCLE + self.age - entry.age()) % AGE_CYCLE) as i32
+        }
+
+        pub fn store(&self, key: u64, depth: usize, bound: Bound, score: i32, best_move: Option<&Move>, ply: usize) {
+            let bucket = self.bucket(key);
+            let entries: [Entry; ENTRIES_PER_BUCKET] = std::array::from_fn(|i| bucket[i].load());
+
+            // The same position is overwritten, otherwise the shallowest and oldest entry makes room
+            let slot = match entries.iter().position(|entry| !entry.is_empty() && entry.key == key) {
+                Some(i) => {
+                    let old = entries[i];
+                    // A much deeper result of the current search is worth more than a new shallow bound
+                    if bound != Bound::Exact && self.relative_age(&old) == 0 && old.depth as usize > depth + 2 {
+                        return
+                    }
+                    i
+                }
+                None => (0..ENTRIES_PER_BUCKET)
+                    .min_by_key(|i| {
+                        let entry = &entries[*i];
+                        if entry.is_empty() { i32::MIN } else { entry.depth as i32 - 8 * self.relative_age(entry) }
+                    })
+                    .unwrap()
+            };
+
+            let old_move = if entries[slot].key == key { entries[slot].best_move } else { 0 };
+            let bound_bits = match bound {
+                Bound::Exact => 1,
+                Bound::Lower => 2,
+                Bound::Upper => 3,
+            };
+
+            bucket[slot].save(&Entry {
+                key,
+                // A fail low has no best move, the one from an earlier search is kept
+                best_move: best_move.map(encode_move).unwrap_or(old_move),
+                score: score_to_tt(score, ply) as i16,
+                depth: depth.min(u8::MAX as usize) as u8,
+                age_bound: self.age << 2 | bound_bits,
+            });
+        }
+
+        // Permille of the sampled entries written in the current search
+        pub fn hashfull(&self) -> usize {
+            let sample = self.entries.len().min(1000);
+            let used = self.entries[..sample].iter()
+                .map(AtomicEntry::load)
+                .filter(|entry| !entry.is_empty() && entry.age() == self.age)
+                .count();
+            used * 1000 / sample
+        }
+    }
+}